@@ -0,0 +1,278 @@
+// src/body.rs
+//
+// 【処理概要】
+// HTTPレスポンスのボディを表す型を実装。
+// メモリ上のバイト列だけでなく、ファイルや逐次生成されるデータも扱う。
+//
+// 【主な機能】
+// - バイト列ボディ（長さ既知）
+// - リーダーボディ（ファイル等。長さが分かればContent-Length送信）
+// - チャンクボディ（イテレータ/プロデューサが順に生成。長さ不明）
+// - ボディのソケットへの書き出し（通常送信 / chunked送信）
+// - ボディの複製（ストリーミングボディは複製間で共有し、送信は最初の1回のみ）
+//
+// 【実装内容】
+// 1. Body列挙型と生成用の便利関数
+// 2. 長さの取得（Content-Lengthを付けられるかの判定に使用）
+// 3. write_to: 生データのまま、またはchunked形式で書き出し
+// 4. リーダー・イテレータはSharedStreamに入れ、複製しても1つの実体を指すようにする
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+
+/// チャンクを順に生成するイテレータの型
+pub type ChunkStream = Box<dyn Iterator<Item = io::Result<Vec<u8>>> + Send>;
+
+/// 一度だけ読み取れるストリームの入れ物
+///
+/// 複製しても同じストリームを共有し、最初に取り出したものだけが中身を読める。
+/// レスポンスを複製した場合、ストリーミングボディを送信できるのはどちらか一方のみ。
+pub struct SharedStream<T>(Arc<Mutex<Option<T>>>);
+
+impl<T> SharedStream<T> {
+    /// ストリームを入れる
+    pub fn new(stream: T) -> Self {
+        SharedStream(Arc::new(Mutex::new(Some(stream))))
+    }
+
+    /// ストリームを取り出す（既に取り出されていればNone）
+    pub fn take(&self) -> Option<T> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+impl<T> Clone for SharedStream<T> {
+    fn clone(&self) -> Self {
+        SharedStream(Arc::clone(&self.0))
+    }
+}
+
+/// レスポンスボディ
+#[derive(Clone)]
+pub enum Body {
+    /// メモリ上のバイト列
+    Bytes(Vec<u8>),
+    /// 任意のリーダー（ファイル等）。lengthがSomeならその長さだけ送信する
+    Reader {
+        reader: SharedStream<Box<dyn Read + Send>>,
+        length: Option<u64>,
+    },
+    /// チャンクを順に生成するイテレータ（全体の長さは不明）
+    Chunks(SharedStream<ChunkStream>),
+}
+
+impl Body {
+    /// 空のボディ
+    pub fn empty() -> Self {
+        Body::Bytes(Vec::new())
+    }
+
+    /// ファイルをボディにする（長さはメタデータから取得）
+    pub fn from_file(file: File) -> io::Result<Self> {
+        let length = file.metadata()?.len();
+        Ok(Body::Reader {
+            reader: SharedStream::new(Box::new(file)),
+            length: Some(length),
+        })
+    }
+
+    /// 任意のリーダーをボディにする（長さ不明として扱う）
+    pub fn from_reader<R>(reader: R) -> Self
+    where
+        R: Read + Send + 'static,
+    {
+        Body::Reader {
+            reader: SharedStream::new(Box::new(reader)),
+            length: None,
+        }
+    }
+
+    /// チャンクのイテレータをボディにする
+    ///
+    /// 例: Body::from_chunks((1..=3).map(|i| format!("line {}\n", i)))
+    pub fn from_chunks<I, T>(chunks: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+        T: Into<Vec<u8>>,
+    {
+        Body::Chunks(SharedStream::new(Box::new(
+            chunks.into_iter().map(|chunk| Ok(chunk.into())),
+        )))
+    }
+
+    /// プロデューサ関数をボディにする
+    ///
+    /// 関数はNoneを返すまで繰り返し呼ばれ、返したデータが順に送信される。
+    /// 途中でErrを返すと送信を中断して接続を閉じる。
+    pub fn from_producer<F>(producer: F) -> Self
+    where
+        F: FnMut() -> Option<io::Result<Vec<u8>>> + Send + 'static,
+    {
+        Body::Chunks(SharedStream::new(Box::new(std::iter::from_fn(producer))))
+    }
+
+    /// ボディの長さ（事前に分からない場合はNone）
+    pub fn length(&self) -> Option<u64> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::Reader { length, .. } => *length,
+            Body::Chunks(_) => None,
+        }
+    }
+
+    /// メモリ上のバイト列であれば参照を返す
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// ボディを書き出す
+    ///
+    /// chunked: trueならchunked形式（サイズ行 + データ + CRLF、最後に0チャンク）
+    /// で書き出す。falseなら生データのまま書き出す。
+    /// 複製元・複製先で既に送信済みのストリーミングボディはエラーになる。
+    pub fn write_to<W: Write>(self, writer: &mut W, chunked: bool) -> io::Result<()> {
+        let write_chunk = |writer: &mut W, data: &[u8]| -> io::Result<()> {
+            if data.is_empty() {
+                // 空チャンクは終端を意味してしまうため送らない
+                return Ok(());
+            }
+            if chunked {
+                write!(writer, "{:x}\r\n", data.len())?;
+                writer.write_all(data)?;
+                writer.write_all(b"\r\n")
+            } else {
+                writer.write_all(data)
+            }
+        };
+
+        match self {
+            Body::Bytes(bytes) => write_chunk(writer, &bytes)?,
+            Body::Reader { reader, length } => {
+                let reader = reader.take().ok_or_else(already_sent)?;
+                let mut reader: Box<dyn Read + Send> = match length {
+                    Some(length) => Box::new(reader.take(length)),
+                    None => reader,
+                };
+                let mut buffer = [0u8; 8192];
+                let mut written = 0u64;
+                loop {
+                    let n = reader.read(&mut buffer)?;
+                    if n == 0 {
+                        break;
+                    }
+                    write_chunk(writer, &buffer[..n])?;
+                    written += n as u64;
+                }
+                // 宣言した長さより短いとクライアントが待ち続けるためエラーにする
+                if length.is_some_and(|length| written < length) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Body ended before its declared length",
+                    ));
+                }
+            }
+            Body::Chunks(chunks) => {
+                for chunk in chunks.take().ok_or_else(already_sent)? {
+                    write_chunk(writer, &chunk?)?;
+                }
+            }
+        }
+
+        if chunked {
+            writer.write_all(b"0\r\n\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// 共有していたストリームが既に送信されていた場合のエラー
+fn already_sent() -> io::Error {
+    io::Error::other("Streaming body was already sent by a clone of this response")
+}
+
+impl Default for Body {
+    fn default() -> Self {
+        Body::empty()
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Bytes(bytes) => write!(f, "Body::Bytes({} bytes)", bytes.len()),
+            Body::Reader { length, .. } => write!(f, "Body::Reader(length: {:?})", length),
+            Body::Chunks(_) => write!(f, "Body::Chunks(..)"),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body::Bytes(bytes)
+    }
+}
+
+impl From<&str> for Body {
+    fn from(text: &str) -> Self {
+        Body::Bytes(text.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Self {
+        Body::Bytes(text.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_chunked() {
+        let body = Body::from_chunks(vec!["Wiki", "", "pedia"]);
+        assert_eq!(body.length(), None);
+
+        let mut out = Vec::new();
+        body.write_to(&mut out, true).unwrap();
+        assert_eq!(out, b"4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n");
+    }
+
+    #[test]
+    fn test_reader_with_length_is_truncated() {
+        let body = Body::Reader {
+            reader: SharedStream::new(Box::new(&b"hello world"[..])),
+            length: Some(5),
+        };
+
+        let mut out = Vec::new();
+        body.write_to(&mut out, false).unwrap();
+        assert_eq!(out, b"hello");
+    }
+
+    #[test]
+    fn test_reader_shorter_than_length_fails() {
+        let body = Body::Reader {
+            reader: SharedStream::new(Box::new(&b"hi"[..])),
+            length: Some(5),
+        };
+        assert!(body.write_to(&mut Vec::new(), false).is_err());
+    }
+
+    #[test]
+    fn test_cloned_stream_is_sent_once() {
+        let body = Body::from_chunks(vec!["a", "b"]);
+        let copy = body.clone();
+
+        let mut out = Vec::new();
+        copy.write_to(&mut out, false).unwrap();
+        assert_eq!(out, b"ab");
+        assert!(body.write_to(&mut Vec::new(), false).is_err());
+    }
+}
@@ -0,0 +1,702 @@
+// src/config.rs
+//
+// 【処理概要】
+// サーバーの設定（動作方式、ワーカー数、キュー容量、タイムアウト、サイズ制限、待ち受けアドレス）を実装。
+// ソースを書き換えずに、設定ファイル・環境変数・コマンドライン引数で調整できるようにする。
+//
+// 【主な機能】
+// - ServerConfig: 設定値（Defaultは開発用の値）
+// - ServerConfigBuilder: メソッドによる設定と、各設定元からの読み込み
+// - 値の検証（不正な値は起動時にエラーとして報告）
+//
+// 【実装内容】
+// 1. 全ての設定元で共通のキー名を使う（例: workers, idle_timeout）
+//    - 設定ファイル: "workers = 8"（1行に1つ。# 以降はコメント）
+//    - 環境変数: HTTP_SERVER_WORKERS=8（未知のキーは警告を出して無視する）
+//    - コマンドライン: --workers 8 / --workers=8（'_' の代わりに '-' も可）
+// 2. 優先順位は 既定値 < 設定ファイル < 環境変数 < コマンドライン
+// 3. 時間は "500ms", "30s", "5m"（単位なしは秒）、サイズは "16KiB", "8M"（単位なしはバイト）
+//
+// 使用例:
+//   let config = ServerConfig::load(std::env::args().skip(1))?;  // --config server.conf など
+//   let server = Server::with_config(config, router);
+
+use crate::http::RequestLimits;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// 環境変数の接頭辞（例: HTTP_SERVER_WORKERS）
+const ENV_PREFIX: &str = "HTTP_SERVER_";
+
+/// 設定ファイルのパスを指定するキー（--config / HTTP_SERVER_CONFIG）
+const CONFIG_KEY: &str = "config";
+
+/// 設定できるキーと説明（usageの表示順）
+const KEYS: &[(&str, &str)] = &[
+    ("bind", "待ち受けるアドレス（カンマ区切りで複数可。例: 0.0.0.0:8080,[::]:8080）"),
+    ("mode", "動作方式（threaded: 接続ごとにワーカー / event-loop: epollで読み書き。Linuxのみ）"),
+    ("workers", "ワーカースレッド数（min_workersとmax_workersを同じ値にする）"),
+    ("min_workers", "常に起動しておくワーカースレッド数"),
+    ("max_workers", "負荷に応じて増やすワーカースレッドの最大数"),
+    ("worker_idle_timeout", "最小数を超えるワーカーが終了するまでのアイドル時間"),
+    ("queue_capacity", "ワーカー待ちの接続を保持するキューの容量"),
+    ("overload_policy", "キューが満杯のときの動作（block: 受付を待たせる / reject: 503を返す）"),
+    ("retry_after", "rejectで返す503のRetry-After"),
+    ("read_timeout", "リクエストの受信中に、次のデータを待つ最大時間（1回の読み込みごと）"),
+    ("write_timeout", "レスポンスの1回の書き込みの最大時間"),
+    ("idle_timeout", "キープアライブで次のリクエストを待つ最大時間"),
+    ("max_requests_per_connection", "1接続で処理する最大リクエスト数"),
+    ("drain_timeout", "シャットダウン時に処理中のリクエストを待つ最大時間"),
+    ("max_request_line", "リクエスト行の最大サイズ"),
+    ("max_header_bytes", "ヘッダー部全体の最大サイズ"),
+    ("max_header_count", "ヘッダーの最大個数"),
+    ("max_body_size", "ボディの最大サイズ"),
+    ("max_chunk_size", "chunked転送の1チャンクの最大サイズ"),
+];
+
+/// サーバーの設定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// 待ち受けるアドレス（"host:port"）
+    pub bind: Vec<String>,
+    /// 動作方式
+    pub mode: ServerMode,
+    /// 常に起動しておくワーカースレッド数
+    pub min_workers: usize,
+    /// 負荷に応じて増やすワーカースレッドの最大数（既定はmin_workersと同じ4で、増減しない）
+    pub max_workers: usize,
+    /// 最小数を超えるワーカーが、この時間ジョブがなければ終了する
+    pub worker_idle_timeout: Duration,
+    /// ワーカー待ちの接続を保持するキューの容量
+    pub queue_capacity: usize,
+    /// キューが満杯のときの動作
+    pub overload_policy: OverloadPolicy,
+    /// OverloadPolicy::Rejectで返す503のRetry-After（秒単位に切り上げ）
+    pub retry_after: Duration,
+    /// リクエストの受信中に、次のデータを待つ最大時間
+    pub read_timeout: Duration,
+    /// レスポンスの書き込みの最大時間
+    pub write_timeout: Duration,
+    /// キープアライブで次のリクエストを待つ最大時間
+    pub idle_timeout: Duration,
+    /// 1接続で処理する最大リクエスト数
+    pub max_requests_per_connection: usize,
+    /// シャットダウン時に処理中のリクエストを待つ最大時間
+    pub drain_timeout: Duration,
+    /// リクエストのサイズ制限
+    pub limits: RequestLimits,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: vec!["127.0.0.1:8080".to_string()],
+            mode: ServerMode::Threaded,
+            min_workers: 4,
+            max_workers: 4,
+            worker_idle_timeout: Duration::from_secs(30),
+            queue_capacity: 1024,
+            overload_policy: OverloadPolicy::Block,
+            retry_after: Duration::from_secs(1),
+            read_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30),
+            idle_timeout: Duration::from_secs(5),
+            max_requests_per_connection: 100,
+            drain_timeout: Duration::from_secs(10),
+            limits: RequestLimits::default(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// 既定値から始めるビルダー
+    pub fn builder() -> ServerConfigBuilder {
+        ServerConfigBuilder {
+            config: ServerConfig::default(),
+        }
+    }
+
+    /// 設定ファイル・環境変数・コマンドライン引数から読み込む
+    ///
+    /// 設定ファイルは --config <path>（なければ環境変数 HTTP_SERVER_CONFIG）で指定する。
+    /// 後の設定元が前の値を上書きし、最後に検証する。
+    pub fn load(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut args: Vec<String> = args.into_iter().collect();
+        let path = take_config_arg(&mut args)?.or_else(|| env::var("HTTP_SERVER_CONFIG").ok());
+
+        let mut builder = ServerConfig::builder();
+        if let Some(path) = path {
+            builder = builder.file(path)?;
+        }
+        builder.env()?.args(args)?.build()
+    }
+
+    /// 値を検証する（不正な組み合わせや範囲外の値はエラー）
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+
+        if self.bind.is_empty() {
+            return invalid("bind must have at least one address".to_string());
+        }
+        for address in &self.bind {
+            let valid = match address.rsplit_once(':') {
+                Some((host, port)) => !host.is_empty() && port.parse::<u16>().is_ok(),
+                None => false,
+            };
+            if !valid {
+                return invalid(format!("bind address {:?} must be host:port", address));
+            }
+        }
+        self.validate_settings()
+    }
+
+    /// 待ち受けアドレス以外の値を検証する（バインド済みのリスナーで起動する場合に使う）
+    pub(crate) fn validate_settings(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+
+        if cfg!(not(target_os = "linux")) && self.mode == ServerMode::EventLoop {
+            return invalid("mode event-loop is only supported on Linux".to_string());
+        }
+        if self.max_workers < self.min_workers {
+            return invalid(format!(
+                "max_workers ({}) must not be less than min_workers ({})",
+                self.max_workers, self.min_workers
+            ));
+        }
+
+        let counts = [
+            ("min_workers", self.min_workers),
+            ("queue_capacity", self.queue_capacity),
+            ("max_requests_per_connection", self.max_requests_per_connection),
+            ("max_request_line", self.limits.max_request_line),
+            ("max_header_bytes", self.limits.max_header_bytes),
+            ("max_header_count", self.limits.max_header_count),
+            ("max_chunk_size", self.limits.max_chunk_size),
+        ];
+        for (key, value) in counts {
+            if value == 0 {
+                return invalid(format!("{} must be at least 1", key));
+            }
+        }
+
+        let timeouts = [
+            ("read_timeout", self.read_timeout),
+            ("write_timeout", self.write_timeout),
+            ("idle_timeout", self.idle_timeout),
+            ("worker_idle_timeout", self.worker_idle_timeout),
+        ];
+        for (key, value) in timeouts {
+            if value.is_zero() {
+                return invalid(format!("{} must be greater than 0", key));
+            }
+        }
+        Ok(())
+    }
+
+    /// コマンドライン引数の説明
+    pub fn usage() -> String {
+        let mut usage = String::from("Options (file: key = value, env: HTTP_SERVER_<KEY>):\n");
+        usage.push_str(&format!("  --{:<30} {}\n", "config <path>", "設定ファイル"));
+        for (key, description) in KEYS {
+            usage.push_str(&format!("  --{:<30} {}\n", key.replace('_', "-"), description));
+        }
+        usage
+    }
+}
+
+/// サーバーの動作方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ServerMode {
+    /// 接続ごとにワーカーが読み書きする（処理中の接続数 = 使用中のワーカー数）
+    #[default]
+    Threaded,
+    /// 1スレッドのepollループがノンブロッキングで読み書きし、
+    /// パースが完了したリクエストだけをワーカーに渡す（アイドル・低速な接続がワーカーを占有しない）
+    EventLoop,
+}
+
+/// キューが満杯のとき（全ワーカーが処理中で、待ちの接続も容量に達したとき）の動作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverloadPolicy {
+    /// キューが空くまで受付を止める（接続はOSのバックログで待つ）
+    #[default]
+    Block,
+    /// 受付スレッドで 503 Service Unavailable と Retry-After を返して切断する
+    Reject,
+}
+
+/// 設定のビルダー
+///
+/// 例:
+///   let config = ServerConfig::builder()
+///       .bind(&["0.0.0.0:8080"])
+///       .workers(16)
+///       .idle_timeout(Duration::from_secs(15))
+///       .build()?;
+#[derive(Debug, Clone)]
+pub struct ServerConfigBuilder {
+    config: ServerConfig,
+}
+
+impl ServerConfigBuilder {
+    /// 待ち受けるアドレス（既定のアドレスは置き換える）
+    pub fn bind(mut self, addresses: &[&str]) -> Self {
+        self.config.bind = addresses.iter().map(|a| a.to_string()).collect();
+        self
+    }
+
+    /// 動作方式
+    pub fn mode(mut self, mode: ServerMode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    /// ワーカースレッド数（増減しない固定数にする）
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.config.min_workers = workers;
+        self.config.max_workers = workers;
+        self
+    }
+
+    /// 常に起動しておくワーカースレッド数
+    pub fn min_workers(mut self, workers: usize) -> Self {
+        self.config.min_workers = workers;
+        self
+    }
+
+    /// 負荷に応じて増やすワーカースレッドの最大数
+    pub fn max_workers(mut self, workers: usize) -> Self {
+        self.config.max_workers = workers;
+        self
+    }
+
+    /// 最小数を超えるワーカーが終了するまでのアイドル時間
+    pub fn worker_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.worker_idle_timeout = timeout;
+        self
+    }
+
+    /// ワーカー待ちの接続を保持するキューの容量
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.config.queue_capacity = capacity;
+        self
+    }
+
+    /// キューが満杯のときの動作
+    pub fn overload_policy(mut self, policy: OverloadPolicy) -> Self {
+        self.config.overload_policy = policy;
+        self
+    }
+
+    /// OverloadPolicy::Rejectで返す503のRetry-After
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.config.retry_after = retry_after;
+        self
+    }
+
+    /// リクエストの受信中に、次のデータを待つ最大時間
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = timeout;
+        self
+    }
+
+    /// レスポンスの書き込みの最大時間
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.config.write_timeout = timeout;
+        self
+    }
+
+    /// キープアライブで次のリクエストを待つ最大時間
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.idle_timeout = timeout;
+        self
+    }
+
+    /// 1接続で処理する最大リクエスト数
+    pub fn max_requests_per_connection(mut self, max: usize) -> Self {
+        self.config.max_requests_per_connection = max;
+        self
+    }
+
+    /// シャットダウン時に処理中のリクエストを待つ最大時間
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.config.drain_timeout = timeout;
+        self
+    }
+
+    /// リクエストのサイズ制限
+    pub fn limits(mut self, limits: RequestLimits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    /// 設定ファイルを読み込む（"key = value" の行。空行と # 以降は無視）
+    pub fn file(mut self, path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|error| ConfigError::Io {
+            path: path.display().to_string(),
+            error,
+        })?;
+
+        for (index, line) in text.lines().enumerate() {
+            let origin = format!("{}:{}", path.display(), index + 1);
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ConfigError::Syntax {
+                    origin,
+                    line: line.to_string(),
+                });
+            };
+            self.set(&origin, key.trim(), value.trim())?;
+        }
+        Ok(self)
+    }
+
+    /// 環境変数（HTTP_SERVER_<KEY>）を読み込む
+    ///
+    /// 未知のキーは、同じ接頭辞の無関係な環境変数かもしれないため、警告を出して無視する。
+    pub fn env(self) -> Result<Self, ConfigError> {
+        self.env_from(env::vars())
+    }
+
+    fn env_from(mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<Self, ConfigError> {
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let key = key.to_ascii_lowercase();
+            if key == CONFIG_KEY {
+                continue;
+            }
+            match self.set(&name, &key, &value) {
+                Err(ConfigError::UnknownKey { origin, .. }) => {
+                    eprintln!("⚠️  Ignoring unknown environment variable {}", origin);
+                }
+                result => result?,
+            }
+        }
+        Ok(self)
+    }
+
+    /// コマンドライン引数（--key value / --key=value）を読み込む
+    ///
+    /// --config <path> はその位置で設定ファイルを読み込む。
+    pub fn args(mut self, args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (key, value) = split_arg(&arg, &mut args)?;
+            if key == CONFIG_KEY {
+                self = self.file(value)?;
+            } else {
+                self.set(&arg, &key, &value)?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// 設定を検証して完成させる
+    pub fn build(self) -> Result<ServerConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+
+    /// キーに対応する値を文字列から設定する（originはエラーメッセージ用）
+    fn set(&mut self, origin: &str, key: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = |expected: &'static str| ConfigError::InvalidValue {
+            origin: origin.to_string(),
+            value: value.to_string(),
+            expected,
+        };
+        let count = || value.parse::<usize>().map_err(|_| invalid("a non-negative integer"));
+        let size = || parse_size(value).ok_or_else(|| invalid("a size such as 1024, 16KiB or 8M"));
+        let duration =
+            || parse_duration(value).ok_or_else(|| invalid("a duration such as 500ms, 30s or 5m"));
+
+        let config = &mut self.config;
+        match key.replace('-', "_").as_str() {
+            "bind" => {
+                config.bind = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|address| !address.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "mode" => {
+                config.mode = match value.to_ascii_lowercase().replace('_', "-").as_str() {
+                    "threaded" => ServerMode::Threaded,
+                    "event-loop" => ServerMode::EventLoop,
+                    _ => return Err(invalid("threaded or event-loop")),
+                }
+            }
+            "workers" => {
+                config.min_workers = count()?;
+                config.max_workers = config.min_workers;
+            }
+            "min_workers" => config.min_workers = count()?,
+            "max_workers" => config.max_workers = count()?,
+            "worker_idle_timeout" => config.worker_idle_timeout = duration()?,
+            "queue_capacity" => config.queue_capacity = count()?,
+            "overload_policy" => {
+                config.overload_policy = match value.to_ascii_lowercase().as_str() {
+                    "block" => OverloadPolicy::Block,
+                    "reject" => OverloadPolicy::Reject,
+                    _ => return Err(invalid("block or reject")),
+                }
+            }
+            "retry_after" => config.retry_after = duration()?,
+            "read_timeout" => config.read_timeout = duration()?,
+            "write_timeout" => config.write_timeout = duration()?,
+            "idle_timeout" => config.idle_timeout = duration()?,
+            "max_requests_per_connection" => config.max_requests_per_connection = count()?,
+            "drain_timeout" => config.drain_timeout = duration()?,
+            "max_request_line" => config.limits.max_request_line = size()?,
+            "max_header_bytes" => config.limits.max_header_bytes = size()?,
+            "max_header_count" => config.limits.max_header_count = count()?,
+            "max_body_size" => config.limits.max_body_size = size()?,
+            "max_chunk_size" => config.limits.max_chunk_size = size()?,
+            _ => {
+                return Err(ConfigError::UnknownKey {
+                    origin: origin.to_string(),
+                    key: key.to_string(),
+                })
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 引数から --config <path> を取り除いて返す（設定ファイルを最初に読み込むため）
+fn take_config_arg(args: &mut Vec<String>) -> Result<Option<String>, ConfigError> {
+    let Some(index) = args
+        .iter()
+        .position(|arg| arg == "--config" || arg.starts_with("--config="))
+    else {
+        return Ok(None);
+    };
+
+    let arg = args.remove(index);
+    if let Some(path) = arg.strip_prefix("--config=") {
+        return Ok(Some(path.to_string()));
+    }
+    if index < args.len() {
+        Ok(Some(args.remove(index)))
+    } else {
+        Err(ConfigError::MissingValue { origin: arg })
+    }
+}
+
+/// "--key value" / "--key=value" を (キー, 値) に分ける
+fn split_arg(arg: &str, rest: &mut impl Iterator<Item = String>) -> Result<(String, String), ConfigError> {
+    let Some(option) = arg.strip_prefix("--") else {
+        return Err(ConfigError::UnknownKey {
+            origin: "command line".to_string(),
+            key: arg.to_string(),
+        });
+    };
+
+    let (key, value) = match option.split_once('=') {
+        Some((key, value)) => (key, value.to_string()),
+        None => {
+            let value = rest.next().ok_or_else(|| ConfigError::MissingValue {
+                origin: arg.to_string(),
+            })?;
+            (option, value)
+        }
+    };
+    Ok((key.replace('-', "_"), value))
+}
+
+/// 時間をパースする（"500ms", "30s", "5m", "1h"。単位なしは秒）
+fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    let duration = match unit.trim() {
+        "ms" => Duration::from_millis(number),
+        "" | "s" => Duration::from_secs(number),
+        "m" => Duration::from_secs(number.checked_mul(60)?),
+        "h" => Duration::from_secs(number.checked_mul(3600)?),
+        _ => return None,
+    };
+    Some(duration)
+}
+
+/// サイズをパースする（"1024", "16K", "16KiB", "8M", "1G"。単位は1024倍ずつ）
+fn parse_size(value: &str) -> Option<usize> {
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: usize = number.parse().ok()?;
+    let multiplier: usize = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return None,
+    };
+    number.checked_mul(multiplier)
+}
+
+/// 設定の読み込み・検証エラー
+#[derive(Debug)]
+pub enum ConfigError {
+    /// 設定ファイルを読み込めない
+    Io { path: String, error: io::Error },
+    /// 設定ファイルの行が "key = value" の形でない
+    Syntax { origin: String, line: String },
+    /// 未知のキー（綴りの誤りなど）
+    UnknownKey { origin: String, key: String },
+    /// オプションに値がない（例: 末尾の --workers）
+    MissingValue { origin: String },
+    /// 値の形式が不正
+    InvalidValue {
+        origin: String,
+        value: String,
+        expected: &'static str,
+    },
+    /// 値の範囲や組み合わせが不正
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "Cannot read config file {}: {}", path, error),
+            ConfigError::Syntax { origin, line } => {
+                write!(f, "{}: expected \"key = value\", got {:?}", origin, line)
+            }
+            ConfigError::UnknownKey { origin, key } => write!(f, "{}: unknown option {:?}", origin, key),
+            ConfigError::MissingValue { origin } => write!(f, "{}: missing value", origin),
+            ConfigError::InvalidValue {
+                origin,
+                value,
+                expected,
+            } => write!(f, "{}: invalid value {:?} (expected {})", origin, value, expected),
+            ConfigError::Invalid(message) => write!(f, "Invalid configuration: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_sources_and_precedence() {
+        let path = env::temp_dir().join(format!("http_server_config_{}.conf", std::process::id()));
+        fs::write(
+            &path,
+            "# サンプル\nworkers = 8\nidle_timeout = 15s  # コメント\nbind = 0.0.0.0:80, [::]:80\n",
+        )
+        .unwrap();
+
+        let vars = [
+            ("HTTP_SERVER_WORKERS".to_string(), "12".to_string()),
+            ("HTTP_SERVER_MAX_BODY_SIZE".to_string(), "2MiB".to_string()),
+            ("HTTP_SERVER_VERSION".to_string(), "1.2.0".to_string()),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+        ];
+        let config = ServerConfig::builder()
+            .file(&path)
+            .unwrap()
+            .env_from(vars)
+            .unwrap()
+            .args(args(&["--workers", "16", "--read-timeout=500ms", "--overload-policy", "reject"]))
+            .unwrap()
+            .build()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((config.min_workers, config.max_workers), (16, 16));
+        assert_eq!(config.idle_timeout, Duration::from_secs(15));
+        assert_eq!(config.read_timeout, Duration::from_millis(500));
+        assert_eq!(config.limits.max_body_size, 2 * 1024 * 1024);
+        assert_eq!(config.bind, ["0.0.0.0:80", "[::]:80"]);
+        assert_eq!(config.overload_policy, OverloadPolicy::Reject);
+    }
+
+    #[test]
+    fn test_unknown_env_var_is_ignored() {
+        // 同じ接頭辞の無関係な環境変数では起動を止めない（値の誤りはエラーのまま）
+        let vars = [
+            ("HTTP_SERVER_WOKERS".to_string(), "2".to_string()),
+            ("HTTP_SERVER_IDLE_TIMEOUT".to_string(), "5s".to_string()),
+        ];
+        let config = ServerConfig::builder().env_from(vars).unwrap().build().unwrap();
+        assert_eq!(config.min_workers, ServerConfig::default().min_workers);
+        assert_eq!(config.idle_timeout, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_errors_name_their_origin() {
+        let err = ServerConfig::builder().args(args(&["--wokers", "2"])).unwrap_err();
+        assert_eq!(err.to_string(), r#"--wokers: unknown option "wokers""#);
+
+        let vars = [("HTTP_SERVER_IDLE_TIMEOUT".to_string(), "soon".to_string())];
+        let err = ServerConfig::builder().env_from(vars).unwrap_err();
+        assert!(err.to_string().starts_with(r#"HTTP_SERVER_IDLE_TIMEOUT: invalid value "soon""#));
+
+        let err = ServerConfig::builder().args(args(&["--workers"])).unwrap_err();
+        assert_eq!(err.to_string(), "--workers: missing value");
+
+        let builder = ServerConfig::builder().args(args(&["--mode", "event_loop"])).unwrap();
+        assert_eq!(builder.config.mode, ServerMode::EventLoop);
+        let err = ServerConfig::builder().args(args(&["--mode=async"])).unwrap_err();
+        assert!(err.to_string().contains("expected threaded or event-loop"));
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(ServerConfig::default().validate().is_ok());
+
+        let err = ServerConfig::builder().workers(0).build().unwrap_err();
+        assert_eq!(err.to_string(), "Invalid configuration: min_workers must be at least 1");
+        let err = ServerConfig::builder().min_workers(8).max_workers(2).build().unwrap_err();
+        assert!(err.to_string().contains("max_workers (2) must not be less than min_workers (8)"));
+        let err = ServerConfig::builder().bind(&["localhost"]).build().unwrap_err();
+        assert!(err.to_string().contains("must be host:port"));
+        let err = ServerConfig::builder()
+            .idle_timeout(Duration::ZERO)
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("idle_timeout"));
+    }
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("7"), Some(Duration::from_secs(7)));
+        assert_eq!(parse_duration("1.5s"), None);
+        assert_eq!(parse_size("16KiB"), Some(16 * 1024));
+        assert_eq!(parse_size("8m"), Some(8 * 1024 * 1024));
+        assert_eq!(parse_size("10 parsecs"), None);
+    }
+
+    #[test]
+    fn test_load_takes_config_path_from_args() {
+        let mut list = args(&["--workers", "2", "--config", "a.conf", "--idle-timeout", "3s"]);
+        assert_eq!(take_config_arg(&mut list).unwrap(), Some("a.conf".to_string()));
+        assert_eq!(list, args(&["--workers", "2", "--idle-timeout", "3s"]));
+    }
+}
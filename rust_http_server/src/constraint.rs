@@ -0,0 +1,291 @@
+// src/constraint.rs
+//
+// 【処理概要】
+// パスパラメータの制約（例: /users/:id<int>）を実装。
+// 制約に合わない値はそのルートにマッチせず、次の候補のルートが試される。
+//
+// 【主な機能】
+// - 組み込みの制約: int, uuid, alpha, alnum, hex
+// - 正規表現風の文字クラス（例: "[a-z0-9-]+", "[0-9]{4}-[0-9]{2}"）
+//
+// 【実装内容】
+// 1. 制約の文字列をアトム（文字クラス + 繰り返し回数）の列にコンパイル
+// 2. マッチングは先頭から貪欲に取り、失敗したら短くして再試行（バックトラック）
+//
+// 対応する構文（正規表現のごく一部）:
+//   [a-z0-9_]  文字クラス（範囲指定可。先頭の ^ で否定）
+//   \d \w      数字 / 英数字と '_'
+//   \x         記号のエスケープ（例: \. \[ \\）
+//   x          その他の文字はそのまま一致
+//   + * ?      1回以上 / 0回以上 / 0回か1回
+//   {n} {n,} {n,m}  回数指定
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// コンパイル済みの制約
+#[derive(Debug, Clone)]
+pub(crate) struct Constraint {
+    /// 元の表記（同じ制約かどうかの比較に使う）
+    source: String,
+    atoms: Vec<Atom>,
+}
+
+/// 文字クラスと繰り返し回数
+#[derive(Debug, Clone)]
+struct Atom {
+    class: CharClass,
+    min: usize,
+    max: Option<usize>,
+}
+
+/// 1文字にマッチする文字クラス
+#[derive(Debug, Clone)]
+struct CharClass {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl CharClass {
+    fn single(c: char) -> Self {
+        CharClass {
+            ranges: vec![(c, c)],
+            negated: false,
+        }
+    }
+
+    fn matches(&self, c: char) -> bool {
+        let found = self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+        found != self.negated
+    }
+}
+
+impl Constraint {
+    /// 制約の文字列をコンパイルする（組み込みの名前か文字クラスの式）
+    ///
+    /// 構文が不正な場合はNone。
+    pub(crate) fn parse(source: &str) -> Option<Self> {
+        let expression = match source {
+            "int" => "[0-9]+",
+            "alpha" => "[A-Za-z]+",
+            "alnum" => "[A-Za-z0-9]+",
+            "hex" => "[0-9A-Fa-f]+",
+            "uuid" => {
+                "[0-9A-Fa-f]{8}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{12}"
+            }
+            expression => expression,
+        };
+
+        Some(Constraint {
+            source: source.to_string(),
+            atoms: compile(expression)?,
+        })
+    }
+
+    /// 元の表記
+    pub(crate) fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// 値全体が制約に一致するか
+    pub(crate) fn matches(&self, value: &str) -> bool {
+        match_atoms(&self.atoms, value)
+    }
+}
+
+/// 式をアトムの列にコンパイルする
+fn compile(expression: &str) -> Option<Vec<Atom>> {
+    let mut chars = expression.chars().peekable();
+    let mut atoms = Vec::new();
+
+    while let Some(c) = chars.next() {
+        let class = match c {
+            '[' => parse_class(&mut chars)?,
+            '\\' => escape_class(chars.next()?),
+            '+' | '*' | '?' | '{' | ']' | '}' => return None,
+            c => CharClass::single(c),
+        };
+
+        // 繰り返し回数（指定がなければ1回）
+        let (min, max) = match chars.peek() {
+            Some('+') => {
+                chars.next();
+                (1, None)
+            }
+            Some('*') => {
+                chars.next();
+                (0, None)
+            }
+            Some('?') => {
+                chars.next();
+                (0, Some(1))
+            }
+            Some('{') => {
+                chars.next();
+                parse_repeat(&mut chars)?
+            }
+            _ => (1, Some(1)),
+        };
+        atoms.push(Atom { class, min, max });
+    }
+
+    if atoms.is_empty() {
+        return None;
+    }
+    Some(atoms)
+}
+
+/// "{n}" "{n,}" "{n,m}" の中身をパースする（'{' は読み取り済み）
+fn parse_repeat(chars: &mut Peekable<Chars<'_>>) -> Option<(usize, Option<usize>)> {
+    let mut spec = String::new();
+    loop {
+        match chars.next()? {
+            '}' => break,
+            c => spec.push(c),
+        }
+    }
+
+    let (min, max) = match spec.split_once(',') {
+        Some((min, "")) => (min.parse().ok()?, None),
+        Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+        None => {
+            let n = spec.parse().ok()?;
+            (n, Some(n))
+        }
+    };
+    if max.is_some_and(|max| max < min) {
+        return None;
+    }
+    Some((min, max))
+}
+
+/// "[...]" の中身をパースする（'[' は読み取り済み）
+fn parse_class(chars: &mut Peekable<Chars<'_>>) -> Option<CharClass> {
+    let mut class = CharClass {
+        ranges: Vec::new(),
+        negated: false,
+    };
+    if chars.peek() == Some(&'^') {
+        chars.next();
+        class.negated = true;
+    }
+
+    loop {
+        let c = match chars.next()? {
+            ']' if !class.ranges.is_empty() => return Some(class),
+            '\\' => {
+                let escaped = escape_class(chars.next()?);
+                class.ranges.extend(escaped.ranges);
+                continue;
+            }
+            c => c,
+        };
+
+        // 範囲指定（末尾の '-' は文字として扱う）
+        let mut lookahead = chars.clone();
+        if lookahead.next() == Some('-') && lookahead.peek().is_some_and(|&end| end != ']') {
+            chars.next();
+            let end = chars.next()?;
+            if end < c {
+                return None;
+            }
+            class.ranges.push((c, end));
+        } else {
+            class.ranges.push((c, c));
+        }
+    }
+}
+
+/// エスケープシーケンスの文字クラス
+fn escape_class(c: char) -> CharClass {
+    match c {
+        'd' => CharClass {
+            ranges: vec![('0', '9')],
+            negated: false,
+        },
+        'w' => CharClass {
+            ranges: vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+            negated: false,
+        },
+        c => CharClass::single(c),
+    }
+}
+
+/// アトムの列が文字列全体に一致するか（貪欲にマッチし、失敗したら戻る）
+fn match_atoms(atoms: &[Atom], text: &str) -> bool {
+    let Some((atom, rest)) = atoms.split_first() else {
+        return text.is_empty();
+    };
+
+    // 取れるだけ取る
+    let mut taken = 0;
+    let mut end = 0;
+    for (i, c) in text.char_indices() {
+        if atom.max == Some(taken) || !atom.class.matches(c) {
+            break;
+        }
+        taken += 1;
+        end = i + c.len_utf8();
+    }
+    if taken < atom.min {
+        return false;
+    }
+    // 最後のアトムは残りを全て取れた場合のみ一致（戻っても短くなるだけ）
+    if rest.is_empty() {
+        return end == text.len();
+    }
+
+    // 1文字ずつ戻しながら残りのアトムを試す
+    let mut matched = &text[..end];
+    loop {
+        if match_atoms(rest, &text[matched.len()..]) {
+            return true;
+        }
+        if taken == atom.min {
+            return false;
+        }
+        let mut chars = matched.chars();
+        chars.next_back();
+        matched = chars.as_str();
+        taken -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(constraint: &str, value: &str) -> bool {
+        Constraint::parse(constraint).unwrap().matches(value)
+    }
+
+    #[test]
+    fn test_builtin_constraints() {
+        assert!(matches("int", "12345"));
+        assert!(!matches("int", "12a"));
+        assert!(matches("uuid", "550e8400-e29b-41d4-a716-446655440000"));
+        assert!(!matches("uuid", "550e8400-e29b-41d4-a716"));
+        assert!(matches("alpha", "abcXYZ"));
+    }
+
+    #[test]
+    fn test_class_expressions() {
+        assert!(matches("[a-z0-9-]+", "hello-world-2"));
+        assert!(!matches("[a-z0-9-]+", "Hello"));
+        assert!(matches("[0-9]{4}-[0-9]{2}", "2024-05"));
+        assert!(!matches("[0-9]{4}-[0-9]{2}", "24-05"));
+        assert!(matches("v\\d+\\.?", "v2."));
+        assert!(matches("[^/.]+\\.json", "report.json"));
+        // バックトラック: a* が全て取ると後ろの a が一致しない
+        assert!(matches("a*a", "aaa"));
+        assert!(matches("[a-zé]+-\\d", "café-1"));
+        assert!(!matches("[0-9]+", "12é"));
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        for source in ["", "[a-z", "+", "[z-a]", "a{3,1}", "a{x}", "a{2"] {
+            assert!(Constraint::parse(source).is_none(), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+// src/error.rs
+//
+// 【処理概要】
+// ハンドラが返すエラーと、エラーからレスポンスへの変換を実装。
+// エラーの形式を RFC 7807（application/problem+json）に揃える。
+//
+// 【主な機能】
+// - BoxError: ハンドラが ? で返せる任意のエラー型
+// - Problem: RFC 7807 の問題詳細（type, title, status, detail, instance と拡張メンバー）
+// - デフォルトのエラー変換（Router::on_errorで置き換え可能）
+//
+// 【実装内容】
+// 1. Problem・ParamErrorは内容をそのままレスポンスにする（4xxなど）
+// 2. それ以外のエラーは内部情報を漏らさないよう、詳細なしの500にしてログに出力
+//
+// 使用例:
+//   router.get("/users/:id", |req| -> Result<Response, BoxError> {
+//       let id: u32 = req.param("id")?;  // 不正なら400
+//       let user = find_user(id).ok_or_else(|| {
+//           Problem::new(StatusCode::NOT_FOUND).with_detail(&format!("User {} not found", id))
+//       })?;
+//       Ok(Response::ok(&user.to_json()))
+//   });
+
+use crate::http::json_escape;
+use crate::router::{IntoResponse, ParamError, Response};
+use crate::status::StatusCode;
+use std::fmt;
+
+/// ハンドラが返せる任意のエラー
+///
+/// std::error::Errorを実装した型は ? で自動的に変換される。
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// RFC 7807 の問題詳細
+///
+/// ハンドラからエラーとして返すと、このステータスのレスポンスになる。
+///
+/// 例:
+///   {"type": "about:blank", "title": "Not Found", "status": 404,
+///    "detail": "User 9 not found", "instance": "/api/users/9"}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub status: StatusCode,
+    /// 問題の種類を表すURI（既定は "about:blank"）
+    pub problem_type: String,
+    /// 問題の種類の要約（既定はステータステキスト）
+    pub title: String,
+    /// この発生に固有の説明
+    pub detail: Option<String>,
+    /// この発生を識別するURI（例: リクエストのパス）
+    pub instance: Option<String>,
+    /// 拡張メンバー（例: "parameter" => "id"）
+    pub extensions: Vec<(String, String)>,
+}
+
+impl Problem {
+    /// ステータスから作成（titleはステータステキスト）
+    pub fn new(status: StatusCode) -> Self {
+        Problem {
+            status,
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            detail: None,
+            instance: None,
+            extensions: Vec::new(),
+        }
+    }
+
+    /// 問題の種類のURIを設定
+    pub fn with_type(mut self, problem_type: &str) -> Self {
+        self.problem_type = problem_type.to_string();
+        self
+    }
+
+    /// 要約を設定
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    /// 説明を設定
+    pub fn with_detail(mut self, detail: &str) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    /// 発生箇所のURIを設定
+    pub fn with_instance(mut self, instance: &str) -> Self {
+        self.instance = Some(instance.to_string());
+        self
+    }
+
+    /// 拡張メンバーを追加（値は文字列として出力される）
+    pub fn with_extension(mut self, name: &str, value: &str) -> Self {
+        self.extensions.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// JSON文字列（メンバーの順序は type, title, status, detail, instance, 拡張）
+    pub fn to_json(&self) -> String {
+        let mut members = vec![
+            format!(r#""type": "{}""#, json_escape(&self.problem_type)),
+            format!(r#""title": "{}""#, json_escape(&self.title)),
+            format!(r#""status": {}"#, self.status.as_u16()),
+        ];
+        if let Some(detail) = &self.detail {
+            members.push(format!(r#""detail": "{}""#, json_escape(detail)));
+        }
+        if let Some(instance) = &self.instance {
+            members.push(format!(r#""instance": "{}""#, json_escape(instance)));
+        }
+        for (name, value) in &self.extensions {
+            members.push(format!(r#""{}": "{}""#, json_escape(name), json_escape(value)));
+        }
+        format!("{{{}}}", members.join(", "))
+    }
+
+    /// application/problem+json のレスポンス
+    pub fn to_response(&self) -> Response {
+        let mut response = Response::new(self.status).with_body(&self.to_json());
+        response
+            .headers
+            .insert("Content-Type", "application/problem+json");
+        response
+    }
+}
+
+/// "404 Not Found: User 9 not found" の形式（説明がなければステータスのみ）
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{}: {}", self.status, detail),
+            None => write!(f, "{}", self.status),
+        }
+    }
+}
+
+impl std::error::Error for Problem {}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        self.to_response()
+    }
+}
+
+/// エラーをレスポンスに変換する（Router::on_errorを設定しない場合の変換）
+///
+/// ProblemとParamErrorはその内容で、それ以外は詳細を含まない500にする
+/// （エラーの内容は標準エラー出力に記録する）。
+/// on_errorで独自の変換を設定した場合も、扱わないエラーはこの関数に任せられる。
+pub fn default_error_response(error: &BoxError) -> Response {
+    if let Some(problem) = error.downcast_ref::<Problem>() {
+        return problem.to_response();
+    }
+    if let Some(error) = error.downcast_ref::<ParamError>() {
+        return error.to_response();
+    }
+
+    eprintln!("❌ Handler error: {}", error);
+    Problem::new(StatusCode::INTERNAL_SERVER_ERROR).to_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_problem_json() {
+        let problem = Problem::new(StatusCode::NOT_FOUND)
+            .with_detail("No \"user\" 9")
+            .with_instance("/users/9")
+            .with_extension("id", "9");
+        assert_eq!(
+            problem.to_json(),
+            r#"{"type": "about:blank", "title": "Not Found", "status": 404, "detail": "No \"user\" 9", "instance": "/users/9", "id": "9"}"#
+        );
+
+        let response = problem.to_response();
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers.get("content-type"),
+            Some("application/problem+json")
+        );
+    }
+
+    #[test]
+    fn test_default_error_response() {
+        let error: BoxError = Problem::new(StatusCode::CONFLICT).into();
+        assert_eq!(default_error_response(&error).status, StatusCode::CONFLICT);
+
+        // 想定外のエラーの内容はレスポンスに含めない
+        let error: BoxError = io::Error::other("db password rejected").into();
+        let response = default_error_response(&error);
+        assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+        let body = response.body.as_bytes().unwrap();
+        assert_eq!(
+            body,
+            br#"{"type": "about:blank", "title": "Internal Server Error", "status": 500}"#
+        );
+    }
+}
@@ -0,0 +1,1022 @@
+// src/event_loop.rs
+//
+// 【処理概要】
+// epollを使ったイベントループ方式のサーバー（ServerMode::EventLoop。Linuxのみ）を実装。
+// 接続の読み書きを1スレッドでノンブロッキングに行い、パースが完了したリクエストだけを
+// ワーカースレッドプールに渡す。アイドル・低速な接続はワーカーを占有しない。
+//
+// 【主な機能】
+// - 接続の受付と、ノンブロッキングの読み取り・書き込み
+// - RequestParser（プッシュ型パーサー）によるリクエストの組み立て（パイプライン対応）
+// - ワーカーへのブロックしない受け渡し（キューが満杯なら待たせておき、空き次第渡す）
+// - アイドル・読み取り・書き込みのタイムアウト
+// - 過負荷時の503と、グレースフルシャットダウン
+//
+// 【実装内容】
+// 1. 接続は 受信中 → 処理中（ワーカー） → 送信中 → 受信中 … の順に状態を移る
+// 2. ワーカーはレスポンスをバイト列にして完了の一覧に入れ、イベントループを起こす
+//    （UnixStreamのペアの片方に書き込み、もう片方をepollで待つ）
+// 3. 処理中の接続は読み取りを止め、後続のリクエストはパーサーのバッファに残す
+// 4. ストリーミングボディはワーカーが断片ずつ生成してチャネルで渡す（全体をメモリに溜めない）
+// 5. 1回の通知で読む量には上限を設け、1つの接続がループを占有しないようにする
+// 6. エラーページ・503は送信後に書き込み側だけを閉じ、受信済みのデータを読み捨ててから切断する
+//    （未読のデータを残して閉じるとRSTが送られ、クライアントが応答を読めないことがある）
+
+use crate::config::{OverloadPolicy, ServerConfig};
+use crate::http::{HttpRequest, HttpResponse, ParseError, ParseStatus, RequestParser};
+use crate::server::{self, ConnectionContext, ConnectionOptions, PreparedResponse};
+use crate::shutdown::CountedConnection;
+use crate::sys::{Epoll, Event, Interest};
+use crate::thread_pool::{Job, ThreadPool};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// タイムアウトと停止の要求を確認する間隔
+const TICK: Duration = Duration::from_millis(100);
+
+/// 1回の読み取りの最大バイト数
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+/// 読み取り可能の1回の通知で読む最大回数（残りは次の通知で読む）
+const MAX_READS_PER_WAKEUP: usize = 4;
+
+/// ストリーミングボディの断片を、ワーカーが先に生成しておける数
+const STREAM_QUEUE: usize = 4;
+
+/// 切断前に受信済みのデータを読み捨てる際、次のデータ（またはEOF）を待つ最大時間
+const LINGER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// ワーカーからの通知を受けるソケットのトークン
+const WAKER_TOKEN: u64 = u64::MAX;
+
+/// 接続の状態
+enum State {
+    /// リクエストの受信中（または次のリクエスト待ち）
+    Reading,
+    /// ワーカーがリクエストを処理中
+    Processing,
+    /// レスポンスの送信中
+    Writing {
+        buffer: Vec<u8>,
+        written: usize,
+        keep_alive: bool,
+        /// ワーカーが生成中のボディの続き（ストリーミングボディの場合）
+        body: Option<mpsc::Receiver<Piece>>,
+        /// 送信後、受信済みのデータを読み捨ててから切断するか（keep_aliveがfalseの場合）
+        linger: bool,
+    },
+    /// 書き込み側を閉じ、切断前に受信済みのデータを読み捨てている（server::REJECT_DRAIN_LIMITまで）
+    Closing { drained: usize },
+}
+
+/// イベントループが管理する接続
+struct Connection {
+    stream: TcpStream,
+    parser: RequestParser,
+    state: State,
+    /// この接続で処理したリクエスト数
+    served: usize,
+    /// 最後に読み書きが進んだ時刻（タイムアウトの判定用）
+    last_activity: Instant,
+    /// ShutdownHandle::active_connectionsに数えるためのもの
+    _counted: CountedConnection,
+}
+
+impl Connection {
+    /// 読み書きが進まないまま待つ最大時間（ワーカーを待つ間は無制限）
+    fn timeout(&self, options: &ConnectionOptions) -> Option<Duration> {
+        match self.state {
+            State::Reading if self.parser.is_partial() => Some(options.read_timeout),
+            State::Reading => Some(options.idle_timeout),
+            _ if self.is_waiting() => None,
+            State::Processing | State::Writing { .. } => Some(options.write_timeout),
+            State::Closing { .. } => Some(LINGER_TIMEOUT),
+        }
+    }
+
+    /// ワーカーを待っているか（リクエストの処理中、またはボディの続きの生成待ち）
+    fn is_waiting(&self) -> bool {
+        match &self.state {
+            State::Reading | State::Closing { .. } => false,
+            State::Processing => true,
+            State::Writing { buffer, written, body, .. } => *written == buffer.len() && body.is_some(),
+        }
+    }
+
+    /// 次のリクエストを待っているか
+    fn is_idle(&self) -> bool {
+        matches!(self.state, State::Reading) && !self.parser.is_partial()
+    }
+}
+
+/// ワーカーが生成するストリーミングボディの断片
+enum Piece {
+    Data(Vec<u8>),
+    /// レスポンスの終わり（受け取る前にチャネルが閉じたら、生成に失敗したため切断）
+    End,
+}
+
+/// ワーカーが処理したレスポンス
+enum Output {
+    /// レスポンス全体のバイト列
+    Bytes(Vec<u8>),
+    /// ワーカーが生成中のレスポンス（断片を順に受け取る）
+    Stream(mpsc::Receiver<Piece>),
+}
+
+/// ワーカーが処理を終えたレスポンス（outputがNoneなら処理に失敗したため切断）
+struct Completion {
+    token: u64,
+    output: Option<Output>,
+    keep_alive: bool,
+}
+
+/// ワーカーからイベントループへの通知
+struct Mailbox {
+    completions: Mutex<Vec<Completion>>,
+    /// ストリーミングボディの続きが届いた接続
+    streams: Mutex<Vec<u64>>,
+    /// ワーカーに渡せていないジョブがあるか（あれば、ジョブを始めたワーカーがイベントループを起こす）
+    backlogged: AtomicBool,
+    waker: UnixStream,
+}
+
+impl Mailbox {
+    fn post(&self, completion: Completion) {
+        self.completions.lock().unwrap().push(completion);
+        self.wake();
+    }
+
+    /// ストリーミングボディの続きが届いたことを知らせる
+    fn notify_stream(&self, token: u64) {
+        self.streams.lock().unwrap().push(token);
+        self.wake();
+    }
+
+    /// ジョブを始めたことを知らせる（キューに空きができたため、待たせているジョブを渡せる）
+    fn job_started(&self) {
+        if self.backlogged.load(Ordering::SeqCst) {
+            self.wake();
+        }
+    }
+
+    fn wake(&self) {
+        // 書き込めない（バッファが満杯）場合は通知済みのため、結果は無視してよい
+        let _ = (&self.waker).write(&[1]);
+    }
+}
+
+/// ワーカーからの応答
+///
+/// 応答せずに破棄された場合（ジョブが実行されなかった、レスポンスの書き出し中にpanicしたなど）は
+/// 処理に失敗したと通知し、接続が処理中のまま残らないようにする。
+struct Reply {
+    token: u64,
+    mailbox: Arc<Mailbox>,
+    posted: bool,
+}
+
+impl Reply {
+    /// 処理したレスポンスをイベントループに渡す
+    ///
+    /// ボディがメモリ上にあれば全体をバイト列にして渡す。ストリーミングボディは先にチャネルを渡し、
+    /// 生成した断片を順に送る（送信が追いつかなければ、ワーカーがSTREAM_QUEUE個先で待つ）。
+    fn send(mut self, prepared: PreparedResponse) {
+        let PreparedResponse {
+            response,
+            head_only,
+            keep_alive,
+        } = prepared;
+        if head_only || response.body.as_bytes().is_some() {
+            let mut bytes = Vec::new();
+            match server::write_response(&mut bytes, response, head_only) {
+                Ok(()) => self.post(Some(Output::Bytes(bytes)), keep_alive),
+                Err(e) => eprintln!("❌ Error handling connection: {}", e),
+            }
+            return;
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(STREAM_QUEUE);
+        self.post(Some(Output::Stream(receiver)), keep_alive);
+        let mut writer = PieceWriter {
+            token: self.token,
+            mailbox: &self.mailbox,
+            sender: Some(sender),
+        };
+        let sent = server::write_response(&mut writer, response, false).and_then(|_| writer.send(Piece::End));
+        if let Err(e) = sent {
+            eprintln!("❌ Error handling connection: {}", e);
+        }
+    }
+
+    fn post(&mut self, output: Option<Output>, keep_alive: bool) {
+        self.posted = true;
+        self.mailbox.post(Completion {
+            token: self.token,
+            output,
+            keep_alive,
+        });
+    }
+}
+
+impl Drop for Reply {
+    fn drop(&mut self) {
+        if !self.posted {
+            self.post(None, false);
+        }
+    }
+}
+
+/// ストリーミングボディの断片をイベントループに送るライター（書き込みごとに1断片）
+///
+/// 破棄する際はチャネルを閉じてからイベントループを起こす
+/// （Endを送る前に破棄された場合も、イベントループが生成の失敗に気づけるようにする）。
+struct PieceWriter<'a> {
+    token: u64,
+    mailbox: &'a Mailbox,
+    sender: Option<mpsc::SyncSender<Piece>>,
+}
+
+impl PieceWriter<'_> {
+    /// 断片を送り、イベントループを起こす（接続が閉じていればエラー）
+    fn send(&self, piece: Piece) -> io::Result<()> {
+        let closed = || io::Error::new(io::ErrorKind::BrokenPipe, "connection closed");
+        let sender = self.sender.as_ref().ok_or_else(closed)?;
+        sender.send(piece).map_err(|_| closed())?;
+        self.mailbox.notify_stream(self.token);
+        Ok(())
+    }
+}
+
+impl Drop for PieceWriter<'_> {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        self.mailbox.notify_stream(self.token);
+    }
+}
+
+impl Write for PieceWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !buf.is_empty() {
+            self.send(Piece::Data(buf.to_vec()))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 書き込みの進み具合
+enum Progress {
+    /// 全て送信した
+    Done { keep_alive: bool, linger: bool },
+    /// ソケットのバッファが満杯（書き込み可能を待つ）
+    Blocked,
+    /// ボディの続きをワーカーが生成中（届けばワーカーが起こす）
+    Waiting,
+    /// 送信できない（切断する）
+    Failed,
+}
+
+/// イベントループを実行する
+///
+/// 停止が要求され、接続が全て閉じる（または期限を過ぎる）と、停止の期限を返す
+/// （呼び出し元はその期限までワーカーの終了を待つ）。
+pub(crate) fn run(
+    listeners: Vec<TcpListener>,
+    context: &Arc<ConnectionContext>,
+    pool: &ThreadPool,
+    config: &ServerConfig,
+    handle_signals: bool,
+) -> io::Result<Instant> {
+    let (waker, wake_receiver) = UnixStream::pair()?;
+    waker.set_nonblocking(true)?;
+    wake_receiver.set_nonblocking(true)?;
+
+    let epoll = Epoll::new()?;
+    for (index, listener) in listeners.iter().enumerate() {
+        epoll.add(listener, index as u64, Interest::Read)?;
+    }
+    epoll.add(&wake_receiver, WAKER_TOKEN, Interest::Read)?;
+    println!("🔁 Event loop started");
+
+    let mut event_loop = EventLoop {
+        epoll,
+        next_token: listeners.len() as u64,
+        listeners,
+        connections: HashMap::new(),
+        mailbox: Arc::new(Mailbox {
+            completions: Mutex::default(),
+            streams: Mutex::default(),
+            backlogged: AtomicBool::new(false),
+            waker,
+        }),
+        wake_receiver,
+        backlog: VecDeque::new(),
+        context,
+        pool,
+        config,
+    };
+    event_loop.run(handle_signals)
+}
+
+struct EventLoop<'a> {
+    epoll: Epoll,
+    listeners: Vec<TcpListener>,
+    connections: HashMap<u64, Connection>,
+    /// 次の接続に割り当てるトークン（リスナーのトークンはその添字）
+    next_token: u64,
+    mailbox: Arc<Mailbox>,
+    wake_receiver: UnixStream,
+    /// キューが満杯でワーカーに渡せていないジョブ（空きができ次第、順に渡す）
+    backlog: VecDeque<Job>,
+    context: &'a Arc<ConnectionContext>,
+    pool: &'a ThreadPool,
+    config: &'a ServerConfig,
+}
+
+impl EventLoop<'_> {
+    /// 処理フロー:
+    /// 1. 停止が要求されたら受付を止め、アイドル接続を閉じる（期限までに残りが閉じるのを待つ）
+    /// 2. イベントを待ち、受付・読み取り・書き込みを進める
+    /// 3. ワーカーが処理を終えたレスポンスの送信を始め、待たせているジョブをワーカーに渡す
+    /// 4. タイムアウトした接続を閉じる
+    fn run(&mut self, handle_signals: bool) -> io::Result<Instant> {
+        let mut events = Vec::new();
+        let mut drain_deadline = None;
+        let mut last_sweep = Instant::now();
+
+        loop {
+            if drain_deadline.is_none() && server::shutdown_requested(&self.context.shutdown, handle_signals) {
+                self.listeners.clear();
+                drain_deadline = Some(Instant::now() + self.config.drain_timeout);
+            }
+            if let Some(deadline) = drain_deadline {
+                let idle: Vec<u64> = self
+                    .connections
+                    .iter()
+                    .filter(|(_, conn)| conn.is_idle())
+                    .map(|(token, _)| *token)
+                    .collect();
+                for token in idle {
+                    self.close(token);
+                }
+                if self.connections.is_empty() {
+                    return Ok(deadline);
+                }
+                if Instant::now() >= deadline {
+                    eprintln!("⚠️  Drain timeout: closing {} connection(s)", self.connections.len());
+                    self.connections.clear();
+                    return Ok(deadline);
+                }
+            }
+
+            self.epoll.wait(&mut events, TICK)?;
+            for event in &events {
+                match event.token {
+                    WAKER_TOKEN => self.drain_waker(),
+                    token if (token as usize) < self.listeners.len() => self.accept(token as usize),
+                    _ => self.ready(event),
+                }
+            }
+            self.complete_jobs();
+            self.flush_backlog();
+
+            if last_sweep.elapsed() >= TICK {
+                self.close_expired();
+                last_sweep = Instant::now();
+            }
+        }
+    }
+
+    /// 通知用ソケットに溜まったバイトを読み捨てる
+    fn drain_waker(&mut self) {
+        let mut buf = [0; 64];
+        while let Ok(1..) = self.wake_receiver.read(&mut buf) {}
+    }
+
+    /// 接続待ちがなくなるまで受け付ける
+    fn accept(&mut self, index: usize) {
+        loop {
+            match self.listeners[index].accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = self.register(stream) {
+                        eprintln!("❌ Connection failed: {}", e);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    eprintln!("❌ Connection failed: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// 接続をノンブロッキングにして登録する（最初は受信待ち）
+    fn register(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(true)?;
+        let token = self.next_token;
+        self.epoll.add(&stream, token, Interest::Read)?;
+        self.next_token += 1;
+
+        let connection = Connection {
+            stream,
+            parser: RequestParser::with_limits(self.context.options.limits),
+            state: State::Reading,
+            served: 0,
+            last_activity: Instant::now(),
+            _counted: self.context.shutdown.count(),
+        };
+        self.connections.insert(token, connection);
+        Ok(())
+    }
+
+    /// 接続のイベントを状態に応じて処理する
+    fn ready(&mut self, event: &Event) {
+        let Some(conn) = self.connections.get(&event.token) else {
+            return;
+        };
+        match conn.state {
+            // 処理中・ボディの生成待ちに切断された（レスポンスは捨てる）
+            _ if event.closed && conn.is_waiting() => self.close(event.token),
+            State::Reading if event.readable || event.closed => self.read(event.token),
+            State::Writing { .. } if event.writable || event.closed => self.write(event.token),
+            State::Closing { .. } if event.readable || event.closed => self.discard(event.token),
+            _ => {}
+        }
+    }
+
+    /// 読み取り、リクエストが完成したらワーカーに渡す
+    ///
+    /// 1回の通知ではMAX_READS_PER_WAKEUP回まで読む（残りはレベルトリガーのため次の通知で読む）。
+    /// 相手が切断した場合は、リクエストの途中でも正常終了として扱う（スレッド方式と同じ）。
+    fn read(&mut self, token: u64) {
+        let mut buf = [0; READ_BUFFER_SIZE];
+        for _ in 0..MAX_READS_PER_WAKEUP {
+            let Some(conn) = self.connections.get_mut(&token) else {
+                return;
+            };
+            let status = match conn.stream.read(&mut buf) {
+                Ok(0) => return self.close(token),
+                Ok(n) => {
+                    conn.last_activity = Instant::now();
+                    conn.parser.feed(&buf[..n])
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    eprintln!("❌ Error handling connection: {}", e);
+                    return self.close(token);
+                }
+            };
+            if !self.handle_parsed(token, status) {
+                return;
+            }
+        }
+    }
+
+    /// パースの結果を処理する（続けて読み取る場合はtrue）
+    fn handle_parsed(&mut self, token: u64, status: Result<ParseStatus, ParseError>) -> bool {
+        match status {
+            Ok(ParseStatus::NeedMore) => true,
+            Ok(ParseStatus::Complete(request)) => {
+                self.dispatch(token, request);
+                false
+            }
+            Err(e) => {
+                // エラーページを返してから切断（以降のバイト列は信用できない）
+                eprintln!("⚠️  Request rejected: {}", e);
+                let response = server::error_response(self.context, &e);
+                self.send_now(token, response);
+                false
+            }
+        }
+    }
+
+    /// リクエストを処理するジョブを作る（処理中は読み取りを止める）
+    ///
+    /// ジョブはbacklogに入れ、flush_backlogでキューの空きの分だけワーカーに渡す
+    /// （キューが満杯でもイベントループはブロックしない）。
+    fn dispatch(&mut self, token: u64, request: HttpRequest) {
+        let Some(conn) = self.connections.get_mut(&token) else {
+            return;
+        };
+        conn.served += 1;
+        conn.state = State::Processing;
+        let served = conn.served;
+        if self.epoll.modify(&conn.stream, token, Interest::None).is_err() {
+            return self.close(token);
+        }
+
+        // 全ワーカーが処理中でキューも満杯なら、待たせずに断る（まだ渡していないジョブも数える）
+        let metrics = &self.context.metrics;
+        if self.config.overload_policy == OverloadPolicy::Reject
+            && metrics.queue_depth() + self.backlog.len() >= self.config.queue_capacity
+        {
+            metrics.record_rejection();
+            return self.send_now(token, server::overload_response(self.config.retry_after));
+        }
+
+        let context = Arc::clone(self.context);
+        let reply = Reply {
+            token,
+            mailbox: Arc::clone(&self.mailbox),
+            posted: false,
+        };
+        self.backlog.push_back(Box::new(move || {
+            reply.mailbox.job_started();
+            let prepared = server::prepare_response(&context, request, served);
+            reply.send(prepared);
+        }));
+    }
+
+    /// 待たせているジョブを、キューに空きがある分だけ順にワーカーに渡す
+    ///
+    /// 渡しきれなければ、ワーカーがジョブを始める（キューに空きができる）たびに起こしてもらう。
+    /// 先にフラグを立ててから渡すため、空きができたことを見逃さない。
+    fn flush_backlog(&mut self) {
+        if self.backlog.is_empty() {
+            return;
+        }
+        self.mailbox.backlogged.store(true, Ordering::SeqCst);
+        while let Some(job) = self.backlog.pop_front() {
+            if let Err(job) = self.pool.try_execute(job) {
+                self.backlog.push_front(job);
+                break;
+            }
+        }
+        self.mailbox.backlogged.store(!self.backlog.is_empty(), Ordering::SeqCst);
+    }
+
+    /// ワーカーが処理を終えたレスポンスの送信を始め、ボディの続きが届いた接続の送信を再開する
+    fn complete_jobs(&mut self) {
+        let completions = std::mem::take(&mut *self.mailbox.completions.lock().unwrap());
+        for completion in completions {
+            match completion.output {
+                Some(output) => self.start_write(completion.token, output, completion.keep_alive, false),
+                None => self.close(completion.token),
+            }
+        }
+
+        let mut streams = std::mem::take(&mut *self.mailbox.streams.lock().unwrap());
+        streams.sort_unstable();
+        streams.dedup();
+        for token in streams {
+            self.write(token);
+        }
+    }
+
+    /// イベントループで作ったレスポンス（エラー・503）を送り、送信後に切断する
+    fn send_now(&mut self, token: u64, response: HttpResponse) {
+        let mut bytes = Vec::new();
+        match server::write_response(&mut bytes, response, false) {
+            Ok(()) => self.start_write(token, Output::Bytes(bytes), false, true),
+            Err(_) => self.close(token),
+        }
+    }
+
+    /// 送信を始める（linger: 送信後、受信済みのデータを読み捨ててから切断する）
+    fn start_write(&mut self, token: u64, output: Output, keep_alive: bool, linger: bool) {
+        let Some(conn) = self.connections.get_mut(&token) else {
+            // 処理中に閉じた接続
+            return;
+        };
+        let (buffer, body) = match output {
+            Output::Bytes(bytes) => (bytes, None),
+            Output::Stream(receiver) => (Vec::new(), Some(receiver)),
+        };
+        conn.state = State::Writing {
+            buffer,
+            written: 0,
+            keep_alive,
+            body,
+            linger,
+        };
+        conn.last_activity = Instant::now();
+        self.write(token);
+    }
+
+    /// 書き込めるだけ書き、送信し終えたら次のリクエストに進む（または切断する）
+    ///
+    /// ストリーミングボディは、届いている断片を順に書き、なくなればワーカーの生成を待つ。
+    fn write(&mut self, token: u64) {
+        let Some(conn) = self.connections.get_mut(&token) else {
+            return;
+        };
+        let Connection {
+            stream,
+            state,
+            last_activity,
+            ..
+        } = conn;
+        let State::Writing {
+            buffer,
+            written,
+            keep_alive,
+            body,
+            linger,
+        } = state
+        else {
+            return;
+        };
+
+        let progress = loop {
+            if *written == buffer.len() {
+                let Some(receiver) = body else {
+                    break Progress::Done {
+                        keep_alive: *keep_alive,
+                        linger: *linger,
+                    };
+                };
+                match receiver.try_recv() {
+                    Ok(Piece::Data(data)) => {
+                        *buffer = data;
+                        *written = 0;
+                        *last_activity = Instant::now();
+                        continue;
+                    }
+                    Ok(Piece::End) => {
+                        break Progress::Done {
+                            keep_alive: *keep_alive,
+                            linger: *linger,
+                        }
+                    }
+                    Err(mpsc::TryRecvError::Empty) => break Progress::Waiting,
+                    Err(mpsc::TryRecvError::Disconnected) => break Progress::Failed,
+                }
+            }
+            match stream.write(&buffer[*written..]) {
+                Ok(0) => break Progress::Failed,
+                Ok(n) => {
+                    *written += n;
+                    *last_activity = Instant::now();
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Progress::Blocked,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => break Progress::Failed,
+            }
+        };
+
+        match progress {
+            Progress::Blocked => {
+                if self.epoll.modify(&conn.stream, token, Interest::Write).is_err() {
+                    self.close(token);
+                }
+            }
+            Progress::Waiting => {
+                if self.epoll.modify(&conn.stream, token, Interest::None).is_err() {
+                    self.close(token);
+                }
+            }
+            Progress::Done { keep_alive: true, .. } => self.next_request(token),
+            Progress::Done { linger: true, .. } => self.linger(token),
+            Progress::Done { .. } | Progress::Failed => self.close(token),
+        }
+    }
+
+    /// 書き込み側を閉じ、切断前に受信済みのデータを読み捨て始める
+    fn linger(&mut self, token: u64) {
+        let Some(conn) = self.connections.get_mut(&token) else {
+            return;
+        };
+        conn.state = State::Closing { drained: 0 };
+        conn.last_activity = Instant::now();
+        if conn.stream.shutdown(Shutdown::Write).is_err()
+            || self.epoll.modify(&conn.stream, token, Interest::Read).is_err()
+        {
+            return self.close(token);
+        }
+        self.discard(token);
+    }
+
+    /// 受信済みのデータを読み捨てる（EOF・読み捨ての上限・エラーで切断する）
+    fn discard(&mut self, token: u64) {
+        let Some(conn) = self.connections.get_mut(&token) else {
+            return;
+        };
+        let Connection {
+            stream,
+            state,
+            last_activity,
+            ..
+        } = conn;
+        let State::Closing { drained } = state else {
+            return;
+        };
+
+        let mut buf = [0; READ_BUFFER_SIZE];
+        while *drained < server::REJECT_DRAIN_LIMIT {
+            match stream.read(&mut buf) {
+                Ok(n @ 1..) => {
+                    *drained += n;
+                    *last_activity = Instant::now();
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                _ => break,
+            }
+        }
+        self.close(token);
+    }
+
+    /// 次のリクエストの受信に戻る（パイプラインで受信済みのリクエストがあれば続けて処理）
+    fn next_request(&mut self, token: u64) {
+        let Some(conn) = self.connections.get_mut(&token) else {
+            return;
+        };
+        conn.state = State::Reading;
+        // 停止が要求されていれば切断
+        if self.context.shutdown.is_shutdown() || self.epoll.modify(&conn.stream, token, Interest::Read).is_err() {
+            return self.close(token);
+        }
+
+        if conn.parser.buffered_len() > 0 {
+            let status = conn.parser.feed(&[]);
+            self.handle_parsed(token, status);
+        }
+    }
+
+    /// タイムアウトした接続を閉じる
+    fn close_expired(&mut self) {
+        let options = self.context.options;
+        let now = Instant::now();
+        let expired: Vec<u64> = self
+            .connections
+            .iter()
+            .filter(|(_, conn)| {
+                conn.timeout(&options)
+                    .is_some_and(|timeout| now.duration_since(conn.last_activity) >= timeout)
+            })
+            .map(|(token, _)| *token)
+            .collect();
+        for token in expired {
+            self.close(token);
+        }
+    }
+
+    /// 接続を閉じる（登録を外し、追跡も終える）
+    fn close(&mut self, token: u64) {
+        if let Some(conn) = self.connections.remove(&token) {
+            let _ = self.epoll.delete(&conn.stream);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::body::Body;
+    use crate::config::{ServerConfig, ServerMode};
+    use crate::http::{HttpResponse, RequestLimits};
+    use crate::router::{Response, Router};
+    use crate::server::Server;
+    use crate::shutdown::ShutdownHandle;
+    use crate::status::StatusCode;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::sync::{mpsc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    /// イベントループ方式のサーバーを空きポートで起動する（GET /ping のみ）
+    fn start(config: ServerConfig) -> (SocketAddr, ShutdownHandle, thread::JoinHandle<()>) {
+        start_with(config, Router::new())
+    }
+
+    /// ルーターを指定して起動する（GET /ping を追加する）
+    fn start_with(config: ServerConfig, mut router: Router) -> (SocketAddr, ShutdownHandle, thread::JoinHandle<()>) {
+        router.get("/ping", |_req| Response::ok("pong"));
+        let server = Server::with_config(config, router);
+        let shutdown = server.shutdown_handle();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let thread = thread::spawn(move || server.serve(listener).unwrap());
+        (addr, shutdown, thread)
+    }
+
+    fn config() -> ServerConfig {
+        ServerConfig::builder()
+            .mode(ServerMode::EventLoop)
+            .workers(1)
+            .build()
+            .unwrap()
+    }
+
+    /// レスポンスを1件読む（Content-Lengthまで）
+    fn read_response(stream: &mut TcpStream) -> String {
+        let mut received = Vec::new();
+        let mut buf = [0; 1024];
+        loop {
+            let n = stream.read(&mut buf).unwrap();
+            assert!(n > 0, "connection closed: {:?}", String::from_utf8_lossy(&received));
+            received.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&received).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .map_or(0, |value| value.parse().unwrap());
+                if body.len() >= length {
+                    return text;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_idle_and_slow_clients_do_not_occupy_workers() {
+        let (addr, shutdown, thread) = start(config());
+
+        // ワーカー1つに対して、アイドル接続と送信途中の接続を多数開く
+        let idle: Vec<TcpStream> = (0..50).map(|_| TcpStream::connect(addr).unwrap()).collect();
+        let mut slow = TcpStream::connect(addr).unwrap();
+        slow.write_all(b"GET /ping HTTP/1.1\r\nHo").unwrap();
+
+        // それでも他の接続のリクエストは処理される（パイプラインも順に応答する）
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .write_all(b"GET /ping HTTP/1.1\r\n\r\nGET /missing HTTP/1.1\r\n\r\n")
+            .unwrap();
+        assert!(read_response(&mut client).starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(read_response(&mut client).starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        // 送信途中だったリクエストも、残りが届けば処理される
+        slow.write_all(b"st: x\r\n\r\n").unwrap();
+        let response = read_response(&mut slow);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("pong"));
+
+        // 停止するとアイドル接続は閉じられる
+        shutdown.shutdown();
+        thread.join().unwrap();
+        assert_eq!(shutdown.active_connections(), 0);
+        drop(idle);
+    }
+
+    #[test]
+    fn test_timeouts_and_malformed_requests_close_connections() {
+        let config = ServerConfig::builder()
+            .mode(ServerMode::EventLoop)
+            .workers(1)
+            .idle_timeout(Duration::from_millis(150))
+            .build()
+            .unwrap();
+        let (addr, shutdown, thread) = start(config);
+
+        // アイドルタイムアウトで閉じられる
+        let mut idle = TcpStream::connect(addr).unwrap();
+        let mut buf = [0; 16];
+        assert_eq!(idle.read(&mut buf).unwrap(), 0);
+
+        // 不正なリクエストにはエラーを返して切断する
+        let mut bad = TcpStream::connect(addr).unwrap();
+        bad.write_all(b"GET / HTTP/3.0\r\n\r\n").unwrap();
+        let mut received = String::new();
+        bad.read_to_string(&mut received).unwrap();
+        assert!(received.starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+        assert!(received.contains("Connection: close"));
+
+        shutdown.shutdown();
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_full_queue_does_not_block_event_loop() {
+        let (entered, handler_entered) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let (entered, released) = (Mutex::new(entered), Mutex::new(released));
+        let mut router = Router::new();
+        router.get("/block", move |_req| {
+            let _ = entered.lock().unwrap().send(());
+            released.lock().unwrap().recv().unwrap();
+            Response::ok("done")
+        });
+        let config = ServerConfig::builder()
+            .mode(ServerMode::EventLoop)
+            .workers(1)
+            .queue_capacity(1)
+            .idle_timeout(Duration::from_millis(150))
+            .build()
+            .unwrap();
+        let (addr, shutdown, thread) = start_with(config, router);
+
+        // ワーカーが1件を処理中、キューに1件、残りはキューに入らず待たされる
+        let mut clients: Vec<TcpStream> = (0..4)
+            .map(|_| {
+                let mut client = TcpStream::connect(addr).unwrap();
+                client.write_all(b"GET /block HTTP/1.1\r\n\r\n").unwrap();
+                client
+            })
+            .collect();
+        handler_entered.recv_timeout(Duration::from_secs(1)).unwrap();
+
+        // それでもイベントループは止まらない（アイドル接続はタイムアウトで閉じられる）
+        let mut idle = TcpStream::connect(addr).unwrap();
+        idle.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        assert_eq!(idle.read(&mut [0; 16]).unwrap(), 0);
+
+        // 解放すると、待たされていたリクエストも全て処理される
+        for _ in 0..clients.len() {
+            release.send(()).unwrap();
+        }
+        for client in &mut clients {
+            client.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+            assert!(read_response(client).ends_with("done"));
+        }
+
+        shutdown.shutdown();
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_streaming_body_is_sent_in_pieces() {
+        let mut router = Router::new();
+        router.get("/count", |_req| {
+            let mut count = 0;
+            HttpResponse::new(StatusCode::OK).with_stream(Body::from_producer(move || {
+                count += 1;
+                (count <= 3).then(|| Ok(format!("piece {}\n", count).into_bytes()))
+            }))
+        });
+        router.get("/broken", |_req| {
+            let mut sent = false;
+            HttpResponse::new(StatusCode::OK).with_stream(Body::from_producer(move || {
+                assert!(!sent, "producer failed");
+                sent = true;
+                Some(Ok(b"partial".to_vec()))
+            }))
+        });
+        let (addr, shutdown, thread) = start_with(config(), router);
+
+        // ワーカーが生成した断片がchunked形式で届き、送信後も接続を使える
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        client.write_all(b"GET /count HTTP/1.1\r\n\r\n").unwrap();
+        let mut received = Vec::new();
+        let mut buf = [0; 1024];
+        while !received.ends_with(b"0\r\n\r\n") {
+            let n = client.read(&mut buf).unwrap();
+            assert!(n > 0, "connection closed: {:?}", String::from_utf8_lossy(&received));
+            received.extend_from_slice(&buf[..n]);
+        }
+        let text = String::from_utf8_lossy(&received);
+        assert!(text.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(text.contains("piece 1\n") && text.contains("piece 3\n"));
+        client.write_all(b"GET /ping HTTP/1.1\r\n\r\n").unwrap();
+        assert!(read_response(&mut client).ends_with("pong"));
+
+        // 生成の途中でpanicしたら、終端を送らずに切断する（処理中のまま残らない）
+        let mut broken = TcpStream::connect(addr).unwrap();
+        broken.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        broken.write_all(b"GET /broken HTTP/1.1\r\n\r\n").unwrap();
+        let mut received = Vec::new();
+        broken.read_to_end(&mut received).unwrap();
+        assert!(!received.ends_with(b"0\r\n\r\n"));
+
+        shutdown.shutdown();
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn test_error_response_is_delivered_before_unread_body() {
+        let config = ServerConfig::builder()
+            .mode(ServerMode::EventLoop)
+            .workers(1)
+            .limits(RequestLimits {
+                max_body_size: 1024,
+                ..RequestLimits::default()
+            })
+            .build()
+            .unwrap();
+        let (addr, shutdown, thread) = start(config);
+
+        // 上限を超えるボディを送っても、読まれなかった入力でレスポンスが失われない（RSTにならない）
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let mut request = b"POST /ping HTTP/1.1\r\nContent-Length: 32768\r\n\r\n".to_vec();
+        request.extend_from_slice(&[b'x'; 32768]);
+        client.write_all(&request).unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).unwrap();
+        let text = String::from_utf8_lossy(&received);
+        assert!(text.starts_with("HTTP/1.1 413 "), "{}", text);
+        let (head, body) = text.split_once("\r\n\r\n").unwrap();
+        let length: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(body.len(), length);
+
+        shutdown.shutdown();
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+// src/extensions.rs
+//
+// 【処理概要】
+// 型をキーにして値を保持するマップ（型付きの拡張領域）を実装。
+// アプリケーションの共有状態や、ミドルウェアからハンドラへ渡すリクエストごとのデータに使う。
+//
+// 【主な機能】
+// - 型ごとに1つの値を登録・取得・削除
+// - 文字列のキーを使わないため、取り出す側で型変換やキーの打ち間違いが起きない
+//
+// 【実装内容】
+// 1. TypeIdをキー、Arc<dyn Any>と型名を値とするHashMap（型名はエラーメッセージ用）
+// 2. 取得時にdowncast_refで元の型に戻す
+// 3. 値はArcで保持するため、マップの複製は参照カウントの増加のみ
+//
+// 使用例:
+//   struct CurrentUser(String);
+//   req.extensions.insert(CurrentUser("alice".to_string()));   // ミドルウェア
+//   let user = req.extensions.get::<CurrentUser>();            // ハンドラ
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// 型をキーにした値のマップ
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, (Arc<dyn Any + Send + Sync>, &'static str)>,
+}
+
+impl Extensions {
+    /// 空のマップを作成
+    pub fn new() -> Self {
+        Extensions::default()
+    }
+
+    /// 値を登録する（同じ型の値があれば置き換える）
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        let entry = (Arc::new(value) as Arc<dyn Any + Send + Sync>, std::any::type_name::<T>());
+        self.map.insert(TypeId::of::<T>(), entry);
+    }
+
+    /// 指定した型の値
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|(value, _)| value.downcast_ref())
+    }
+
+    /// 指定した型の値があるか
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    /// 指定した型の値を取り除く（複製したマップと共有中でも、このマップからは消える）
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> bool {
+        self.map.remove(&TypeId::of::<T>()).is_some()
+    }
+
+    /// 両方のマップにある型のうち1つの型名（なければNone）
+    pub(crate) fn conflicting(&self, other: &Extensions) -> Option<&'static str> {
+        self.map
+            .iter()
+            .find(|(type_id, _)| other.map.contains_key(type_id))
+            .map(|(_, (_, name))| *name)
+    }
+
+    /// 他のマップの値を全て加える（同じ型の値は置き換える）
+    pub(crate) fn merge(&mut self, other: Extensions) {
+        self.map.extend(other.map);
+    }
+
+    /// 登録されている値の数
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// 値が1つもないか
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+/// 値の型は消えているため、件数のみ表示する
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.map.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct UserId(u32);
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut extensions = Extensions::new();
+        assert!(extensions.get::<UserId>().is_none());
+
+        extensions.insert(UserId(1));
+        extensions.insert("label");
+        extensions.insert(UserId(2));
+        assert_eq!(extensions.get::<UserId>(), Some(&UserId(2)));
+        assert_eq!(extensions.get::<&str>(), Some(&"label"));
+        assert_eq!(extensions.len(), 2);
+
+        // 複製は値を共有するが、削除は複製元に影響しない
+        let copy = extensions.clone();
+        assert!(extensions.remove::<UserId>());
+        assert!(!extensions.contains::<UserId>());
+        assert_eq!(copy.get::<UserId>(), Some(&UserId(2)));
+    }
+
+    #[test]
+    fn test_conflicting_and_merge() {
+        let mut base = Extensions::new();
+        base.insert(UserId(1));
+        let mut other = Extensions::new();
+        other.insert(UserId(2));
+        other.insert(3u8);
+
+        let name = base.conflicting(&other).unwrap();
+        assert!(name.ends_with("UserId"), "{}", name);
+
+        assert!(other.remove::<UserId>());
+        assert_eq!(base.conflicting(&other), None);
+        base.merge(other);
+        assert_eq!(base.get::<UserId>(), Some(&UserId(1)));
+        assert_eq!(base.get::<u8>(), Some(&3));
+    }
+}
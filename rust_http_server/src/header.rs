@@ -0,0 +1,185 @@
+// src/header.rs
+//
+// 【処理概要】
+// HTTPヘッダーを保持するマップ型を実装。
+// HashMapでは表現できない「同名ヘッダーの複数値」と「並び順」を扱う。
+//
+// 【主な機能】
+// - ヘッダー名の大文字小文字を区別しない検索
+// - 挿入順と元の表記（大文字小文字）の保持
+// - insert（同名ヘッダーを置き換え）と append（同名ヘッダーを追加）
+// - 同名ヘッダーの全値の取得（例: 複数のSet-Cookie）
+//
+// 【実装内容】
+// 1. (名前, 値) のペアをVecで保持（ヘッダー数は少ないため線形探索で十分）
+// 2. 名前の比較は eq_ignore_ascii_case で行う
+// 3. イテレーションは挿入順（レスポンスの出力順もこの順になる）
+
+/// HTTPヘッダーのマップ
+///
+/// 例:
+///   let mut headers = HeaderMap::new();
+///   headers.insert("Content-Type", "text/plain");
+///   headers.append("Set-Cookie", "a=1");
+///   headers.append("Set-Cookie", "b=2");
+///   assert_eq!(headers.get("content-type"), Some("text/plain"));
+///   assert_eq!(headers.get_all("set-cookie").count(), 2);
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    /// 空のヘッダーマップを作成
+    pub fn new() -> Self {
+        HeaderMap {
+            entries: Vec::new(),
+        }
+    }
+
+    /// 指定した名前の最初の値を取得（大文字小文字を区別しない）
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.position(name)
+            .map(|index| self.entries[index].1.as_str())
+    }
+
+    /// 指定した名前の全ての値を挿入順に取得
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// 指定した名前のヘッダーが存在するか
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// ヘッダーを設定（同名のヘッダーがあれば全て置き換える）
+    ///
+    /// 既存のヘッダーがある場合は最初の位置に新しい値を置くため、
+    /// 出力順は変わらない。
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        let value = value.into();
+
+        match self.position(&name) {
+            Some(index) => {
+                // 2つ目以降の同名ヘッダーを削除してから、最初の位置を置き換える
+                let mut i = 0;
+                self.entries.retain(|(key, _)| {
+                    let keep = i <= index || !key.eq_ignore_ascii_case(&name);
+                    i += 1;
+                    keep
+                });
+                self.entries[index] = (name, value);
+            }
+            None => self.entries.push((name, value)),
+        }
+    }
+
+    /// ヘッダーを追加（同名のヘッダーがあっても残す）
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    /// 指定した名前のヘッダーを全て削除し、最初の値を返す
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let first = self.position(name).map(|index| self.entries[index].1.clone());
+        self.entries.retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+        first
+    }
+
+    /// 全てのヘッダーを挿入順に取得（名前は元の表記のまま）
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// ヘッダーの個数（同名ヘッダーはそれぞれ数える）
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// ヘッダーが1つもないか
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 指定した名前の最初の位置
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|(key, _)| key.eq_ignore_ascii_case(name))
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Box<dyn Iterator<Item = (&'a str, &'a str)> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for HeaderMap
+where
+    K: Into<String>,
+    V: Into<String>,
+{
+    /// (名前, 値) のペアからヘッダーマップを作成（同名ヘッダーは全て残す）
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut headers = HeaderMap::new();
+        for (name, value) in iter {
+            headers.append(name, value);
+        }
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_insensitive_lookup_preserves_casing() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Request-ID", "42");
+
+        assert_eq!(headers.get("x-request-id"), Some("42"));
+        assert_eq!(headers.iter().next(), Some(("X-Request-ID", "42")));
+    }
+
+    #[test]
+    fn test_insert_replaces_all_values_in_place() {
+        let mut headers: HeaderMap = vec![
+            ("Server", "RustHTTP/1.0"),
+            ("Set-Cookie", "a=1"),
+            ("Content-Type", "text/plain"),
+            ("set-cookie", "b=2"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(headers.get_all("Set-Cookie").collect::<Vec<_>>(), ["a=1", "b=2"]);
+
+        headers.insert("SET-COOKIE", "c=3");
+        let names: Vec<&str> = headers.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["Server", "SET-COOKIE", "Content-Type"]);
+        assert_eq!(headers.get("set-cookie"), Some("c=3"));
+    }
+
+    #[test]
+    fn test_append_and_remove() {
+        let mut headers = HeaderMap::new();
+        headers.append("Set-Cookie", "a=1");
+        headers.append("Set-Cookie", "b=2");
+        assert_eq!(headers.len(), 2);
+
+        assert_eq!(headers.remove("set-cookie"), Some("a=1".to_string()));
+        assert!(headers.is_empty());
+        assert_eq!(headers.remove("set-cookie"), None);
+    }
+}
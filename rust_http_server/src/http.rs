@@ -6,149 +6,1062 @@
 //
 // 【主な機能】
 // - HTTPリクエストの解析（メソッド、パス、ヘッダー、ボディ）
+//   任意のBufReadからのパースと、バイト列を少しずつ渡すプッシュ型パース
 // - HTTPレスポンスの生成（ステータスコード、ヘッダー、ボディ）
 // - 生のバイト列とHTTP構造体の相互変換
+// - リクエストのサイズ制限（リクエスト行・ヘッダー・ボディ）
 //
 // 【実装内容】
 // 1. リクエスト行のパース（例: "GET /path HTTP/1.1"）
 // 2. ヘッダーのパース（例: "Content-Type: application/json"）
-// 3. ボディの読み取り（Content-Lengthに基づく）
+// 3. ボディの読み取り（Content-Length または Transfer-Encoding: chunked に基づく）
 // 4. レスポンスのバイト列生成（ステータス行 + ヘッダー + ボディ）
+// 5. ボディの種類に応じた転送方式の決定（Content-Length / chunked）
 
-use std::collections::HashMap;
-use std::io::{self, BufRead, BufReader, Read};
-use std::net::TcpStream;
+use crate::body::Body;
+use crate::header::HeaderMap;
+use crate::method::{is_token_char, Method};
+use crate::status::StatusCode;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// リクエストのサイズ制限
+///
+/// パース中に各上限を超えた場合は ParseError::TooLarge を返し、
+/// サーバーは対応する4xxレスポンス（414 / 431 / 413）を送信する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// リクエスト行の最大バイト数（改行を除く）
+    pub max_request_line: usize,
+    /// ヘッダー部全体の最大バイト数（トレーラーにも適用）
+    pub max_header_bytes: usize,
+    /// ヘッダーの最大個数（トレーラーにも適用）
+    pub max_header_count: usize,
+    /// ボディの最大バイト数（Content-Length / chunked共通）
+    pub max_body_size: usize,
+    /// chunked転送における1チャンクの最大バイト数
+    pub max_chunk_size: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            max_request_line: 8 * 1024,
+            max_header_bytes: 16 * 1024,
+            max_header_count: 100,
+            max_body_size: 8 * 1024 * 1024,
+            max_chunk_size: 1024 * 1024,
+        }
+    }
+}
+
+/// サイズ制限の超過を表すエラー
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    RequestLine, // 414 URI Too Long
+    HeaderBytes, // 431 Request Header Fields Too Large
+    HeaderCount, // 431 Request Header Fields Too Large
+    Body,        // 413 Content Too Large
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            LimitExceeded::RequestLine => "Request line too long",
+            LimitExceeded::HeaderBytes => "Header section too large",
+            LimitExceeded::HeaderCount => "Too many header fields",
+            LimitExceeded::Body => "Request body too large",
+        };
+        f.write_str(message)
+    }
+}
+
+/// リクエストのパースエラー
+///
+/// 接続断・タイムアウト・I/Oエラー以外はクライアントの誤りであり、
+/// サーバーはstatus()のステータスでエラーレスポンスを返す。
+#[derive(Debug)]
+pub enum ParseError {
+    /// リクエストを1バイトも受信しないまま接続が閉じられた
+    ConnectionClosed,
+    /// リクエストの途中で接続が閉じられた
+    UnexpectedEof,
+    /// 読み取りがタイムアウトした
+    Timeout,
+    /// その他のI/Oエラー
+    Io(io::Error),
+    /// 空のリクエスト行
+    EmptyRequest,
+    /// リクエスト行の形式が不正（"METHOD TARGET VERSION" でない）
+    InvalidRequestLine(String),
+    /// メソッド名にトークンとして使えない文字が含まれる
+    InvalidMethod(String),
+    /// サーバーとして受け付けないメソッド（CONNECT / TRACE）
+    MethodNotAllowed(String),
+    /// HTTPバージョンの形式が不正
+    InvalidVersion(String),
+    /// 対応していないHTTPバージョン（HTTP/1.0, HTTP/1.1 以外）
+    UnsupportedVersion(String),
+    /// ヘッダー行の形式が不正
+    InvalidHeader(String),
+    /// Content-Lengthの値が不正
+    InvalidContentLength(String),
+    /// Content-LengthとTransfer-Encodingの両方が指定された
+    ConflictingLength,
+    /// chunked以外のTransfer-Encoding
+    UnsupportedTransferEncoding(String),
+    /// chunked形式のボディが不正
+    InvalidChunk(String),
+    /// UTF-8として解釈できない行
+    InvalidEncoding,
+    /// サイズ制限の超過
+    TooLarge(LimitExceeded),
+}
+
+impl ParseError {
+    /// エラーレスポンスを返さず、そのまま接続を閉じるべきエラーか
+    /// （接続断・タイムアウト・I/Oエラー）
+    pub fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            ParseError::ConnectionClosed
+                | ParseError::UnexpectedEof
+                | ParseError::Timeout
+                | ParseError::Io(_)
+        )
+    }
+
+    /// 対応するステータスコード
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ParseError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            ParseError::UnsupportedVersion(_) => StatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            ParseError::UnsupportedTransferEncoding(_) => StatusCode::NOT_IMPLEMENTED,
+            ParseError::Timeout => StatusCode::REQUEST_TIMEOUT,
+            ParseError::TooLarge(LimitExceeded::RequestLine) => StatusCode::URI_TOO_LONG,
+            ParseError::TooLarge(LimitExceeded::HeaderBytes | LimitExceeded::HeaderCount) => {
+                StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+            }
+            ParseError::TooLarge(LimitExceeded::Body) => StatusCode::CONTENT_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// デフォルトのエラーレスポンス（JSONボディ）
+    ///
+    /// 例: {"error": "Bad Request", "message": "Invalid request line: GET"}
+    pub fn to_response(&self) -> HttpResponse {
+        let status = self.status();
+        let body = format!(
+            r#"{{"error": "{}", "message": "{}"}}"#,
+            status.canonical_reason().unwrap_or_default(),
+            json_escape(&self.to_string())
+        );
+        let mut response = HttpResponse::new(status).with_body(&body);
+        if status == StatusCode::METHOD_NOT_ALLOWED {
+            response.headers.insert("Allow", ALLOWED_METHODS);
+        }
+        response
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::ConnectionClosed => write!(f, "Connection closed before receiving a request"),
+            ParseError::UnexpectedEof => {
+                write!(f, "Connection closed before receiving complete request")
+            }
+            ParseError::Timeout => write!(f, "Timed out while reading the request"),
+            ParseError::Io(e) => write!(f, "I/O error: {}", e),
+            ParseError::EmptyRequest => write!(f, "Empty HTTP request"),
+            ParseError::InvalidRequestLine(line) => write!(f, "Invalid request line: {}", line),
+            ParseError::InvalidMethod(method) => write!(f, "Invalid method: {}", method),
+            ParseError::MethodNotAllowed(method) => write!(f, "Method not allowed: {}", method),
+            ParseError::InvalidVersion(version) => write!(f, "Invalid HTTP version: {}", version),
+            ParseError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported HTTP version: {}", version)
+            }
+            ParseError::InvalidHeader(line) => write!(f, "Invalid header: {}", line),
+            ParseError::InvalidContentLength(value) => {
+                write!(f, "Invalid Content-Length: {}", value)
+            }
+            ParseError::ConflictingLength => {
+                write!(f, "Request has both Content-Length and Transfer-Encoding")
+            }
+            ParseError::UnsupportedTransferEncoding(encoding) => {
+                write!(f, "Unsupported Transfer-Encoding: {}", encoding)
+            }
+            ParseError::InvalidChunk(reason) => write!(f, "Invalid chunked body: {}", reason),
+            ParseError::InvalidEncoding => write!(f, "Request is not valid UTF-8"),
+            ParseError::TooLarge(limit) => write!(f, "{}", limit),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => ParseError::UnexpectedEof,
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ParseError::Timeout,
+            _ => ParseError::Io(error),
+        }
+    }
+}
+
+impl From<LimitExceeded> for ParseError {
+    fn from(limit: LimitExceeded) -> Self {
+        ParseError::TooLarge(limit)
+    }
+}
+
+/// 405レスポンスのAllowヘッダーに載せるメソッド
+const ALLOWED_METHODS: &str = "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS";
 
 /// HTTPリクエストを表す構造体
 #[derive(Debug, Clone)]
 pub struct HttpRequest {
-    pub method: String,
+    pub method: Method,
     pub path: String,
     pub version: String,
-    pub headers: HashMap<String, String>,
+    pub headers: HeaderMap,
     pub body: Vec<u8>,
+    pub trailers: HeaderMap, // chunked転送のトレーラー（なければ空）
 }
 
 impl HttpRequest {
-    /// TcpStreamからHTTPリクエストをパースする
+    /// 任意のBufReadからHTTPリクエストをパースする（デフォルトのサイズ制限を適用）
+    ///
+    /// TcpStreamに限らず、BufReader<UnixStream>やTLSストリーム、
+    /// テスト用のバイト列（&[u8]）からもパースできる。
+    pub fn parse<R: BufRead>(reader: &mut R) -> Result<Self, ParseError> {
+        Self::parse_with_limits(reader, &RequestLimits::default())
+    }
+
+    /// サイズ制限を指定してHTTPリクエストをパースする
     /// 
     /// パース手順:
     /// 1. リクエスト行を読み取り（例: GET /path HTTP/1.1）
     /// 2. ヘッダー行を全て読み取り（空行まで）
     /// 3. Content-Lengthがあればボディを読み取り
-    pub fn parse(stream: &mut TcpStream) -> io::Result<Self> {
-        let mut reader = BufReader::new(stream);
-        let mut lines = Vec::new();
+    ///    Transfer-Encoding: chunkedならチャンクを順に読み取って連結
+    ///
+    /// Content-LengthとTransfer-Encodingの両方を含むリクエストは
+    /// リクエストスマグリングの原因となるため拒否する。
+    ///
+    /// 各行は上限に達した時点で読み取りを打ち切り、
+    /// Content-Lengthは上限と比較してからバッファを確保する。
+    ///
+    /// キープアライブ接続では同じリーダーを使い回すため、
+    /// 先読みしたバイト（次のリクエストの先頭）は失われない。
+    pub fn parse_with_limits<R: BufRead>(
+        reader: &mut R,
+        limits: &RequestLimits,
+    ) -> Result<Self, ParseError> {
+        // 1バイトも届かないまま時間切れになった場合はアイドル接続の切断として扱う
+        // （受信を始めた後のタイムアウトはTimeoutのまま返し、408で応答できるようにする）
+        match reader.fill_buf() {
+            Ok([]) => return Err(ParseError::ConnectionClosed),
+            Ok(_) => {}
+            Err(e) => match ParseError::from(e) {
+                ParseError::Timeout => return Err(ParseError::ConnectionClosed),
+                e => return Err(e),
+            },
+        }
+        let head = read_head(reader, limits)?;
 
-        // ヘッダー部分を読み取り（空行まで）
-        loop {
-            let mut line = String::new();
-            let bytes_read = reader.read_line(&mut line)?;
-            
-            if bytes_read == 0 {
-                return Err(io::Error::new(
-                    io::ErrorKind::UnexpectedEof,
-                    "Connection closed before receiving complete request",
-                ));
-            }
-
-            // 改行を削除
-            let line = line.trim_end().to_string();
-            
-            // 空行はヘッダーの終わりを示す
-            if line.is_empty() {
-                break;
+        // ボディの読み取り
+        let mut body = Vec::new();
+        let mut trailers = HeaderMap::new();
+        match body_kind(&head.headers, limits)? {
+            BodyKind::Empty => {}
+            BodyKind::Length(length) => {
+                body = vec![0; length];
+                reader.read_exact(&mut body)?;
+            }
+            BodyKind::Chunked => {
+                (body, trailers) = ChunkedDecoder::new().read_from(reader, limits)?;
             }
-            
-            lines.push(line);
         }
 
-        if lines.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Empty HTTP request",
-            ));
+        Ok(head.into_request(body, trailers))
+    }
+
+    /// クライアントが接続の持続（キープアライブ）を望んでいるか
+    ///
+    /// - Connection: close があれば常に false
+    /// - HTTP/1.1 はデフォルトで持続接続
+    /// - HTTP/1.0 は Connection: keep-alive がある場合のみ持続
+    pub fn wants_keep_alive(&self) -> bool {
+        let has_token = |token: &str| {
+            self.headers
+                .get_all("connection")
+                .any(|value| has_connection_token(value, token))
+        };
+
+        if has_token("close") {
+            return false;
         }
 
-        // リクエスト行をパース（例: "GET /path HTTP/1.1"）
-        let request_line = &lines[0];
-        let parts: Vec<&str> = request_line.split_whitespace().collect();
-        
-        if parts.len() != 3 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Invalid request line: {}", request_line),
-            ));
+        match self.version.as_str() {
+            "HTTP/1.0" => has_token("keep-alive"),
+            _ => true,
         }
+    }
+}
+
+// ===== プッシュ型パーサー =====
+
+/// RequestParser::feedの結果
+#[derive(Debug)]
+pub enum ParseStatus {
+    /// リクエストが完成していない（さらにバイト列が必要）
+    NeedMore,
+    /// リクエストが1件完成した
+    Complete(HttpRequest),
+}
 
-        let method = parts[0].to_string();
-        let path = parts[1].to_string();
-        let version = parts[2].to_string();
+/// プッシュ型（インクリメンタル）のリクエストパーサー
+///
+/// 受信したバイト列を少しずつfeedし、リクエストが完成したら
+/// ParseStatus::Complete を受け取る。ノンブロッキングI/Oのイベントループや
+/// ファジングから直接駆動できる。
+///
+/// 使用例:
+///   let mut parser = RequestParser::new();
+///   match parser.feed(&buf[..n])? {
+///       ParseStatus::NeedMore => { /* 次の読み取りを待つ */ }
+///       ParseStatus::Complete(request) => { /* 処理 */ }
+///   }
+///
+/// パイプライン化された後続リクエストのバイト列はバッファに残り、
+/// feed(&[]) を呼ぶと次のリクエストのパースを試みる。
+/// エラーを返した後のパーサーは再利用しないこと。
+///
+/// 途中までの結果（ヘッダー部を探し終えた位置、chunkedの読み取り状態と
+/// デコード済みのボディ）を保持するため、少しずつ届いても各バイトは1度しか調べない。
+#[derive(Debug)]
+pub struct RequestParser {
+    limits: RequestLimits,
+    buffer: Vec<u8>,
+    /// ヘッダー部の終端（空行）を探した位置
+    head_scan: SectionScan,
+    /// パース済みのヘッダー部とボディの読み取り状態（ボディ待ちの間だけSome）
+    head: Option<(RequestHead, BodyState)>,
+}
+
+/// ボディの読み取り状態
+#[derive(Debug)]
+enum BodyState {
+    Empty,
+    Length(usize),
+    Chunked(ChunkedDecoder),
+}
+
+impl RequestParser {
+    /// デフォルトのサイズ制限でパーサーを作成
+    pub fn new() -> Self {
+        Self::with_limits(RequestLimits::default())
+    }
 
-        // ヘッダーをパース（例: "Content-Type: application/json"）
-        let mut headers = HashMap::new();
-        for line in &lines[1..] {
-            if let Some((key, value)) = line.split_once(':') {
-                headers.insert(
-                    key.trim().to_lowercase(),
-                    value.trim().to_string(),
-                );
+    /// サイズ制限を指定してパーサーを作成
+    pub fn with_limits(limits: RequestLimits) -> Self {
+        RequestParser {
+            limits,
+            buffer: Vec::new(),
+            head_scan: SectionScan::default(),
+            head: None,
+        }
+    }
+
+    /// バイト列を追加し、リクエストの完成を試みる
+    ///
+    /// 1. ヘッダー部が未完成なら、前回の続きから終端の空行を探す
+    /// 2. 終端が見つかったらヘッダー部を1度だけパースしてバッファから取り除く
+    /// 3. ボディは届いた分だけ読み進め、揃っていればリクエストを返す
+    pub fn feed(&mut self, data: &[u8]) -> Result<ParseStatus, ParseError> {
+        self.buffer.extend_from_slice(data);
+
+        if self.head.is_none() {
+            let Some(end) = self.head_scan.scan(&self.buffer, &self.limits, true)? else {
+                return Ok(ParseStatus::NeedMore);
+            };
+            let mut reader = &self.buffer[..end];
+            let head = read_head(&mut reader, &self.limits)?;
+            let body = match body_kind(&head.headers, &self.limits)? {
+                BodyKind::Empty => BodyState::Empty,
+                BodyKind::Length(length) => BodyState::Length(length),
+                BodyKind::Chunked => BodyState::Chunked(ChunkedDecoder::new()),
+            };
+            self.buffer.drain(..end);
+            self.head_scan = SectionScan::default();
+            self.head = Some((head, body));
+        }
+
+        // ボディの読み取り
+        let Some((_, body)) = &mut self.head else {
+            unreachable!("request head is parsed");
+        };
+        let (body, trailers) = match body {
+            BodyState::Empty => (Vec::new(), HeaderMap::new()),
+            BodyState::Length(length) => {
+                if self.buffer.len() < *length {
+                    return Ok(ParseStatus::NeedMore);
+                }
+                (self.buffer.drain(..*length).collect(), HeaderMap::new())
+            }
+            BodyState::Chunked(decoder) => match decoder.advance(&mut self.buffer, &self.limits)? {
+                Some(done) => done,
+                None => return Ok(ParseStatus::NeedMore),
+            },
+        };
+
+        let (head, _) = self.head.take().expect("request head is parsed");
+        Ok(ParseStatus::Complete(head.into_request(body, trailers)))
+    }
+
+    /// まだリクエストとして消費されていないバイト数
+    pub fn buffered_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// リクエストの途中かどうか（途中で接続が閉じられたらエラーとすべき状態）
+    pub fn is_partial(&self) -> bool {
+        self.head.is_some() || !self.buffer.is_empty()
+    }
+}
+
+impl Default for RequestParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// ヘッダー部（またはトレーラー）の終端の空行を探す状態
+///
+/// 前回調べた位置を覚えておき、追加されたバイトだけを調べる。
+/// 終端が見つかる前でもサイズ制限を超えた時点でエラーにする。
+#[derive(Debug, Default)]
+struct SectionScan {
+    /// 次に調べる位置
+    offset: usize,
+    /// 読み取り中の行の先頭
+    line_start: usize,
+    /// 読み終えた行数
+    lines: usize,
+    /// 読み終えたヘッダー行のバイト数の合計（改行を除く）
+    header_bytes: usize,
+}
+
+impl SectionScan {
+    /// 終端の空行を探し、見つかれば空行の直後の位置を返す
+    ///
+    /// request_line: 最初の行がリクエスト行か（falseならトレーラー）
+    fn scan(
+        &mut self,
+        buffer: &[u8],
+        limits: &RequestLimits,
+        request_line: bool,
+    ) -> Result<Option<usize>, ParseError> {
+        while let Some(i) = buffer[self.offset..].iter().position(|&b| b == b'\n') {
+            let newline = self.offset + i;
+            let line = &buffer[self.line_start..newline];
+            let length = line.len() - usize::from(line.last() == Some(&b'\r'));
+            let is_request_line = request_line && self.lines == 0;
+
+            self.offset = newline + 1;
+            self.line_start = newline + 1;
+            self.lines += 1;
+            // 空行で終端（空のリクエスト行もここで返し、read_headにエラーを任せる）
+            if length == 0 {
+                return Ok(Some(newline + 1));
+            }
+            if !is_request_line {
+                self.header_bytes += length;
             }
         }
+        self.offset = buffer.len();
 
-        // ボディの読み取り（Content-Lengthがある場合）
-        let mut body = Vec::new();
-        if let Some(length_str) = headers.get("content-length") {
-            if let Ok(length) = length_str.parse::<usize>() {
-                body = vec![0; length];
-                reader.read_exact(&mut body)?;
+        // 終端が届く前でも、上限を超えていればエラー（CRの1バイトは上限に含めない）
+        let partial = buffer.len() - self.line_start;
+        if request_line && self.lines == 0 {
+            if partial > limits.max_request_line + 1 {
+                return Err(LimitExceeded::RequestLine.into());
+            }
+        } else {
+            if self.header_bytes + partial > limits.max_header_bytes + 1 {
+                return Err(LimitExceeded::HeaderBytes.into());
+            }
+            if self.lines - usize::from(request_line) > limits.max_header_count {
+                return Err(LimitExceeded::HeaderCount.into());
             }
         }
+        Ok(None)
+    }
+}
 
-        Ok(HttpRequest {
-            method,
-            path,
-            version,
-            headers,
+/// chunked形式のボディの読み取り状態
+#[derive(Debug)]
+enum ChunkState {
+    /// チャンクサイズ行を待っている
+    Size,
+    /// チャンクデータ（残りのバイト数）
+    Data(usize),
+    /// チャンクデータ直後のCRLFを待っている
+    DataEnd,
+    /// 最終チャンクの後のトレーラー
+    Trailers(SectionScan),
+}
+
+/// 届いた分だけchunked形式のボディをデコードする
+///
+/// フォーマット:
+/// 1a;ext=value\r\n      <- チャンクサイズ（16進数）とチャンク拡張
+/// ...26バイトのデータ...\r\n
+/// 0\r\n                 <- 最終チャンク
+/// Trailer: value\r\n    <- トレーラー（任意）
+/// \r\n
+///
+/// チャンクサイズとボディ全体のサイズにはlimitsの上限を適用する。
+/// トレーラーにはヘッダーと同じ上限を適用する。
+#[derive(Debug)]
+struct ChunkedDecoder {
+    state: ChunkState,
+    /// デコード済みのボディ
+    body: Vec<u8>,
+}
+
+impl ChunkedDecoder {
+    fn new() -> Self {
+        ChunkedDecoder {
+            state: ChunkState::Size,
+            body: Vec::new(),
+        }
+    }
+
+    /// バッファから読めるだけ読み進める（読んだバイトはバッファから取り除く）
+    ///
+    /// トレーラーまで揃えばボディとトレーラーを返す。
+    fn advance(
+        &mut self,
+        buffer: &mut Vec<u8>,
+        limits: &RequestLimits,
+    ) -> Result<Option<(Vec<u8>, HeaderMap)>, ParseError> {
+        let mut consumed = 0;
+
+        let done = loop {
+            let available = &buffer[consumed..];
+            match &mut self.state {
+                ChunkState::Size => {
+                    let Some(newline) = available.iter().position(|&b| b == b'\n') else {
+                        if available.len() > MAX_CHUNK_SIZE_LINE + 1 {
+                            return Err(chunk_size_line_too_long());
+                        }
+                        break None;
+                    };
+                    let line = &available[..newline];
+                    let line = line.strip_suffix(b"\r").unwrap_or(line);
+                    if line.len() > MAX_CHUNK_SIZE_LINE {
+                        return Err(chunk_size_line_too_long());
+                    }
+                    let line = std::str::from_utf8(line).map_err(|_| ParseError::InvalidEncoding)?;
+                    let size = parse_chunk_size(line, limits)?;
+                    consumed += newline + 1;
+
+                    if size == 0 {
+                        // トレーラーの探索はバッファ先頭からの位置で行う
+                        buffer.drain(..consumed);
+                        consumed = 0;
+                        self.state = ChunkState::Trailers(SectionScan::default());
+                    } else if self.body.len() + size > limits.max_body_size {
+                        return Err(LimitExceeded::Body.into());
+                    } else {
+                        self.state = ChunkState::Data(size);
+                    }
+                }
+                ChunkState::Data(remaining) => {
+                    if available.is_empty() {
+                        break None;
+                    }
+                    let n = (*remaining).min(available.len());
+                    self.body.extend_from_slice(&available[..n]);
+                    consumed += n;
+                    *remaining -= n;
+                    if *remaining == 0 {
+                        self.state = ChunkState::DataEnd;
+                    }
+                }
+                ChunkState::DataEnd => {
+                    if available.len() < 2 {
+                        break None;
+                    }
+                    if &available[..2] != b"\r\n" {
+                        return Err(ParseError::InvalidChunk(
+                            "missing CRLF after chunk data".to_string(),
+                        ));
+                    }
+                    consumed += 2;
+                    self.state = ChunkState::Size;
+                }
+                ChunkState::Trailers(scan) => {
+                    let Some(end) = scan.scan(buffer, limits, false)? else {
+                        break None;
+                    };
+                    let mut reader = &buffer[..end];
+                    let trailers = read_header_fields(&mut reader, limits)?;
+                    consumed = end;
+                    break Some((std::mem::take(&mut self.body), trailers));
+                }
+            }
+        };
+
+        buffer.drain(..consumed);
+        Ok(done)
+    }
+
+    /// BufReadからボディとトレーラーを最後まで読み取る
+    ///
+    /// fill_bufで得た分をadvanceに渡す。終端より後ろのバイト（次のリクエストの先頭）は
+    /// リーダーに残す。
+    fn read_from<R: BufRead>(
+        mut self,
+        reader: &mut R,
+        limits: &RequestLimits,
+    ) -> Result<(Vec<u8>, HeaderMap), ParseError> {
+        // 行やトレーラーの途中で途切れた分（リーダーからは消費済み）
+        let mut pending = Vec::new();
+
+        loop {
+            let available = reader.fill_buf()?;
+            if available.is_empty() {
+                return Err(ParseError::UnexpectedEof);
+            }
+            let received = available.len();
+            pending.extend_from_slice(available);
+
+            let done = self.advance(&mut pending, limits)?;
+            // 終端に達した場合、残りは今回受け取った分の末尾にある
+            reader.consume(received - if done.is_some() { pending.len() } else { 0 });
+            if let Some(done) = done {
+                return Ok(done);
+            }
+        }
+    }
+}
+
+// ===== パースの共通処理 =====
+
+/// パース済みのヘッダー部（リクエスト行 + ヘッダー）
+#[derive(Debug)]
+struct RequestHead {
+    method: Method,
+    path: String,
+    version: String,
+    headers: HeaderMap,
+}
+
+impl RequestHead {
+    fn into_request(self, body: Vec<u8>, trailers: HeaderMap) -> HttpRequest {
+        HttpRequest {
+            method: self.method,
+            path: self.path,
+            version: self.version,
+            headers: self.headers,
             body,
-        })
+            trailers,
+        }
+    }
+}
+
+/// ボディの転送方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyKind {
+    Empty,
+    Length(usize),
+    Chunked,
+}
+
+/// リクエスト行とヘッダーを読み取る
+fn read_head<R: BufRead>(reader: &mut R, limits: &RequestLimits) -> Result<RequestHead, ParseError> {
+    // リクエスト行を読み取り
+    let request_line =
+        read_line_limited(reader, limits.max_request_line, LimitExceeded::RequestLine)?
+            .ok_or(ParseError::ConnectionClosed)?;
+
+    if request_line.is_empty() {
+        return Err(ParseError::EmptyRequest);
+    }
+
+    // リクエスト行をパース（例: "GET /path HTTP/1.1"）
+    let parts: Vec<&str> = request_line.split_whitespace().collect();
+    
+    if parts.len() != 3 {
+        return Err(ParseError::InvalidRequestLine(request_line));
+    }
+
+    let method = parse_method(parts[0])?;
+    let path = parts[1].to_string();
+    let version = parse_version(parts[2])?;
+
+    // ヘッダーをパース（例: "Content-Type: application/json"）
+    let headers = read_header_fields(reader, limits)?;
+
+    Ok(RequestHead {
+        method,
+        path,
+        version,
+        headers,
+    })
+}
+
+/// ヘッダーからボディの転送方式を判定する
+///
+/// - Content-LengthとTransfer-Encodingの両方 → エラー（スマグリング対策）
+/// - Transfer-Encoding: chunked → Chunked（chunked以外は非対応）
+/// - Content-Length → Length（値の食い違う複数指定や上限超過はエラー）
+fn body_kind(headers: &HeaderMap, limits: &RequestLimits) -> Result<BodyKind, ParseError> {
+    // 複数行のTransfer-Encodingはカンマ区切りで連結したものとして扱う
+    let encodings: Vec<&str> = headers
+        .get_all("transfer-encoding")
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    let mut lengths = headers.get_all("content-length");
+    let length = lengths.next();
+    if let Some(other) = lengths.find(|&other| Some(other) != length) {
+        return Err(ParseError::InvalidContentLength(other.to_string()));
+    }
+
+    match (encodings.is_empty(), length) {
+        (false, Some(_)) => Err(ParseError::ConflictingLength),
+        (false, None) => {
+            if encodings.len() != 1 || !encodings[0].eq_ignore_ascii_case("chunked") {
+                return Err(ParseError::UnsupportedTransferEncoding(encodings.join(", ")));
+            }
+            Ok(BodyKind::Chunked)
+        }
+        (true, Some(length_str)) => {
+            let length = length_str
+                .parse::<usize>()
+                .map_err(|_| ParseError::InvalidContentLength(length_str.to_string()))?;
+            if length > limits.max_body_size {
+                return Err(LimitExceeded::Body.into());
+            }
+            Ok(BodyKind::Length(length))
+        }
+        (true, None) => Ok(BodyKind::Empty),
+    }
+}
+
+/// メソッド名を検証する（RFC 9110のtoken文字のみ許可）
+fn parse_method(method: &str) -> Result<Method, ParseError> {
+    let method: Method = method
+        .parse()
+        .map_err(|_| ParseError::InvalidMethod(method.to_string()))?;
+    // プロキシ用のCONNECTと、ヘッダーを反射するTRACEは受け付けない
+    if matches!(method, Method::Connect | Method::Trace) {
+        return Err(ParseError::MethodNotAllowed(method.to_string()));
+    }
+    Ok(method)
+}
+
+/// HTTPバージョンを検証する（"HTTP/x.y" 形式、1.0と1.1のみ対応）
+fn parse_version(version: &str) -> Result<String, ParseError> {
+    let digits = version
+        .strip_prefix("HTTP/")
+        .map(|v| v.as_bytes())
+        .filter(|v| v.len() == 3 && v[0].is_ascii_digit() && v[1] == b'.' && v[2].is_ascii_digit())
+        .ok_or_else(|| ParseError::InvalidVersion(version.to_string()))?;
+
+    match digits {
+        b"1.0" | b"1.1" => Ok(version.to_string()),
+        _ => Err(ParseError::UnsupportedVersion(version.to_string())),
+    }
+}
+
+/// ヘッダー行を空行まで読み取る
+///
+/// ヘッダー名は元の表記のまま、同名ヘッダーも全て保持する。ヘッダー部全体のバイト数と
+/// ヘッダーの個数がlimitsを超えたらエラー。
+fn read_header_fields<R: BufRead>(
+    reader: &mut R,
+    limits: &RequestLimits,
+) -> Result<HeaderMap, ParseError> {
+    let mut headers = HeaderMap::new();
+    let mut remaining = limits.max_header_bytes;
+    let mut count = 0;
+
+    loop {
+        let line = read_line_limited(reader, remaining, LimitExceeded::HeaderBytes)?
+            .ok_or(ParseError::UnexpectedEof)?;
+
+        // 空行はヘッダーの終わりを示す
+        if line.is_empty() {
+            return Ok(headers);
+        }
+
+        remaining = remaining.saturating_sub(line.len());
+        count += 1;
+        if count > limits.max_header_count {
+            return Err(LimitExceeded::HeaderCount.into());
+        }
+
+        // ヘッダー名は空でなく、コロンの前に空白を含まないこと
+        match line.split_once(':') {
+            Some((key, value)) if !key.is_empty() && key.bytes().all(is_token_char) => {
+                headers.append(key, value.trim());
+            }
+            _ => return Err(ParseError::InvalidHeader(line)),
+        }
     }
 }
 
+/// chunk-size行の最大バイト数（チャンク拡張を含む）
+const MAX_CHUNK_SIZE_LINE: usize = 1024;
+
+/// チャンクサイズ行からサイズを取り出す（チャンク拡張は読み飛ばす）
+fn parse_chunk_size(line: &str, limits: &RequestLimits) -> Result<usize, ParseError> {
+    let size_str = match line.split_once(';') {
+        Some((size, _extensions)) => size,
+        None => line,
+    }
+    .trim_end_matches([' ', '\t']);
+
+    if size_str.is_empty() || !size_str.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseError::InvalidChunk(format!("invalid chunk size: {}", line)));
+    }
+    usize::from_str_radix(size_str, 16)
+        .ok()
+        .filter(|&size| size <= limits.max_chunk_size)
+        .ok_or_else(|| LimitExceeded::Body.into())
+}
+
+/// チャンクサイズ行が長すぎる場合のエラー
+fn chunk_size_line_too_long() -> ParseError {
+    ParseError::InvalidChunk("chunk size line too long".to_string())
+}
+
+/// 1行読み取り、末尾の改行（CRLFまたはLF）を取り除いて返す
+///
+/// 改行を除いてlimitバイトを超えた時点でexceededのエラーを返すため、
+/// 改行を送らないクライアントがいてもメモリを使い切らない。
+/// 何も読まずにEOFに達した場合はNoneを返す。
+fn read_line_limited<R: BufRead>(
+    reader: &mut R,
+    limit: usize,
+    exceeded: LimitExceeded,
+) -> Result<Option<String>, ParseError> {
+    let mut line = Vec::new();
+
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            if line.is_empty() {
+                return Ok(None);
+            }
+            return Err(ParseError::UnexpectedEof);
+        }
+
+        let (chunk, found_newline) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (&available[..i], true),
+            None => (available, false),
+        };
+        // CRの1バイトは上限に含めない
+        if line.len() + chunk.len() > limit + 1 {
+            return Err(exceeded.into());
+        }
+        line.extend_from_slice(chunk);
+        let consumed = chunk.len() + usize::from(found_newline);
+        reader.consume(consumed);
+
+        if found_newline {
+            break;
+        }
+    }
+
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    if line.len() > limit {
+        return Err(exceeded.into());
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ParseError::InvalidEncoding)
+}
+
+/// JSON文字列リテラル用に特殊文字をエスケープする
+pub fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Connectionヘッダーの値（カンマ区切り）に指定トークンが含まれるか
+fn has_connection_token(value: &str, token: &str) -> bool {
+    value
+        .split(',')
+        .any(|t| t.trim().eq_ignore_ascii_case(token))
+}
+
 /// HTTPレスポンスを表す構造体
+///
+/// 複製した場合、ストリーミングボディを送信できるのは一方のみ（SharedStreamを参照）。
 #[derive(Debug, Clone)]
 pub struct HttpResponse {
-    pub status_code: u16,
-    pub status_text: String,
-    pub headers: HashMap<String, String>,
-    pub body: Vec<u8>,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Body,
+}
+
+/// レスポンスボディの転送方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// Content-Lengthで長さを通知
+    ContentLength(u64),
+    /// Transfer-Encoding: chunked
+    Chunked,
+    /// 長さ不明かつchunked非対応のクライアント。接続を閉じてボディの終端を示す
+    CloseDelimited,
+    /// ボディを持たないステータス（1xx / 204 / 304）。長さのヘッダーも付けない
+    NoBody,
 }
 
 impl HttpResponse {
     /// 新しいレスポンスを作成
-    pub fn new(status_code: u16, status_text: &str) -> Self {
-        let mut headers = HashMap::new();
-        headers.insert("Server".to_string(), "RustHTTP/1.0".to_string());
-        headers.insert("Content-Type".to_string(), "application/json".to_string());
+    ///
+    /// ステータステキストはステータスコードの標準のものが使われる。
+    pub fn new(status: StatusCode) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert("Server", "RustHTTP/1.0");
+        headers.insert("Content-Type", "application/json");
         
         HttpResponse {
-            status_code,
-            status_text: status_text.to_string(),
+            status,
             headers,
-            body: Vec::new(),
+            body: Body::empty(),
         }
     }
 
     /// ボディを設定
     pub fn with_body(mut self, body: &str) -> Self {
-        self.body = body.as_bytes().to_vec();
-        self.headers.insert(
-            "Content-Length".to_string(),
-            self.body.len().to_string(),
-        );
+        self.body = Body::from(body);
+        self.headers.insert("Content-Length", body.len().to_string());
+        self
+    }
+
+    /// ストリーミングボディ（ファイル、チャンクのイテレータ等）を設定
+    ///
+    /// 転送方式（Content-Length / chunked）は送信時に
+    /// ボディの長さとクライアントのバージョンから決定する。
+    pub fn with_stream(mut self, body: Body) -> Self {
+        self.body = body;
+        self.headers.remove("content-length");
+        self.headers.remove("transfer-encoding");
         self
     }
 
+    /// ハンドラがConnection: closeを指定しているか
+    pub fn wants_close(&self) -> bool {
+        self.headers
+            .get_all("connection")
+            .any(|value| has_connection_token(value, "close"))
+    }
+
+    /// サーバーが決定した接続の持続可否をConnectionヘッダーに反映
+    pub fn set_keep_alive(&mut self, keep_alive: bool) {
+        let value = if keep_alive { "keep-alive" } else { "close" };
+        self.headers.insert("Connection", value);
+    }
+
+    /// ボディの転送方式を決定し、対応するヘッダーを設定する
+    ///
+    /// - 長さが分かるボディ → Content-Length
+    /// - 長さ不明でchunked可（HTTP/1.1） → Transfer-Encoding: chunked
+    /// - 長さ不明でchunked不可（HTTP/1.0） → ヘッダーなし、送信後に切断
+    /// - 1xx / 204 / 304 → ボディを捨て、どちらのヘッダーも付けない
+    pub fn prepare_framing(&mut self, chunked_allowed: bool) -> Framing {
+        self.headers.remove("content-length");
+        self.headers.remove("transfer-encoding");
+
+        if !self.status_allows_body() {
+            self.body = Body::empty();
+            return Framing::NoBody;
+        }
+
+        match self.body.length() {
+            Some(length) => {
+                self.headers.insert("Content-Length", length.to_string());
+                Framing::ContentLength(length)
+            }
+            None if chunked_allowed => {
+                self.headers.insert("Transfer-Encoding", "chunked");
+                Framing::Chunked
+            }
+            None => Framing::CloseDelimited,
+        }
+    }
+
+    /// HEADリクエストへの応答としてボディを取り除く
+    ///
+    /// ヘッダーはGETの場合と同じものを残し、長さが分かるボディであれば
+    /// そのContent-Lengthを設定する。以降はprepare_framingを呼ばず、
+    /// write_head_toでヘッダー部だけを送ること。
+    pub fn strip_body(&mut self) {
+        if !self.status_allows_body() {
+            self.headers.remove("content-length");
+            self.headers.remove("transfer-encoding");
+        } else if let Some(length) = self.body.length() {
+            self.headers.insert("Content-Length", length.to_string());
+        }
+        self.body = Body::empty();
+    }
+
+    /// ボディを送れるステータスか（1xx・204・304はボディを持たない）
+    fn status_allows_body(&self) -> bool {
+        !(self.status.is_informational()
+            || self.status == StatusCode::NO_CONTENT
+            || self.status == StatusCode::NOT_MODIFIED)
+    }
+
+    /// ステータス行とヘッダーだけを書き出す（HEADレスポンス用）
+    pub fn write_head_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.head_bytes())
+    }
+
+    /// レスポンスを書き出す（ステータス行 + ヘッダー + ボディ）
+    ///
+    /// Transfer-Encoding: chunkedが設定されていればボディをchunked形式で送る。
+    /// 事前にprepare_framingで転送方式を決めておくこと。
+    pub fn write_to<W: Write>(self, writer: &mut W) -> io::Result<()> {
+        let chunked = self
+            .headers
+            .get("transfer-encoding")
+            .is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+        writer.write_all(&self.head_bytes())?;
+        self.body.write_to(writer, chunked)
+    }
+
     /// HTTPレスポンスをバイト列に変換
     /// 
     /// フォーマット:
@@ -157,17 +1070,29 @@ impl HttpResponse {
     /// Header2: Value2\r\n
     /// \r\n
     /// body content
-    pub fn to_bytes(&self) -> Vec<u8> {
+    ///
+    /// write_toでメモリ上に書き出すため、ストリーミングのボディも最後まで読み取って含める
+    /// （読み取りに失敗した場合はエラー）。
+    pub fn to_bytes(self) -> io::Result<Vec<u8>> {
+        let mut response = Vec::new();
+        self.write_to(&mut response)?;
+        Ok(response)
+    }
+
+    /// ステータス行とヘッダー（末尾の空行を含む）をバイト列に変換
+    fn head_bytes(&self) -> Vec<u8> {
         let mut response = Vec::new();
 
         // ステータス行
+        // 未登録のコードはステータステキストを空にする（RFC 9112で許容）
         let status_line = format!(
             "HTTP/1.1 {} {}\r\n",
-            self.status_code, self.status_text
+            self.status.as_u16(),
+            self.status.canonical_reason().unwrap_or_default()
         );
         response.extend_from_slice(status_line.as_bytes());
 
-        // ヘッダー
+        // ヘッダー（挿入順に出力）
         for (key, value) in &self.headers {
             let header_line = format!("{}: {}\r\n", key, value);
             response.extend_from_slice(header_line.as_bytes());
@@ -176,9 +1101,6 @@ impl HttpResponse {
         // 空行（ヘッダーとボディの区切り）
         response.extend_from_slice(b"\r\n");
 
-        // ボディ
-        response.extend_from_slice(&self.body);
-
         response
     }
 }
@@ -188,32 +1110,32 @@ impl HttpResponse {
 impl HttpResponse {
     /// 200 OK レスポンス
     pub fn ok(body: &str) -> Self {
-        Self::new(200, "OK").with_body(body)
+        Self::new(StatusCode::OK).with_body(body)
     }
 
     /// 201 Created レスポンス
     pub fn created(body: &str) -> Self {
-        Self::new(201, "Created").with_body(body)
+        Self::new(StatusCode::CREATED).with_body(body)
     }
 
     /// 400 Bad Request レスポンス
     pub fn bad_request(body: &str) -> Self {
-        Self::new(400, "Bad Request").with_body(body)
+        Self::new(StatusCode::BAD_REQUEST).with_body(body)
     }
 
     /// 401 Unauthorized レスポンス
     pub fn unauthorized(body: &str) -> Self {
-        Self::new(401, "Unauthorized").with_body(body)
+        Self::new(StatusCode::UNAUTHORIZED).with_body(body)
     }
 
     /// 404 Not Found レスポンス
     pub fn not_found(body: &str) -> Self {
-        Self::new(404, "Not Found").with_body(body)
+        Self::new(StatusCode::NOT_FOUND).with_body(body)
     }
 
     /// 500 Internal Server Error レスポンス
     pub fn internal_error(body: &str) -> Self {
-        Self::new(500, "Internal Server Error").with_body(body)
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR).with_body(body)
     }
 }
 
@@ -224,11 +1146,291 @@ mod tests {
     #[test]
     fn test_response_to_bytes() {
         let response = HttpResponse::ok(r#"{"status": "success"}"#);
-        let bytes = response.to_bytes();
+        let bytes = response.to_bytes().unwrap();
         let text = String::from_utf8_lossy(&bytes);
         
         assert!(text.contains("HTTP/1.1 200 OK"));
         assert!(text.contains("Content-Type: application/json"));
         assert!(text.contains(r#"{"status": "success"}"#));
+
+        // ストリーミングのボディも含める
+        let mut sent = false;
+        let response = HttpResponse::new(StatusCode::OK).with_stream(Body::from_producer(move || {
+            (!std::mem::replace(&mut sent, true)).then(|| Ok(b"streamed".to_vec()))
+        }));
+        assert!(response.to_bytes().unwrap().ends_with(b"\r\n\r\nstreamed"));
+    }
+
+    #[test]
+    fn test_status_line_uses_canonical_reason() {
+        let text = String::from_utf8(HttpResponse::new(StatusCode::CONTENT_TOO_LARGE).to_bytes().unwrap()).unwrap();
+        assert!(text.starts_with("HTTP/1.1 413 Content Too Large\r\n"));
+
+        let unknown = StatusCode::from_u16(599).unwrap();
+        let text = String::from_utf8(HttpResponse::new(unknown).to_bytes().unwrap()).unwrap();
+        assert!(text.starts_with("HTTP/1.1 599 \r\n"));
+    }
+
+    fn request_with(version: &str, connection: Option<&str>) -> HttpRequest {
+        let mut headers = HeaderMap::new();
+        if let Some(value) = connection {
+            headers.insert("Connection", value);
+        }
+        HttpRequest {
+            method: Method::Get,
+            path: "/".to_string(),
+            version: version.to_string(),
+            headers,
+            body: Vec::new(),
+            trailers: HeaderMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_keep_alive_defaults() {
+        assert!(request_with("HTTP/1.1", None).wants_keep_alive());
+        assert!(!request_with("HTTP/1.1", Some("close")).wants_keep_alive());
+        assert!(!request_with("HTTP/1.0", None).wants_keep_alive());
+        assert!(request_with("HTTP/1.0", Some("Keep-Alive")).wants_keep_alive());
+    }
+
+    #[test]
+    fn test_set_keep_alive_header() {
+        let mut response = HttpResponse::new(StatusCode::NO_CONTENT);
+        response.set_keep_alive(false);
+        assert_eq!(response.headers.get("Connection"), Some("close"));
+        assert!(response.wants_close());
+    }
+
+    #[test]
+    fn test_prepare_framing() {
+        // ボディを持たないステータスには長さのヘッダーを付けない
+        for status in [StatusCode::CONTINUE, StatusCode::NO_CONTENT, StatusCode::NOT_MODIFIED] {
+            let mut response = HttpResponse::new(status).with_body("ignored");
+            assert_eq!(response.prepare_framing(true), Framing::NoBody);
+            assert!(!response.headers.contains_key("Content-Length"));
+            assert!(!response.headers.contains_key("Transfer-Encoding"));
+
+            let mut out = Vec::new();
+            response.write_to(&mut out).unwrap();
+            assert!(out.ends_with(b"\r\n\r\n"));
+        }
+
+        let mut response = HttpResponse::new(StatusCode::OK);
+        assert_eq!(response.prepare_framing(true), Framing::ContentLength(0));
+        assert_eq!(response.headers.get("Content-Length"), Some("0"));
+
+        let mut response = HttpResponse::new(StatusCode::OK).with_stream(Body::from_chunks(vec!["a"]));
+        assert_eq!(response.prepare_framing(false), Framing::CloseDelimited);
+        assert_eq!(response.prepare_framing(true), Framing::Chunked);
+
+        let mut out = Vec::new();
+        response.write_to(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(text.ends_with("\r\n\r\n1\r\na\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_read_chunked_body() {
+        let raw = b"4;name=value\r\nWiki\r\n5\r\npedia\r\n0\r\nChecksum: abc\r\n\r\nNEXT";
+        let mut reader = &raw[..];
+
+        let (body, trailers) = ChunkedDecoder::new()
+            .read_from(&mut reader, &RequestLimits::default())
+            .unwrap();
+        assert_eq!(body, b"Wikipedia");
+        assert_eq!(trailers.get("checksum"), Some("abc"));
+        // 次のリクエストのバイトは消費しない
+        assert_eq!(reader, b"NEXT");
+
+        // 小さなバッファで少しずつ届いても同じ結果になる
+        let mut reader = io::BufReader::with_capacity(3, &raw[..]);
+        let (body, trailers) = ChunkedDecoder::new()
+            .read_from(&mut reader, &RequestLimits::default())
+            .unwrap();
+        assert_eq!(body, b"Wikipedia");
+        assert_eq!(trailers.get("checksum"), Some("abc"));
+        assert_eq!(io::read_to_string(reader).unwrap(), "NEXT");
+    }
+
+    #[test]
+    fn test_read_chunked_body_rejects_invalid() {
+        let cases: [&[u8]; 3] = [
+            b"zz\r\nWiki\r\n0\r\n\r\n",     // 16進数でない
+            b"4\r\nWikiXX\r\n0\r\n\r\n",     // データ後にCRLFがない
+            b"ffffffff\r\n",                   // チャンクが大きすぎる
+        ];
+        for raw in cases {
+            let mut reader = raw;
+            let limits = RequestLimits::default();
+            assert!(ChunkedDecoder::new().read_from(&mut reader, &limits).is_err());
+        }
+    }
+
+    #[test]
+    fn test_overlong_chunk_size_line_is_bad_request() {
+        let mut raw = format!("1;ext={}\r\n", "x".repeat(MAX_CHUNK_SIZE_LINE)).into_bytes();
+        raw.extend_from_slice(b"a\r\n0\r\n\r\n");
+        let mut reader = &raw[..];
+
+        let error = ChunkedDecoder::new()
+            .read_from(&mut reader, &RequestLimits::default())
+            .unwrap_err();
+        assert!(matches!(error, ParseError::InvalidChunk(_)), "{:?}", error);
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_read_line_limited() {
+        let mut reader = &b"short\r\nthis line is too long\r\n"[..];
+        let line = read_line_limited(&mut reader, 8, LimitExceeded::RequestLine).unwrap();
+        assert_eq!(line.as_deref(), Some("short"));
+
+        let err = read_line_limited(&mut reader, 8, LimitExceeded::RequestLine).unwrap_err();
+        assert!(matches!(err, ParseError::TooLarge(LimitExceeded::RequestLine)));
+        assert_eq!(err.status(), StatusCode::URI_TOO_LONG);
+    }
+
+    #[test]
+    fn test_header_count_limit() {
+        let limits = RequestLimits {
+            max_header_count: 2,
+            ..RequestLimits::default()
+        };
+        let mut reader = &b"A: 1\r\nB: 2\r\nC: 3\r\n\r\n"[..];
+        let err = read_header_fields(&mut reader, &limits).unwrap_err();
+        assert!(matches!(err, ParseError::TooLarge(LimitExceeded::HeaderCount)));
+    }
+
+    #[test]
+    fn test_parse_errors_map_to_status() {
+        let cases: [(&[u8], u16); 6] = [
+            (b"GET /\r\n\r\n", 400),
+            (b"G(T / HTTP/1.1\r\n\r\n", 400),
+            (b"TRACE / HTTP/1.1\r\n\r\n", 405),
+            (b"GET / HTTP/2.0\r\n\r\n", 505),
+            (b"GET / HTTP/1.1\r\nBad Header: x\r\n\r\n", 400),
+            (b"POST / HTTP/1.1\r\nContent-Length: 1\r\nTransfer-Encoding: chunked\r\n\r\n", 400),
+        ];
+        for (raw, status) in cases {
+            let err = HttpRequest::parse(&mut &raw[..]).unwrap_err();
+            assert!(!err.is_connection_error());
+            assert_eq!(err.status(), status, "{}", err);
+        }
+
+        let err = HttpRequest::parse(&mut &b""[..]).unwrap_err();
+        assert!(matches!(err, ParseError::ConnectionClosed));
+    }
+
+    #[test]
+    fn test_error_response_escapes_message() {
+        let err = ParseError::InvalidRequestLine("GET \"/\"".to_string());
+        let text = String::from_utf8(err.to_response().to_bytes().unwrap()).unwrap();
+        assert!(text.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(text.contains(r#""message": "Invalid request line: GET \"/\"""#));
+    }
+
+    #[test]
+    fn test_parse_from_slice() {
+        let raw = b"POST /api/users HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello";
+        let request = HttpRequest::parse(&mut &raw[..]).unwrap();
+        assert_eq!(request.method, Method::Post);
+        assert_eq!(request.path, "/api/users");
+        assert_eq!(request.body, b"hello");
+    }
+
+    #[test]
+    fn test_request_parser_byte_by_byte() {
+        let raw = b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n";
+        let mut parser = RequestParser::new();
+
+        for (i, byte) in raw.iter().enumerate() {
+            match parser.feed(std::slice::from_ref(byte)).unwrap() {
+                ParseStatus::NeedMore => assert!(i < raw.len() - 1),
+                ParseStatus::Complete(request) => {
+                    assert_eq!(i, raw.len() - 1);
+                    assert_eq!(request.body, b"abc");
+                }
+            }
+        }
+        assert!(!parser.is_partial());
+    }
+
+    #[test]
+    fn test_request_parser_consumes_chunks_as_they_arrive() {
+        let mut raw = b"POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+        for _ in 0..50 {
+            raw.extend_from_slice(b"a;ext=1\r\n0123456789\r\n");
+        }
+        raw.extend_from_slice(b"0\r\nChecksum: abc\r\n\r\nGET /next HTTP/1.1\r\n\r\n");
+        let mut parser = RequestParser::new();
+
+        let mut completed = Vec::new();
+        for byte in &raw {
+            if let ParseStatus::Complete(request) = parser.feed(std::slice::from_ref(byte)).unwrap() {
+                completed.push(request);
+            }
+            // デコード済みのデータはバッファに溜めず、未完成のヘッダー部や行だけが残る
+            assert!(parser.buffered_len() <= 64, "{} bytes buffered", parser.buffered_len());
+        }
+
+        assert_eq!(completed.len(), 2);
+        assert_eq!(completed[0].body, b"0123456789".repeat(50));
+        assert_eq!(completed[0].trailers.get("checksum"), Some("abc"));
+        assert_eq!(completed[1].path, "/next");
+        assert!(!parser.is_partial());
+    }
+
+    #[test]
+    fn test_request_parser_pipelined() {
+        let mut parser = RequestParser::new();
+        let raw = b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\nContent-Length: 2\r\n\r\nok";
+
+        let paths: Vec<String> = [parser.feed(raw).unwrap(), parser.feed(&[]).unwrap()]
+            .into_iter()
+            .map(|status| match status {
+                ParseStatus::Complete(request) => request.path,
+                ParseStatus::NeedMore => panic!("expected a complete request"),
+            })
+            .collect();
+        assert_eq!(paths, ["/a", "/b"]);
+        assert!(matches!(parser.feed(&[]).unwrap(), ParseStatus::NeedMore));
+        assert_eq!(parser.buffered_len(), 0);
+    }
+
+    #[test]
+    fn test_request_parser_enforces_limits_before_complete() {
+        let limits = RequestLimits {
+            max_request_line: 16,
+            ..RequestLimits::default()
+        };
+        let mut parser = RequestParser::with_limits(limits);
+        // 改行が届く前でも上限を超えた時点でエラー
+        let err = parser.feed(b"GET /a-very-long-path-without-newline").unwrap_err();
+        assert!(matches!(err, ParseError::TooLarge(LimitExceeded::RequestLine)));
+    }
+
+    #[test]
+    fn test_duplicate_headers_and_order() {
+        let raw = b"GET / HTTP/1.1\r\nX-Forwarded-For: a\r\nx-forwarded-for: b\r\n\r\n";
+        let request = HttpRequest::parse(&mut &raw[..]).unwrap();
+        assert_eq!(request.headers.get_all("X-Forwarded-For").collect::<Vec<_>>(), ["a", "b"]);
+
+        let mut response = HttpResponse::ok("{}");
+        response.headers.append("Set-Cookie", "a=1");
+        response.headers.append("Set-Cookie", "b=2");
+        let text = String::from_utf8(response.to_bytes().unwrap()).unwrap();
+        assert!(text.starts_with(
+            "HTTP/1.1 200 OK\r\nServer: RustHTTP/1.0\r\nContent-Type: application/json\r\n\
+             Content-Length: 2\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\n\r\n"
+        ));
+    }
+
+    #[test]
+    fn test_conflicting_content_lengths_rejected() {
+        let raw = b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab";
+        let err = HttpRequest::parse(&mut &raw[..]).unwrap_err();
+        assert!(matches!(err, ParseError::InvalidContentLength(_)));
     }
 }
@@ -0,0 +1,47 @@
+// src/lib.rs
+//
+// 【処理概要】
+// クレートのライブラリ部分。HTTPサーバーを構成する各モジュールを公開する。
+//
+// 【主な機能】
+// - http: HTTPプロトコルの低レベル処理（パース・レスポンス生成）
+// - body: レスポンスボディ（バイト列・ファイル・ストリーム）
+// - config: サーバーの設定（ファイル・環境変数・コマンドライン引数からの読み込みと検証）
+// - constraint: パスパラメータの制約（内部モジュール）
+// - error: ハンドラのエラーとRFC 7807の問題詳細（problem+json）
+// - event_loop: epollによるイベントループ方式のサーバー（内部モジュール。Linuxのみ）
+// - extensions: 型をキーにした値のマップ（共有状態・リクエストごとのデータ）
+// - header: 複数値・順序保持のヘッダーマップ
+// - method: HTTPメソッドの型
+// - middleware: ハンドラの前後を包むミドルウェア
+// - router: ルーティングとミドルウェア
+// - server: TCPリスナーと接続の処理
+// - shutdown: グレースフルシャットダウン（停止の要求と接続の追跡）
+// - status: HTTPステータスコードの型
+// - sys: Unixのシステムコール（poll, signal, epoll）の呼び出し（内部モジュール）
+// - thread_pool: ワーカースレッドプール（結果付きのジョブ、スコープ付きのジョブ）
+// - tree: ルート検索用の基数木（内部モジュール）
+// - url: リクエストターゲットの分割、パーセントデコード、クエリ解析
+//
+// 【実装内容】
+// main.rs（バイナリ）からは `rust_http_server::...` として利用する。
+
+pub mod body;
+pub mod config;
+mod constraint;
+pub mod error;
+#[cfg(target_os = "linux")]
+mod event_loop;
+pub mod extensions;
+pub mod header;
+pub mod http;
+pub mod method;
+pub mod middleware;
+pub mod router;
+pub mod server;
+pub mod shutdown;
+pub mod status;
+mod sys;
+pub mod thread_pool;
+mod tree;
+pub mod url;
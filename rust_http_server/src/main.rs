@@ -4,136 +4,239 @@
 // エントリーポイント。HTTPサーバーの初期化とルーティング設定を行う。
 // 
 // 【主な機能】
+// - 設定の読み込み（設定ファイル・環境変数・コマンドライン引数）
 // - サーバーインスタンスの生成
 // - エンドポイント（ルート）の登録
 // - ミドルウェア（ロギング、認証風処理）の設定
 // - サーバーの起動とリクエスト受付
 //
 // 【実装内容】
-// 1. ルーターを作成し、各URLパスにハンドラ関数を紐付け
-// 2. グローバルミドルウェア（全リクエストで実行）を追加
-// 3. サーバーを指定ポートでリッスン開始
-// 4. 各リクエストをワーカースレッドプールで並行処理
+// 1. 設定を読み込み、不正な値があれば起動前に終了
+// 2. ルーターを作成し、各URLパスにハンドラ関数を紐付け
+// 3. グローバルミドルウェア（全リクエストで実行）と /api グループのミドルウェアを追加
+// 4. サーバーを設定されたアドレスでリッスン開始
+// 5. 各リクエストをワーカースレッドプールで並行処理
+//    （--mode event-loop ではepollで接続を待ち、パース済みのリクエストだけをプールに渡す）
+
+use rust_http_server::config::ServerConfig;
+use rust_http_server::error::{BoxError, Problem};
+use rust_http_server::http::json_escape;
+use rust_http_server::middleware::Next;
+use rust_http_server::router::{Request, Response, Router};
+use rust_http_server::status::StatusCode;
+use rust_http_server::server::{Server, ServerMetrics};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// アプリケーションの共有状態（全てのワーカースレッドから参照される）
+struct AppState {
+    started_at: Instant,
+    requests: AtomicU64, // 処理したリクエスト数（ロギングミドルウェアで数える）
+    metrics: Arc<ServerMetrics>, // サーバーの統計情報（panicの回数など）
+    users: Mutex<Vec<User>>,
+}
+
+/// ユーザー
+struct User {
+    id: u32,
+    name: String,
+    role: &'static str,
+}
 
-mod server;
-mod router;
-mod http;
+impl User {
+    fn to_json(&self) -> String {
+        format!(
+            r#"{{"id": {}, "name": "{}", "role": "{}"}}"#,
+            self.id,
+            json_escape(&self.name),
+            self.role
+        )
+    }
+}
 
-use server::Server;
-use router::{Router, Request, Response, Middleware, MiddlewareResult};
-use std::collections::HashMap;
+/// 認証済みのユーザー名（auth_middlewareがリクエストの拡張領域に入れる）
+struct AuthUser(String);
 
 fn main() {
     println!("=== Rust HTTP Server (標準ライブラリのみ実装) ===\n");
 
-    // ルーターの初期化
-    let mut router = Router::new();
+    // 設定の読み込み（例: --workers 8 --mode event-loop --config server.conf / HTTP_SERVER_IDLE_TIMEOUT=15s）
+    let config = match ServerConfig::load(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("❌ {}\n\n{}", e, ServerConfig::usage());
+            std::process::exit(2);
+        }
+    };
+
+    // ルーターの初期化（共有状態を登録。統計情報はサーバーと共有する）
+    let metrics = Arc::new(ServerMetrics::new());
+    let state = AppState {
+        started_at: Instant::now(),
+        requests: AtomicU64::new(0),
+        metrics: Arc::clone(&metrics),
+        users: Mutex::new(vec![
+            User { id: 1, name: "Alice".to_string(), role: "admin" },
+            User { id: 2, name: "Bob".to_string(), role: "user" },
+            User { id: 3, name: "Charlie".to_string(), role: "user" },
+        ]),
+    };
+    let mut router = Router::new().with_state(state);
 
     // ===== ミドルウェアの登録 =====
     
     // ロギングミドルウェア: 全リクエストのログを出力
     router.use_middleware(logging_middleware);
-    
-    // 認証風ミドルウェア: Authorizationヘッダーのチェック（デモ）
-    router.use_middleware(auth_middleware);
 
     // ===== ルート（エンドポイント）の登録 =====
     
     // GET / - ルートパス
-    router.get("/", Box::new(|_req| {
-        Response::ok(r#"{"message": "Welcome to Rust HTTP Server!", "version": "1.0"}"#)
-    }));
-
-    // GET /api/users - ユーザー一覧取得
-    router.get("/api/users", Box::new(|_req| {
-        let users = r#"{"users": [
-            {"id": 1, "name": "Alice", "role": "admin"},
-            {"id": 2, "name": "Bob", "role": "user"},
-            {"id": 3, "name": "Charlie", "role": "user"}
-        ]}"#;
-        Response::ok(users)
-    }));
-
-    // GET /api/users/:id - 特定ユーザー取得（パスパラメータ）
-    router.get("/api/users/:id", Box::new(|req| {
-        if let Some(id) = req.params.get("id") {
-            let user = format!(
-                r#"{{"id": {}, "name": "User {}", "email": "user{}@example.com"}}"#,
-                id, id, id
-            );
-            Response::ok(&user)
-        } else {
-            Response::bad_request(r#"{"error": "User ID is required"}"#)
-        }
-    }));
-
-    // POST /api/users - ユーザー作成（ボディ解析デモ）
-    router.post("/api/users", Box::new(|req| {
-        let body = String::from_utf8_lossy(&req.body);
-        let response = format!(
-            r#"{{"message": "User created", "received_data": {}}}"#,
-            body
-        );
-        Response::created(&response)
-    }));
-
-    // GET /api/stats - サーバー統計情報
-    router.get("/api/stats", Box::new(|_req| {
-        let stats = r#"{"uptime": "unknown", "requests": "many", "threads": 4}"#;
-        Response::ok(stats)
-    }));
-
-    // 404ハンドラー
-    router.not_found(Box::new(|req| {
-        let error = format!(
-            r#"{{"error": "Not Found", "path": "{}"}}"#,
-            req.path
-        );
-        Response::not_found(&error)
-    }));
+    router
+        .get("/", |_req| {
+            Response::ok(r#"{"message": "Welcome to Rust HTTP Server!", "version": "1.0"}"#)
+        })
+        .meta("summary", "ウェルカムメッセージ");
+
+    // /api 以下のルート（APIモジュール）
+    router.group("/api", api_routes);
+
+    // 404ハンドラー（API全体で同じproblem+json形式にする）
+    router.not_found(|req| {
+        Problem::new(StatusCode::NOT_FOUND)
+            .with_detail("No route matches the requested path")
+            .with_instance(&req.path)
+    });
 
     // ===== サーバー起動 =====
-    let addr = "127.0.0.1:8080";
-    println!("🚀 Server starting on http://{}", addr);
+    println!("🚀 Server starting on http://{}", config.bind.join(", http://"));
     println!("📡 Available endpoints:");
-    println!("   GET  /");
-    println!("   GET  /api/users");
-    println!("   GET  /api/users/:id");
-    println!("   POST /api/users");
-    println!("   GET  /api/stats");
-    println!("\n💡 Try: curl http://localhost:8080/api/users\n");
-
-    let server = Server::new(addr, router);
+    for route in router.routes() {
+        let method = route.method.as_ref().map_or("ANY", |method| method.as_str());
+        let summary = route.meta("summary").unwrap_or("");
+        println!("   {:<4} {:<20} {}", method, route.pattern, summary);
+    }
+    println!("\n💡 Try: curl http://{}/api/users\n", config.bind[0]);
+
+    let mut server = Server::with_config(config, router);
+    server.metrics(metrics);
+    // Ctrl+C / SIGTERMで受付を止め、処理中のリクエストを待ってから終了
+    server.shutdown_on_signals();
     
-    // サーバー起動（ブロッキング）
+    // サーバー起動（ブロッキング。シャットダウンが完了すると戻る）
     if let Err(e) = server.run() {
         eprintln!("❌ Server error: {}", e);
     }
 }
 
+// ===== APIルート =====
+
+/// /api 以下のルートを登録
+fn api_routes(api: &mut Router) {
+    // 認証風ミドルウェア: Authorizationヘッダーのチェック（デモ）
+    api.use_middleware(auth_middleware);
+
+    // GET /api/users - ユーザー一覧取得
+    api.get("/users", |req| -> Result<Response, BoxError> {
+        let users = req.state::<AppState>()?.users.lock().unwrap();
+        let items: Vec<String> = users.iter().map(User::to_json).collect();
+        Ok(Response::ok(&format!(r#"{{"users": [{}]}}"#, items.join(", "))))
+    })
+    .meta("summary", "ユーザー一覧");
+
+    // GET /api/users/:id - 特定ユーザー取得（パスパラメータ。数値でなければ400）
+    api.get("/users/:id", |req| -> Result<Response, BoxError> {
+        let id: u32 = req.param("id")?;
+        let users = req.state::<AppState>()?.users.lock().unwrap();
+        let user = users.iter().find(|user| user.id == id).ok_or_else(|| {
+            Problem::new(StatusCode::NOT_FOUND).with_detail(&format!("User {} not found", id))
+        })?;
+        Ok(Response::ok(&user.to_json()))
+    })
+    .meta("summary", "ユーザー取得");
+
+    // POST /api/users - ユーザー作成（ボディのテキストを名前とする）
+    api.post("/users", |req| -> Result<Response, BoxError> {
+        let name = String::from_utf8_lossy(&req.body).trim().to_string();
+        if name.is_empty() {
+            let problem = Problem::new(StatusCode::BAD_REQUEST).with_detail("Name is required");
+            return Err(problem.into());
+        }
+
+        let mut users = req.state::<AppState>()?.users.lock().unwrap();
+        let id = users.iter().map(|user| user.id).max().unwrap_or(0) + 1;
+        let user = User { id, name, role: "user" };
+        let response = format!(r#"{{"message": "User created", "user": {}}}"#, user.to_json());
+        users.push(user);
+        Ok(Response::created(&response))
+    })
+    .meta("summary", "ユーザー作成");
+
+    // GET /api/stats - サーバー統計情報
+    api.get("/stats", |req| -> Result<Response, BoxError> {
+        let state = req.state::<AppState>()?;
+        let stats = format!(
+            r#"{{"uptime_secs": {}, "requests": {}, "users": {}, "panics": {}, "threads": {}, "busy_threads": {}, "queue_depth": {}, "rejected": {}}}"#,
+            state.started_at.elapsed().as_secs(),
+            state.requests.load(Ordering::Relaxed),
+            state.users.lock().unwrap().len(),
+            state.metrics.panics(),
+            state.metrics.workers(),
+            state.metrics.busy_workers(),
+            state.metrics.queue_depth(),
+            state.metrics.rejected_connections()
+        );
+        Ok(Response::ok(&stats))
+    })
+    .meta("summary", "サーバー統計情報");
+
+    // GET /api/me - 認証済みのユーザー（auth_middlewareが設定した拡張領域から取得）
+    api.get("/me", |req| match req.extensions.get::<AuthUser>() {
+        Some(user) => Response::ok(&format!(r#"{{"name": "{}"}}"#, json_escape(&user.0))),
+        None => Problem::new(StatusCode::UNAUTHORIZED).to_response(),
+    })
+    .meta("summary", "認証済みのユーザー");
+}
+
 // ===== ミドルウェア実装 =====
 
 /// ロギングミドルウェア
-/// 全リクエストのメソッドとパスをコンソールに出力
-fn logging_middleware(req: &Request, _res: &mut Response) -> MiddlewareResult {
-    println!("📝 {} {}", req.method, req.path);
-    MiddlewareResult::Continue
+/// 全リクエストのメソッド・パスと、マッチしたルート・ステータス・処理時間をコンソールに出力
+fn logging_middleware(req: &mut Request, next: Next) -> Response {
+    let start = Instant::now();
+    if let Ok(state) = req.state::<AppState>() {
+        state.requests.fetch_add(1, Ordering::Relaxed);
+    }
+    let res = next.run(req);
+
+    // next.runの後はマッチしたルートを参照できる（404・405ではNone）
+    let route = req.route.as_ref().map_or("-", |route| route.pattern.as_str());
+    println!(
+        "📝 {} {} [{}] -> {} ({:?})",
+        req.method,
+        req.path,
+        route,
+        res.status.as_u16(),
+        start.elapsed()
+    );
+    res
 }
 
-/// 認証風ミドルウェア
+/// 認証風ミドルウェア（/api グループのルートにのみ適用）
 /// Authorizationヘッダーをチェック（デモ用、簡易実装）
+/// "Bearer <名前>" ならその名前を認証済みのユーザーとしてハンドラに渡す。
 /// ヘッダーがない場合は警告を出すが、処理は続行
-fn auth_middleware(req: &Request, res: &mut Response) -> MiddlewareResult {
-    // /api/ で始まるパスのみ認証チェック
-    if req.path.starts_with("/api/") {
-        if let Some(auth) = req.headers.get("authorization") {
-            println!("🔐 Auth header found: {}", auth);
-        } else {
-            println!("⚠️  No authorization header (continuing anyway for demo)");
-            // 本番環境では、ここで401を返すべき
-            // *res = Response::unauthorized(r#"{"error": "Unauthorized"}"#);
-            // return MiddlewareResult::Stop;
+fn auth_middleware(req: &mut Request, next: Next) -> Response {
+    if let Some(auth) = req.headers.get("authorization") {
+        println!("🔐 Auth header found: {}", auth);
+        if let Some(name) = auth.strip_prefix("Bearer ") {
+            let user = AuthUser(name.to_string());
+            req.extensions.insert(user);
         }
+    } else {
+        println!("⚠️  No authorization header (continuing anyway for demo)");
+        // 本番環境では、ここで401を返すべき
+        // return Response::unauthorized(r#"{"error": "Unauthorized"}"#);
     }
-    MiddlewareResult::Continue
+    next.run(req)
 }
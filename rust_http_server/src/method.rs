@@ -0,0 +1,198 @@
+// src/method.rs
+//
+// 【処理概要】
+// HTTPメソッドを表す型を実装。
+// 文字列比較によるメソッド判定（"Get" などのタイプミス）を型で防ぐ。
+//
+// 【主な機能】
+// - IANA HTTP Method Registry に登録された全メソッドの列挙
+// - 未登録の拡張メソッド（トークン文字のみ）のサポート
+// - 安全（safe）・冪等（idempotent）の分類
+// - 文字列との相互変換（FromStr / Display）
+//
+// 【実装内容】
+// 1. methods! マクロで列挙子・メソッド名・分類を一括定義
+// 2. パース時は登録メソッドを優先し、それ以外はExtensionとして保持
+//    （メソッド名は大文字小文字を区別する: "get" は GET ではない）
+
+use std::fmt;
+use std::str::FromStr;
+
+/// 登録メソッドの定義から Method 列挙型を生成するマクロ
+///
+/// 各行: 列挙子 => "メソッド名", safe, idempotent;
+macro_rules! methods {
+    ($($variant:ident => $name:literal, $safe:literal, $idempotent:literal;)*) => {
+        /// HTTPメソッド
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum Method {
+            $($variant,)*
+            /// 未登録の拡張メソッド
+            Extension(String),
+        }
+
+        impl Method {
+            /// IANAに登録されている全メソッド
+            pub const REGISTERED: &'static [Method] = &[$(Method::$variant,)*];
+
+            /// メソッド名
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(Method::$variant => $name,)*
+                    Method::Extension(name) => name,
+                }
+            }
+
+            /// 安全なメソッド（サーバーの状態を変更しない）か
+            pub fn is_safe(&self) -> bool {
+                match self {
+                    $(Method::$variant => $safe,)*
+                    Method::Extension(_) => false,
+                }
+            }
+
+            /// 冪等なメソッド（同じリクエストを繰り返しても結果が同じ）か
+            pub fn is_idempotent(&self) -> bool {
+                match self {
+                    $(Method::$variant => $idempotent,)*
+                    Method::Extension(_) => false,
+                }
+            }
+
+            /// 登録メソッド名から列挙子を取得
+            fn from_registered(name: &str) -> Option<Method> {
+                match name {
+                    $($name => Some(Method::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+// IANA HTTP Method Registry
+// https://www.iana.org/assignments/http-methods/
+methods! {
+    Acl => "ACL", false, true;
+    BaselineControl => "BASELINE-CONTROL", false, true;
+    Bind => "BIND", false, true;
+    Checkin => "CHECKIN", false, true;
+    Checkout => "CHECKOUT", false, true;
+    Connect => "CONNECT", false, false;
+    Copy => "COPY", false, true;
+    Delete => "DELETE", false, true;
+    Get => "GET", true, true;
+    Head => "HEAD", true, true;
+    Label => "LABEL", false, true;
+    Link => "LINK", false, true;
+    Lock => "LOCK", false, false;
+    Merge => "MERGE", false, true;
+    MkActivity => "MKACTIVITY", false, true;
+    MkCalendar => "MKCALENDAR", false, true;
+    MkCol => "MKCOL", false, true;
+    MkRedirectRef => "MKREDIRECTREF", false, true;
+    MkWorkspace => "MKWORKSPACE", false, true;
+    Move => "MOVE", false, true;
+    Options => "OPTIONS", true, true;
+    OrderPatch => "ORDERPATCH", false, true;
+    Patch => "PATCH", false, false;
+    Post => "POST", false, false;
+    Pri => "PRI", true, true;
+    PropFind => "PROPFIND", true, true;
+    PropPatch => "PROPPATCH", false, true;
+    Put => "PUT", false, true;
+    Rebind => "REBIND", false, true;
+    Report => "REPORT", true, true;
+    Search => "SEARCH", true, true;
+    Trace => "TRACE", true, true;
+    Unbind => "UNBIND", false, true;
+    Uncheckout => "UNCHECKOUT", false, true;
+    Unlink => "UNLINK", false, true;
+    Unlock => "UNLOCK", false, true;
+    Update => "UPDATE", false, true;
+    UpdateRedirectRef => "UPDATEREDIRECTREF", false, true;
+    VersionControl => "VERSION-CONTROL", false, true;
+}
+
+/// メソッド名として不正な文字列
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidMethod(pub String);
+
+impl fmt::Display for InvalidMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid method: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidMethod {}
+
+impl FromStr for Method {
+    type Err = InvalidMethod;
+
+    /// メソッド名をパースする
+    ///
+    /// 登録メソッドは対応する列挙子に、それ以外のトークンは Extension になる。
+    /// 空文字列やトークン以外の文字を含む場合はエラー。
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if let Some(method) = Method::from_registered(name) {
+            return Ok(method);
+        }
+        if name.is_empty() || !name.bytes().all(is_token_char) {
+            return Err(InvalidMethod(name.to_string()));
+        }
+        Ok(Method::Extension(name.to_string()))
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PartialEq<str> for Method {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Method {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// RFC 9110のtoken文字か（メソッド名・ヘッダー名に使用）
+pub(crate) fn is_token_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_registered_and_extension() {
+        assert_eq!("GET".parse::<Method>(), Ok(Method::Get));
+        assert_eq!("VERSION-CONTROL".parse::<Method>(), Ok(Method::VersionControl));
+        // メソッド名は大文字小文字を区別する
+        assert_eq!("Get".parse::<Method>(), Ok(Method::Extension("Get".to_string())));
+        assert!("G(T".parse::<Method>().is_err());
+        assert!("".parse::<Method>().is_err());
+    }
+
+    #[test]
+    fn test_round_trip_all_registered() {
+        for method in Method::REGISTERED {
+            assert_eq!(method.as_str().parse::<Method>().as_ref(), Ok(method));
+        }
+    }
+
+    #[test]
+    fn test_classification() {
+        assert!(Method::Get.is_safe() && Method::Get.is_idempotent());
+        assert!(!Method::Put.is_safe() && Method::Put.is_idempotent());
+        assert!(!Method::Post.is_safe() && !Method::Post.is_idempotent());
+        assert!(!Method::Extension("PURGE".to_string()).is_idempotent());
+    }
+}
@@ -0,0 +1,206 @@
+// src/middleware.rs
+//
+// 【処理概要】
+// ミドルウェアの仕組みを実装。
+// ミドルウェアは「次の処理（next）」を包み込む形で実行され、
+// ハンドラの前後に任意の処理を挟める（around型）。
+//
+// 【主な機能】
+// - Middlewareトレイト（関数・構造体のどちらでも実装できる）
+// - 状態を持つクロージャをミドルウェアにするfrom_fn
+// - 前処理（リクエストの書き換え）・後処理（レスポンスの書き換え、処理時間の計測）
+// - nextを呼ばずにレスポンスを返す短絡（例: 認証エラー）
+//
+// 【実装内容】
+// 1. Nextは残りのミドルウェアの列と最終的な処理（ルーティング/ハンドラ）を持つ
+// 2. Next::runで先頭のミドルウェアを呼び、そのミドルウェアに残りを渡す
+//
+// 使用例:
+//   fn timing(req: &mut Request, next: Next) -> Response {
+//       let start = Instant::now();
+//       let mut res = next.run(req);
+//       res.headers.insert("X-Response-Time", format!("{:?}", start.elapsed()));
+//       res
+//   }
+//   router.use_middleware(timing);
+
+use crate::router::{Request, Response};
+use std::sync::Arc;
+
+/// ミドルウェア
+///
+/// nextを呼ぶと後続のミドルウェアとハンドラが実行され、そのレスポンスが返る。
+/// nextを呼ばずに自分でレスポンスを返すと、そこで処理を打ち切る。
+///
+/// `fn(&mut Request, Next) -> Response` の関数はそのままミドルウェアになる。
+/// 状態を持たせる場合は構造体に実装するか、from_fnでクロージャを包む。
+pub trait Middleware: Send + Sync + 'static {
+    fn handle(&self, req: &mut Request, next: Next<'_>) -> Response;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&mut Request, Next<'_>) -> Response + Send + Sync + 'static,
+{
+    fn handle(&self, req: &mut Request, next: Next<'_>) -> Response {
+        self(req, next)
+    }
+}
+
+/// クロージャのミドルウェア（from_fnで作成）
+pub struct FromFn<F>(F);
+
+impl<F> Middleware for FromFn<F>
+where
+    F: Fn(&mut Request, Next<'_>) -> Response + Send + Sync + 'static,
+{
+    fn handle(&self, req: &mut Request, next: Next<'_>) -> Response {
+        (self.0)(req, next)
+    }
+}
+
+/// クロージャをミドルウェアにする（引数の型を推論させるためのヘルパー）
+///
+/// 例:
+///   let count = Arc::new(AtomicUsize::new(0));
+///   router.use_middleware(from_fn(move |req, next| {
+///       count.fetch_add(1, Ordering::Relaxed);
+///       next.run(req)
+///   }));
+pub fn from_fn<F>(f: F) -> FromFn<F>
+where
+    F: Fn(&mut Request, Next<'_>) -> Response + Send + Sync + 'static,
+{
+    FromFn(f)
+}
+
+/// ミドルウェアの後続処理
+pub struct Next<'a> {
+    middlewares: &'a [Arc<dyn Middleware>],
+    endpoint: &'a (dyn Fn(&mut Request) -> Response + Send + Sync),
+}
+
+impl<'a> Next<'a> {
+    /// ミドルウェアの列と、その後に実行する処理から作成
+    pub(crate) fn new(
+        middlewares: &'a [Arc<dyn Middleware>],
+        endpoint: &'a (dyn Fn(&mut Request) -> Response + Send + Sync),
+    ) -> Self {
+        Next {
+            middlewares,
+            endpoint,
+        }
+    }
+
+    /// 後続のミドルウェアとハンドラを実行してレスポンスを受け取る
+    pub fn run(self, req: &mut Request) -> Response {
+        match self.middlewares.split_first() {
+            Some((first, rest)) => first.handle(req, Next::new(rest, self.endpoint)),
+            None => (self.endpoint)(req),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::HttpRequest;
+    use crate::router::Router;
+    use crate::status::StatusCode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    fn get(router: &Router, target: &str) -> Response {
+        let raw = format!("GET {} HTTP/1.1\r\n\r\n", target);
+        router.handle(HttpRequest::parse(&mut raw.as_bytes()).unwrap())
+    }
+
+    /// 呼び出し順を記録する構造体のミドルウェア
+    struct Trace {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Middleware for Trace {
+        fn handle(&self, req: &mut Request, next: Next<'_>) -> Response {
+            self.log.lock().unwrap().push(format!("{} before", self.name));
+            let res = next.run(req);
+            self.log.lock().unwrap().push(format!("{} after", self.name));
+            res
+        }
+    }
+
+    #[test]
+    fn test_around_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut router = Router::new();
+        for name in ["outer", "inner"] {
+            router.use_middleware(Trace {
+                name,
+                log: Arc::clone(&log),
+            });
+        }
+        let handler_log = Arc::clone(&log);
+        router.get("/", move |_req| {
+            handler_log.lock().unwrap().push("handler".to_string());
+            Response::ok("")
+        });
+
+        get(&router, "/");
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["outer before", "inner before", "handler", "inner after", "outer after"]
+        );
+    }
+
+    #[test]
+    fn test_stateful_closure_mutates_request_and_response() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&count);
+
+        let mut router = Router::new();
+        router.use_middleware(from_fn(move |req, next| {
+            let n = counter.fetch_add(1, Ordering::Relaxed) + 1;
+            req.headers.insert("X-Request-Number", n.to_string());
+            let mut res = next.run(req);
+            res.headers.insert("X-Served-By", "middleware");
+            res
+        }));
+        router.get("/", |req| Response::ok(req.headers.get("x-request-number").unwrap()));
+
+        get(&router, "/");
+        let res = get(&router, "/");
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+        assert_eq!(res.body.as_bytes(), Some(&b"2"[..]));
+        assert_eq!(res.headers.get("x-served-by"), Some("middleware"));
+
+        // ルートがなくても（404でも）ミドルウェアは実行される
+        let res = get(&router, "/missing");
+        assert_eq!(res.status, StatusCode::NOT_FOUND);
+        assert_eq!(res.headers.get("x-served-by"), Some("middleware"));
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_short_circuit_skips_handler() {
+        let called = Arc::new(AtomicUsize::new(0));
+        let handler_called = Arc::clone(&called);
+
+        let mut router = Router::new();
+        router.use_middleware(|req: &mut Request, next: Next| {
+            if req.headers.contains_key("authorization") {
+                next.run(req)
+            } else {
+                Response::unauthorized(r#"{"error": "Unauthorized"}"#)
+            }
+        });
+        router.get("/", move |_req| {
+            handler_called.fetch_add(1, Ordering::Relaxed);
+            Response::ok("")
+        });
+
+        let res = get(&router, "/");
+        assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(called.load(Ordering::Relaxed), 0);
+    }
+}
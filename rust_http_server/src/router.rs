@@ -6,231 +6,1414 @@
 //
 // 【主な機能】
 // - URLパスとハンドラ関数のマッピング
-// - パスパラメータの抽出（例: /users/:id）
+// - クエリ文字列の解析とパスのパーセントデコード
+// - パスパラメータの抽出（例: /users/:id, /files/:name.:ext, /static/*filepath）
+// - パス・クエリ・ヘッダーの型付き取得（不正な値は400レスポンス）
+// - Resultを返すハンドラと、エラーからレスポンスへの変換（既定はRFC 7807のproblem+json）
+// - パラメータの制約（例: /users/:id<int>）と省略可能な末尾パラメータ（例: /posts/:page?）
 // - ミドルウェアチェーンの実行（前処理・後処理）
-// - HTTPメソッド別のルーティング（GET, POST等）
+// - 接頭辞とミドルウェアを共有するルートグループ、サブルーターのマウント
+// - ルート単位のミドルウェアとメタデータ（マッチ後に実行され、パラメータやパターンを参照できる）
+// - アプリケーションの共有状態（Router::with_state）とリクエストごとの拡張領域（Request::extensions）
+// - 基数木によるルート検索（静的セグメントをパラメータより優先）
+// - HTTPメソッド別のルーティング（GET, POST, PUT, PATCH, DELETE等）
+// - HEAD（GETルートから自動）・OPTIONS（Allowヘッダー。OPTIONS * を含む）への自動応答と405
 //
 // 【実装内容】
-// 1. ルート登録（静的パス、動的パラメータ対応）
-// 2. リクエストマッチング（正規表現ベース）
-// 3. ミドルウェアの順次実行（Continue/Stop制御）
+// 1. ルート登録（メソッドごとの基数木。重複・曖昧なルートは登録時に検出）
+// 2. リクエストマッチング（静的セグメント優先、パスの長さに比例する時間）
+// 3. ミドルウェアの実行（nextで後続を呼ぶaround型。呼ばなければ短絡）
 // 4. ハンドラ実行とレスポンス生成
 
+use crate::error::{default_error_response, BoxError, Problem};
+use crate::extensions::Extensions;
+use crate::header::HeaderMap;
 use crate::http::{HttpRequest, HttpResponse};
-use std::collections::HashMap;
+use crate::method::Method;
+use crate::middleware::{Middleware, Next};
+use crate::status::StatusCode;
+use crate::tree::Node;
+use crate::url::{percent_decode, split_target, QueryMap};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
 
 /// リクエスト情報（ハンドラに渡される）
 #[derive(Debug, Clone)]
 pub struct Request {
-    pub method: String,
-    pub path: String,
-    pub headers: HashMap<String, String>,
+    pub method: Method,
+    pub path: String, // パーセントデコード済みのパス（クエリ文字列を含まない）
+    pub query: QueryMap, // クエリパラメータ（例: ?page=2 -> {"page" => ["2"]}）
+    pub headers: HeaderMap,
     pub body: Vec<u8>,
     pub params: HashMap<String, String>, // パスパラメータ（例: {:id => "123"}）
+    pub route: Option<Arc<RouteInfo>>,   // マッチしたルート（マッチ前・404・405ではNone）
+    /// ミドルウェアからハンドラへ渡すデータ（例: 認証済みのユーザー）
+    pub extensions: Extensions,
+    /// ルーターの共有状態（Router::with_stateで登録したもの。stateメソッドで取得する）
+    pub state: Arc<Extensions>,
 }
 
 /// レスポンス情報（ハンドラが返す）
 pub type Response = HttpResponse;
 
-/// ハンドラ関数の型
-/// リクエストを受け取り、レスポンスを返す
-pub type Handler = Box<dyn Fn(&Request) -> Response + Send + Sync>;
+/// 登録済みのハンドラの型
+/// リクエストを受け取り、レスポンスかエラーを返す
+/// （ミドルウェアと同じく&mut Requestを受け取るが、登録するハンドラは&Requestで書く）
+/// （省略可能なパラメータでは2つのルートで共有するためArcで保持する）
+pub type Handler = Arc<dyn Fn(&mut Request) -> Result<Response, BoxError> + Send + Sync>;
 
-/// ミドルウェア関数の型
-/// リクエストとレスポンスを受け取り、処理を続けるか停止するかを返す
-pub type Middleware = fn(&Request, &mut Response) -> MiddlewareResult;
+/// ハンドラのエラーをレスポンスに変換する関数の型（Router::on_error）
+pub type ErrorHandler = Arc<dyn Fn(BoxError, &Request) -> Response + Send + Sync>;
 
-/// ミドルウェアの実行結果
-#[derive(Debug, PartialEq)]
-pub enum MiddlewareResult {
-    Continue, // 次のミドルウェア/ハンドラに進む
-    Stop,     // ここで処理を停止（レスポンスを即座に返す）
+/// レスポンスに変換できる型
+pub trait IntoResponse {
+    fn into_response(self) -> Response;
 }
 
-/// ルート情報
+impl IntoResponse for Response {
+    fn into_response(self) -> Response {
+        self
+    }
+}
+
+/// ハンドラの戻り値にできる型
+///
+/// IntoResponseを実装した型（Response, Problem等）のほか、
+/// Result<Response, E> も返せる（Eはstd::error::Errorを実装した型）。
+/// エラーはルーターのエラー変換（Router::on_error。既定はproblem+json）で
+/// レスポンスになる。
+///
+/// 例:
+///   router.get("/users/:id", |req| -> Result<Response, BoxError> {
+///       let id: u32 = req.param("id")?; // 数値でなければ400
+///       Ok(Response::ok(&format!(r#"{{"id": {}}}"#, id)))
+///   });
+pub trait HandlerOutput {
+    fn into_result(self) -> Result<Response, BoxError>;
+}
+
+impl<T: IntoResponse> HandlerOutput for T {
+    fn into_result(self) -> Result<Response, BoxError> {
+        Ok(self.into_response())
+    }
+}
+
+impl<T: IntoResponse, E: Into<BoxError>> HandlerOutput for Result<T, E> {
+    fn into_result(self) -> Result<Response, BoxError> {
+        self.map(IntoResponse::into_response).map_err(Into::into)
+    }
+}
+
+/// マッチしたルートの情報（Request::routeでミドルウェアやハンドラから参照できる）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteInfo {
+    /// ルートのメソッド（anyで登録したルートはNone）
+    pub method: Option<Method>,
+    /// 登録したパターン（マウントしたルートは接頭辞付き。例: "/api/users/:id"）
+    pub pattern: String,
+    /// 登録時に付けたメタデータ（例: "role" => "admin"）
+    pub metadata: HashMap<String, String>,
+}
+
+impl RouteInfo {
+    /// メタデータの値
+    pub fn meta(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+}
+
+/// 木に登録するルート（ハンドラと、その前に実行するミドルウェア）
+#[derive(Clone)]
 struct Route {
-    method: String,
-    pattern: String,        // 元のパターン（例: "/users/:id"）
-    param_names: Vec<String>, // パラメータ名のリスト
     handler: Handler,
+    /// グループ・ルート単位のミドルウェア（外側から順）
+    middlewares: Vec<Arc<dyn Middleware>>,
+    info: Arc<RouteInfo>,
+}
+
+impl Route {
+    /// マッチした情報をリクエストに設定し、ミドルウェアとハンドラを実行する
+    fn call(&self, router: &Router, request: &mut Request, params: HashMap<String, String>) -> Response {
+        request.params = params;
+        request.route = Some(Arc::clone(&self.info));
+        let handler = |req: &mut Request| router.run_handler(&self.handler, req);
+        Next::new(&self.middlewares, &handler).run(request)
+    }
+}
+
+/// 登録したルートにミドルウェアやメタデータを追加するビルダー
+///
+/// 例:
+///   router
+///       .delete("/users/:id", delete_user)
+///       .middleware(require_role)
+///       .meta("role", "admin");
+pub struct RouteBuilder<'a> {
+    tree: &'a mut Node<Route>,
+    pattern: String,
+}
+
+impl RouteBuilder<'_> {
+    /// このルートにだけ適用するミドルウェアを追加（登録順に外側から実行される）
+    ///
+    /// ルートのマッチング後に実行されるため、パスパラメータとRequest::routeを参照できる。
+    pub fn middleware(self, middleware: impl Middleware) -> Self {
+        let middleware: Arc<dyn Middleware> = Arc::new(middleware);
+        self.tree.for_each_value_mut(&self.pattern, |route| {
+            route.middlewares.push(Arc::clone(&middleware));
+        });
+        self
+    }
+
+    /// メタデータを設定（同じキーは上書き）
+    pub fn meta(self, key: &str, value: &str) -> Self {
+        self.tree.for_each_value_mut(&self.pattern, |route| {
+            let info = Arc::make_mut(&mut route.info);
+            info.metadata.insert(key.to_string(), value.to_string());
+        });
+        self
+    }
+}
+
+impl fmt::Debug for RouteBuilder<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RouteBuilder")
+            .field("pattern", &self.pattern)
+            .finish_non_exhaustive()
+    }
+}
+
+/// パラメータの取得元
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamSource {
+    Path,   // パスパラメータ（例: /users/:id）
+    Query,  // クエリパラメータ（例: ?page=2）
+    Header, // リクエストヘッダー
+}
+
+impl ParamSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ParamSource::Path => "path",
+            ParamSource::Query => "query",
+            ParamSource::Header => "header",
+        }
+    }
 }
 
+/// パラメータの取得・変換エラー（ハンドラから返すと400になる）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamError {
+    pub source: ParamSource,
+    pub name: String,
+    /// 変換に失敗した値（パラメータがない場合はNone）
+    pub value: Option<String>,
+    /// 変換に失敗した理由（FromStrのエラーメッセージ）
+    pub reason: Option<String>,
+}
+
+impl ParamError {
+    /// パラメータが指定されていない
+    fn missing(source: ParamSource, name: &str) -> Self {
+        ParamError {
+            source,
+            name: name.to_string(),
+            value: None,
+            reason: None,
+        }
+    }
+
+    /// 400の問題詳細（どのパラメータが不正かを拡張メンバーで示す）
+    ///
+    /// 例: {"type": "about:blank", "title": "Bad Request", "status": 400,
+    ///      "detail": "Invalid path parameter 'id' ...", "parameter": "id", "location": "path"}
+    pub fn to_problem(&self) -> Problem {
+        Problem::new(StatusCode::BAD_REQUEST)
+            .with_detail(&self.to_string())
+            .with_extension("parameter", &self.name)
+            .with_extension("location", self.source.as_str())
+    }
+
+    /// 400レスポンス（application/problem+json）
+    pub fn to_response(&self) -> Response {
+        self.to_problem().to_response()
+    }
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = self.source.as_str();
+        match (&self.value, &self.reason) {
+            (Some(value), Some(reason)) => write!(
+                f,
+                "Invalid {} parameter '{}' ({:?}): {}",
+                source, self.name, value, reason
+            ),
+            _ => write!(f, "Missing {} parameter '{}'", source, self.name),
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+impl IntoResponse for ParamError {
+    fn into_response(self) -> Response {
+        self.to_response()
+    }
+}
+
+/// 共有状態が登録されていないエラー（ハンドラから返すと500になる）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingState {
+    pub type_name: &'static str,
+}
+
+impl fmt::Display for MissingState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "State {} is not registered (use Router::with_state)", self.type_name)
+    }
+}
+
+impl std::error::Error for MissingState {}
+
+impl Request {
+    /// ルーターに登録した共有状態（Router::with_state）
+    ///
+    /// 登録されていない型を指定した場合はMissingStateを返す
+    /// （?でハンドラから返すと500になる）。
+    ///
+    /// 例: let db = req.state::<Database>()?;
+    pub fn state<T: Send + Sync + 'static>(&self) -> Result<&T, MissingState> {
+        self.try_state().ok_or(MissingState {
+            type_name: std::any::type_name::<T>(),
+        })
+    }
+
+    /// ルーターに登録した共有状態（登録されていなければNone）
+    pub fn try_state<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.state.get()
+    }
+
+    /// パスパラメータを型に変換して取得
+    ///
+    /// 例: let id: u32 = req.param("id")?;
+    pub fn param<T>(&self, name: &str) -> Result<T, ParamError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let value = self.params.get(name).map(String::as_str);
+        parse_param(ParamSource::Path, name, value)
+    }
+
+    /// クエリパラメータ（最初の値）を型に変換して取得（なければエラー）
+    pub fn query_param<T>(&self, name: &str) -> Result<T, ParamError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        parse_param(ParamSource::Query, name, self.query.get(name))
+    }
+
+    /// 省略可能なクエリパラメータを型に変換して取得（なければNone）
+    ///
+    /// 例: let page = req.query_param_opt::<u32>("page")?.unwrap_or(1);
+    pub fn query_param_opt<T>(&self, name: &str) -> Result<Option<T>, ParamError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        match self.query.get(name) {
+            Some(value) => parse_param(ParamSource::Query, name, Some(value)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// ヘッダー（最初の値）を型に変換して取得（なければエラー）
+    pub fn header<T>(&self, name: &str) -> Result<T, ParamError>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        parse_param(ParamSource::Header, name, self.headers.get(name))
+    }
+}
+
+/// マウントするルートの一覧（パターンに接頭辞を付け、ミドルウェアを外側に加える）
+///
+/// 省略可能なパラメータのルートは同じパターンで2つ登録されているため、
+/// パターンごとに1つにまとめる（挿入時に再び2つに展開される）。
+fn mounted_routes(prefix: &str, tree: &Node<Route>, middlewares: &[Arc<dyn Middleware>]) -> Vec<Route> {
+    let mut seen = HashSet::new();
+    let mut routes: Vec<Route> = tree
+        .leaves()
+        .into_iter()
+        .filter(|leaf| seen.insert(leaf.pattern.as_str()))
+        .map(|leaf| leaf.value.clone())
+        .collect();
+
+    for route in &mut routes {
+        let info = Arc::make_mut(&mut route.info);
+        info.pattern = join_prefix(prefix, &info.pattern);
+        route.middlewares.splice(0..0, middlewares.iter().cloned());
+    }
+    routes
+}
+
+/// 接頭辞とパターンを連結する
+///
+/// 例: ("/api", "/users") -> "/api/users", ("/api/", "/") -> "/api"
+fn join_prefix(prefix: &str, pattern: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    match pattern {
+        "/" if !prefix.is_empty() => prefix.to_string(),
+        _ => format!("{}{}", prefix, pattern),
+    }
+}
+
+/// ミドルウェアなしのルートを作成
+fn new_route(method: Option<Method>, pattern: &str, handler: Handler) -> Route {
+    Route {
+        handler,
+        middlewares: Vec::new(),
+        info: Arc::new(RouteInfo {
+            method,
+            pattern: pattern.to_string(),
+            metadata: HashMap::new(),
+        }),
+    }
+}
+
+/// 登録されたハンドラの戻り値をResponseに変換するようにまとめる
+fn into_handler<R: HandlerOutput>(
+    handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+) -> Handler {
+    Arc::new(move |req: &mut Request| handler(req).into_result())
+}
+
+/// パラメータの値をFromStrで変換する
+fn parse_param<T>(source: ParamSource, name: &str, value: Option<&str>) -> Result<T, ParamError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let value = value.ok_or_else(|| ParamError::missing(source, name))?;
+    value.parse().map_err(|e: T::Err| ParamError {
+        source,
+        name: name.to_string(),
+        value: Some(value.to_string()),
+        reason: Some(e.to_string()),
+    })
+}
+
+/// ルート登録のエラー（登録時に検出する）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteError {
+    /// パターンの形式が不正
+    InvalidPattern { pattern: String, reason: &'static str },
+    /// 同じ形のルートが登録済み
+    Duplicate { pattern: String, existing: String },
+    /// 同じ位置に異なる名前のパラメータがあり、どちらにマッチするか曖昧
+    Conflict { pattern: String, existing: String },
+    /// マウントするルーターと同じ型の共有状態が登録済み
+    StateConflict { type_name: &'static str },
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::InvalidPattern { pattern, reason } => {
+                write!(f, "Invalid route pattern {}: {}", pattern, reason)
+            }
+            RouteError::Duplicate { pattern, existing } => {
+                write!(f, "Route {} duplicates {}", pattern, existing)
+            }
+            RouteError::Conflict { pattern, existing } => {
+                write!(f, "Route {} conflicts with {}", pattern, existing)
+            }
+            RouteError::StateConflict { type_name } => {
+                write!(f, "State {} is registered in both routers", type_name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
+
 /// ルーター本体
+#[derive(Default)]
 pub struct Router {
-    routes: Vec<Route>,
-    middlewares: Vec<Middleware>,
+    trees: Vec<(Method, Node<Route>)>, // メソッドごとの基数木（メソッドの登録順）
+    any_tree: Node<Route>,             // 全メソッド（any）のルート
+    middlewares: Vec<Arc<dyn Middleware>>,
     not_found_handler: Option<Handler>,
+    error_handler: Option<ErrorHandler>,
+    state: Arc<Extensions>, // 全てのリクエストで共有する状態（型ごとに1つ）
 }
 
 impl Router {
     /// 新しいルーターを作成
     pub fn new() -> Self {
         Router {
-            routes: Vec::new(),
+            trees: Vec::new(),
+            any_tree: Node::default(),
             middlewares: Vec::new(),
             not_found_handler: None,
+            error_handler: None,
+            state: Arc::default(),
         }
     }
 
-    /// GETルートを登録
-    pub fn get(&mut self, pattern: &str, handler: Handler) {
-        self.add_route("GET", pattern, handler);
+    /// 全てのハンドラとミドルウェアで共有する状態を登録（Request::stateで取得する）
+    ///
+    /// 型ごとに1つ登録でき、同じ型を登録すると置き換わる。
+    /// 状態は全てのワーカースレッドで共有されるため、書き換える値は
+    /// MutexやAtomic型で包む。
+    ///
+    /// 例:
+    ///   struct AppState { hits: AtomicU64 }
+    ///   let mut router = Router::new().with_state(AppState { hits: AtomicU64::new(0) });
+    ///   router.get("/hits", |req| -> Result<Response, BoxError> {
+    ///       let hits = req.state::<AppState>()?.hits.fetch_add(1, Ordering::Relaxed);
+    ///       Ok(Response::ok(&hits.to_string()))
+    ///   });
+    pub fn with_state<T: Send + Sync + 'static>(mut self, state: T) -> Self {
+        Arc::make_mut(&mut self.state).insert(state);
+        self
+    }
+
+    /// GETルートを登録（HEADリクエストにも自動で応答する）
+    pub fn get<R: HandlerOutput>(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+    ) -> RouteBuilder<'_> {
+        self.route(Method::Get, pattern, handler)
     }
 
     /// POSTルートを登録
-    pub fn post(&mut self, pattern: &str, handler: Handler) {
-        self.add_route("POST", pattern, handler);
+    pub fn post<R: HandlerOutput>(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+    ) -> RouteBuilder<'_> {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    /// PUTルートを登録
+    pub fn put<R: HandlerOutput>(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+    ) -> RouteBuilder<'_> {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    /// PATCHルートを登録
+    pub fn patch<R: HandlerOutput>(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+    ) -> RouteBuilder<'_> {
+        self.route(Method::Patch, pattern, handler)
+    }
+
+    /// DELETEルートを登録
+    pub fn delete<R: HandlerOutput>(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+    ) -> RouteBuilder<'_> {
+        self.route(Method::Delete, pattern, handler)
     }
 
-    /// 任意のメソッドでルートを登録
-    fn add_route(&mut self, method: &str, pattern: &str, handler: Handler) {
-        let param_names = extract_param_names(pattern);
-        
-        self.routes.push(Route {
-            method: method.to_string(),
+    /// HEADルートを登録（GETルートからの自動応答より優先される）
+    pub fn head<R: HandlerOutput>(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+    ) -> RouteBuilder<'_> {
+        self.route(Method::Head, pattern, handler)
+    }
+
+    /// OPTIONSルートを登録（自動応答より優先される）
+    pub fn options<R: HandlerOutput>(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+    ) -> RouteBuilder<'_> {
+        self.route(Method::Options, pattern, handler)
+    }
+
+    /// 全てのメソッドに応答するルートを登録
+    ///
+    /// メソッドを指定したルートがあればそちらが優先される。
+    pub fn any<R: HandlerOutput>(
+        &mut self,
+        pattern: &str,
+        handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+    ) -> RouteBuilder<'_> {
+        let route = new_route(None, pattern, into_handler(handler));
+        match self.any_tree.insert(pattern, route) {
+            Ok(()) => RouteBuilder {
+                tree: &mut self.any_tree,
+                pattern: pattern.to_string(),
+            },
+            Err(e) => panic!("ANY {}", e),
+        }
+    }
+
+    /// 任意のメソッドでルートを登録（拡張メソッドも可）
+    ///
+    /// 重複・曖昧なルートは設定ミスとしてpanicする。
+    /// エラーとして扱いたい場合はtry_routeを使う。
+    pub fn route<R: HandlerOutput>(
+        &mut self,
+        method: Method,
+        pattern: &str,
+        handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+    ) -> RouteBuilder<'_> {
+        let name = method.to_string();
+        match self.try_route(method, pattern, handler) {
+            Ok(builder) => builder,
+            Err(e) => panic!("{} {}", name, e),
+        }
+    }
+
+    /// 任意のメソッドでルートを登録し、重複・曖昧なルートはエラーを返す
+    pub fn try_route<R: HandlerOutput>(
+        &mut self,
+        method: Method,
+        pattern: &str,
+        handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+    ) -> Result<RouteBuilder<'_>, RouteError> {
+        let index = self.tree_index(method.clone());
+        let route = new_route(Some(method), pattern, into_handler(handler));
+        let tree = &mut self.trees[index].1;
+        tree.insert(pattern, route)?;
+        Ok(RouteBuilder {
+            tree,
             pattern: pattern.to_string(),
-            param_names,
-            handler,
-        });
+        })
     }
 
-    /// ミドルウェアを追加（登録順に実行される）
-    pub fn use_middleware(&mut self, middleware: Middleware) {
-        self.middlewares.push(middleware);
+    /// 指定したメソッドの木の位置（なければ作成）
+    fn tree_index(&mut self, method: Method) -> usize {
+        match self.trees.iter().position(|(m, _)| *m == method) {
+            Some(index) => index,
+            None => {
+                self.trees.push((method, Node::default()));
+                self.trees.len() - 1
+            }
+        }
+    }
+
+    /// ミドルウェアを追加（登録順に外側から実行される）
+    ///
+    /// ルーティングの前に全てのリクエストに対して実行され、404や405のレスポンスも受け取る
+    /// （Request::routeはnext.runの後でのみ参照できる）。
+    /// グループやマウントされたルーターのミドルウェアは、そのルートにマッチした場合のみ
+    /// マッチング後に実行される（ルート単位のミドルウェアはRouteBuilder::middleware）。
+    pub fn use_middleware(&mut self, middleware: impl Middleware) {
+        self.middlewares.push(Arc::new(middleware));
     }
 
     /// 404ハンドラーを設定
-    pub fn not_found(&mut self, handler: Handler) {
-        self.not_found_handler = Some(handler);
+    pub fn not_found<R: HandlerOutput>(
+        &mut self,
+        handler: impl Fn(&Request) -> R + Send + Sync + 'static,
+    ) {
+        self.not_found_handler = Some(into_handler(handler));
+    }
+
+    /// ハンドラが返したエラーをレスポンスに変換する関数を設定
+    ///
+    /// 設定しない場合はerror::default_error_response（problem+json）が使われる。
+    /// 扱わないエラーはdefault_error_responseに任せられる。
+    ///
+    /// 例:
+    ///   router.on_error(|error, req| match error.downcast_ref::<DbError>() {
+    ///       Some(_) => Problem::new(StatusCode::SERVICE_UNAVAILABLE).to_response(),
+    ///       None => default_error_response(&error),
+    ///   });
+    pub fn on_error<R: IntoResponse>(
+        &mut self,
+        handler: impl Fn(BoxError, &Request) -> R + Send + Sync + 'static,
+    ) {
+        self.error_handler = Some(Arc::new(move |error, req| handler(error, req).into_response()));
+    }
+
+    /// 共通の接頭辞を持つルートのグループを登録
+    ///
+    /// buildには新しいルーターが渡され、そこに登録したルートとミドルウェアが
+    /// prefixの下にマウントされる（mountと同じ）。グループのミドルウェアは
+    /// グループ内のルートにマッチしたリクエストにだけ実行される。
+    ///
+    /// 例:
+    ///   router.group("/api", |api| {
+    ///       api.use_middleware(auth_middleware);
+    ///       api.get("/users", list_users);          // GET /api/users
+    ///       api.group("/admin", |admin| {
+    ///           admin.get("/stats", admin_stats);   // GET /api/admin/stats
+    ///       });
+    ///   });
+    pub fn group(&mut self, prefix: &str, build: impl FnOnce(&mut Router)) {
+        let mut group = Router::new();
+        build(&mut group);
+        self.mount(prefix, group);
+    }
+
+    /// 別に組み立てたルーターをprefixの下にマウント
+    ///
+    /// マウントしたルーターのミドルウェアはそのルーターのルートにだけ適用され、
+    /// こちらのミドルウェアの後に実行される。共有状態は引き継いで全てのルートで共有する
+    /// （同じ型の状態が両方にあればエラー）。404ハンドラーとエラー変換は引き継がない。
+    /// 重複・曖昧なルートはpanicする（エラーとして扱う場合はtry_mount）。
+    pub fn mount(&mut self, prefix: &str, router: Router) {
+        if let Err(e) = self.try_mount(prefix, router) {
+            panic!("mount {}: {}", prefix, e);
+        }
+    }
+
+    /// 別に組み立てたルーターをprefixの下にマウントし、重複・曖昧なルートはエラーを返す
+    ///
+    /// エラーの場合、マウントしようとしたルートと共有状態は1つも登録されない。
+    pub fn try_mount(&mut self, prefix: &str, router: Router) -> Result<(), RouteError> {
+        let Router {
+            trees,
+            any_tree,
+            middlewares,
+            not_found_handler: _,
+            error_handler: _,
+            state,
+        } = router;
+
+        // どちらの状態を使うか決められないため、同じ型の状態はエラーにする
+        if let Some(type_name) = state.conflicting(&self.state) {
+            return Err(RouteError::StateConflict { type_name });
+        }
+
+        let middlewares: Arc<[Arc<dyn Middleware>]> = middlewares.into();
+        let mut routes = Vec::new();
+        for (_, tree) in &trees {
+            routes.extend(mounted_routes(prefix, tree, &middlewares));
+        }
+        routes.extend(mounted_routes(prefix, &any_tree, &middlewares));
+
+        // 1つでも登録できないルートがあれば、何も登録せずにエラーを返す
+        for route in &routes {
+            let tree = match &route.info.method {
+                Some(method) => self.tree(method),
+                None => Some(&self.any_tree),
+            };
+            if let Some(tree) = tree {
+                tree.check(&route.info.pattern)?;
+            }
+        }
+        for route in routes {
+            let tree = match route.info.method.clone() {
+                Some(method) => {
+                    let index = self.tree_index(method);
+                    &mut self.trees[index].1
+                }
+                None => &mut self.any_tree,
+            };
+            tree.insert(&route.info.pattern.clone(), route)?;
+        }
+        Arc::make_mut(&mut self.state).merge(Arc::unwrap_or_clone(state));
+        Ok(())
+    }
+
+    /// 登録されたルートの一覧（メソッドの登録順。anyのルートは最後）
+    ///
+    /// メタデータと合わせてAPIの一覧やドキュメントの生成に使える。
+    pub fn routes(&self) -> Vec<Arc<RouteInfo>> {
+        let trees = self.trees.iter().map(|(_, tree)| tree);
+        let mut routes: Vec<Arc<RouteInfo>> = Vec::new();
+        for tree in trees.chain([&self.any_tree]) {
+            // 省略可能なパラメータのルートは同じパターンで2つの葉に登録されている
+            let mut seen = HashSet::new();
+            for leaf in tree.leaves() {
+                if seen.insert(leaf.pattern.as_str()) {
+                    routes.push(Arc::clone(&leaf.value.info));
+                }
+            }
+        }
+        routes
     }
 
     /// リクエストを処理してレスポンスを返す
     /// 
     /// 処理フロー:
-    /// 1. HttpRequestをRequestに変換
-    /// 2. ミドルウェアを順次実行
+    /// 1. HttpRequestをRequestに変換（パスとクエリを分割してデコード）
+    /// 2. ミドルウェアを順次実行（各ミドルウェアが後続の処理を包む）
     /// 3. ルートをマッチング
     /// 4. マッチしたハンドラを実行
-    /// 5. レスポンスを返す
+    /// 5. レスポンスを返す（HEADリクエストならボディを取り除く）
     pub fn handle(&self, http_req: HttpRequest) -> Response {
+        let is_head = http_req.method == Method::Head;
+        let mut response = self.respond(http_req);
+        if is_head {
+            response.strip_body();
+        }
+        response
+    }
+
+    /// リクエストをRequestに変換し、ミドルウェアとルートを実行する
+    fn respond(&self, http_req: HttpRequest) -> Response {
+        // リクエストターゲットをパスとクエリに分割し、パスは '/' で区切ってからデコード
+        // （"%2F" は区切りではなくセグメント内の文字として扱う）
+        let (raw_path, raw_query) = split_target(&http_req.path);
+        let Some(segments) = raw_path
+            .split('/')
+            .map(percent_decode)
+            .collect::<Option<Vec<String>>>()
+        else {
+            let problem = Problem::new(StatusCode::BAD_REQUEST);
+            return problem.with_detail("Invalid percent-encoding in path").to_response();
+        };
+        let Some(query) = QueryMap::parse(raw_query.unwrap_or("")) else {
+            let problem = Problem::new(StatusCode::BAD_REQUEST);
+            return problem.with_detail("Invalid percent-encoding in query").to_response();
+        };
+        let path = segments.join("/");
+        let target = routing_path(segments.iter().map(String::as_str));
+
         // Requestに変換
         let mut request = Request {
             method: http_req.method.clone(),
-            path: http_req.path.clone(),
+            path: path.clone(),
+            query,
             headers: http_req.headers.clone(),
             body: http_req.body.clone(),
             params: HashMap::new(),
+            route: None,
+            extensions: Extensions::new(),
+            state: Arc::clone(&self.state),
         };
 
-        // デフォルトレスポンス
-        let mut response = Response::ok(r#"{"status": "ok"}"#);
+        // ミドルウェアを順に実行し、最後にルーティング
+        // （ミドルウェアがパスを書き換えた場合は、書き換え後のパスでマッチングする）
+        let routing = |req: &mut Request| {
+            if req.path == path {
+                self.dispatch(req, &target)
+            } else {
+                let rewritten = routing_path(req.path.split('/'));
+                self.dispatch(req, &rewritten)
+            }
+        };
+        Next::new(&self.middlewares, &routing).run(&mut request)
+    }
 
-        // ミドルウェア実行
-        for middleware in &self.middlewares {
-            match middleware(&request, &mut response) {
-                MiddlewareResult::Continue => continue,
-                MiddlewareResult::Stop => return response,
+    /// ルートをマッチングしてハンドラを実行する
+    ///
+    /// target: マッチングに使うパス（routing_pathで作ったもの）
+    ///
+    /// 0. OPTIONS * はサーバー全体が受け付けるメソッドをAllowヘッダーで返す
+    /// 1. メソッドとパスが一致するルート
+    /// 2. HEADでHEADルートがなければGETルート
+    /// 3. OPTIONSでOPTIONSルートがなければAllowヘッダー付きの204
+    /// 4. パスのみ一致するルートがあれば405（Allowヘッダー付き）
+    /// 5. どれにも当てはまらなければ404
+    fn dispatch(&self, request: &mut Request, target: &str) -> Response {
+        // アスタリスク形式のターゲットはサーバー全体を指し、ルートにはマッチしない
+        if target == "*" {
+            if request.method != Method::Options {
+                return Problem::new(StatusCode::BAD_REQUEST)
+                    .with_detail("Asterisk-form target is only allowed for OPTIONS")
+                    .to_response();
             }
+            return options_response(&self.server_methods());
         }
 
-        // ルートマッチング
-        for route in &self.routes {
-            // メソッドチェック
-            if route.method != request.method {
-                continue;
+        if let Some((route, params)) = self.find_route(&request.method, target) {
+            return route.call(self, request, params);
+        }
+
+        if request.method == Method::Head {
+            if let Some((route, params)) = self.find_route(&Method::Get, target) {
+                return route.call(self, request, params);
             }
+        }
 
-            // パスマッチング
-            if let Some(params) = match_path(&route.pattern, &route.param_names, &request.path) {
-                request.params = params;
-                return (route.handler)(&request);
+        let allowed = self.allowed_methods(target);
+        if !allowed.is_empty() {
+            if request.method == Method::Options {
+                return options_response(&allowed);
             }
+            let mut response = Problem::new(StatusCode::METHOD_NOT_ALLOWED)
+                .with_instance(&request.path)
+                .to_response();
+            response.headers.insert("Allow", allow_header(&allowed));
+            return response;
         }
 
         // 404ハンドラー
         if let Some(handler) = &self.not_found_handler {
-            handler(&request)
+            self.run_handler(handler, request)
         } else {
-            Response::not_found(r#"{"error": "Not Found"}"#)
+            Problem::new(StatusCode::NOT_FOUND)
+                .with_instance(&request.path)
+                .to_response()
         }
     }
-}
 
-/// パターンからパラメータ名を抽出
-/// 例: "/users/:id/posts/:post_id" -> ["id", "post_id"]
-fn extract_param_names(pattern: &str) -> Vec<String> {
-    pattern
-        .split('/')
-        .filter(|seg| seg.starts_with(':'))
-        .map(|seg| seg[1..].to_string())
-        .collect()
-}
+    /// ハンドラを実行し、エラーならエラー変換でレスポンスにする
+    fn run_handler(&self, handler: &Handler, request: &mut Request) -> Response {
+        match handler(request) {
+            Ok(response) => response,
+            Err(error) => match &self.error_handler {
+                Some(error_handler) => error_handler(error, request),
+                None => default_error_response(&error),
+            },
+        }
+    }
 
-/// パスがパターンにマッチするかチェックし、パラメータを抽出
-/// 
-/// 例:
-/// pattern: "/users/:id/posts/:post_id"
-/// path: "/users/123/posts/456"
-/// -> Some({"id": "123", "post_id": "456"})
-fn match_path(
-    pattern: &str,
-    param_names: &[String],
-    path: &str,
-) -> Option<HashMap<String, String>> {
-    let pattern_segments: Vec<&str> = pattern.split('/').collect();
-    let path_segments: Vec<&str> = path.split('/').collect();
+    /// メソッドとパスに一致するルートと、抽出したパラメータ
+    ///
+    /// メソッドごとの木を先に検索し、なければ全メソッドの木を検索する。
+    fn find_route(&self, method: &Method, path: &str) -> Option<(&Route, HashMap<String, String>)> {
+        let mut params = Vec::new();
+        let leaf = self
+            .tree(method)
+            .and_then(|tree| tree.find(path, &mut params))
+            .or_else(|| self.any_tree.find(path, &mut params))?;
 
-    // セグメント数が一致しない場合はマッチしない
-    if pattern_segments.len() != path_segments.len() {
-        return None;
+        // マッチングに使ったパスでは '%' と '/' だけがエンコードされている
+        let params = params
+            .into_iter()
+            .map(|(name, value)| {
+                let value = percent_decode(value).unwrap_or_else(|| value.to_string());
+                (name.to_string(), value)
+            })
+            .collect();
+        Some((&leaf.value, params))
     }
 
-    let mut params = HashMap::new();
-    let mut param_index = 0;
+    /// 指定したメソッドの木
+    fn tree(&self, method: &Method) -> Option<&Node<Route>> {
+        self.trees
+            .iter()
+            .find(|(m, _)| m == method)
+            .map(|(_, tree)| tree)
+    }
 
-    for (pattern_seg, path_seg) in pattern_segments.iter().zip(path_segments.iter()) {
-        if pattern_seg.starts_with(':') {
-            // 動的セグメント（パラメータ）
-            if param_index < param_names.len() {
-                params.insert(param_names[param_index].clone(), path_seg.to_string());
-                param_index += 1;
+    /// パスに一致するルートが受け付けるメソッドの一覧（Allowヘッダー用）
+    ///
+    /// GETがあればHEADを、いずれかのルートがあればOPTIONSを加える。
+    /// パスに一致するルートがなければ空。
+    fn allowed_methods(&self, path: &str) -> Vec<Method> {
+        // 明示的なHEAD/OPTIONSルートと重複しないよう、最初の位置だけ残す
+        fn push(allowed: &mut Vec<Method>, method: Method) {
+            if !allowed.contains(&method) {
+                allowed.push(method);
             }
-        } else if pattern_seg != path_seg {
-            // 静的セグメントが一致しない
-            return None;
         }
+
+        let mut allowed = Vec::new();
+        for (method, tree) in &self.trees {
+            if tree.find(path, &mut Vec::new()).is_none() {
+                continue;
+            }
+            push(&mut allowed, method.clone());
+            if *method == Method::Get {
+                push(&mut allowed, Method::Head);
+            }
+        }
+
+        // 全メソッドのルートがある場合は405にならないため、ここでは考慮しない
+        if !allowed.is_empty() {
+            push(&mut allowed, Method::Options);
+        }
+        allowed
+    }
+
+    /// いずれかのルートで受け付けるメソッドの一覧（OPTIONS * のAllowヘッダー用）
+    ///
+    /// allowed_methodsと同じく、GETがあればHEADを加え、OPTIONSは常に含める。
+    fn server_methods(&self) -> Vec<Method> {
+        let mut methods: Vec<Method> = Vec::new();
+        let mut push = |method: Method| {
+            if !methods.contains(&method) {
+                methods.push(method);
+            }
+        };
+        for (method, _) in &self.trees {
+            push(method.clone());
+            if *method == Method::Get {
+                push(Method::Head);
+            }
+        }
+        push(Method::Options);
+        methods
     }
+}
+
+/// デコード済みのパスのセグメントからマッチング用のパスを作る
+///
+/// セグメント内の '/' と '%' だけを再エンコードして '/' で連結する。
+/// 木は '/' をセグメントの区切りとして扱うため、"%2F" を含むセグメントも
+/// 1つのパラメータの値としてマッチする（値はfind_routeでデコードし直す）。
+///
+/// 例: ["", "files", "a/b"] -> "/files/a%2Fb"
+fn routing_path<'a>(segments: impl Iterator<Item = &'a str>) -> String {
+    segments
+        .map(|segment| segment.replace('%', "%25").replace('/', "%2F"))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Allowヘッダーの値
+fn allow_header(methods: &[Method]) -> String {
+    methods
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
-    Some(params)
+/// OPTIONSへの自動応答（Allowヘッダー付きの204）
+fn options_response(methods: &[Method]) -> Response {
+    let mut response = Response::new(StatusCode::NO_CONTENT);
+    response.headers.remove("content-type");
+    response.headers.insert("Allow", allow_header(methods));
+    response
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn http_request(method: &str, target: &str) -> HttpRequest {
+        HttpRequest::parse(&mut format!("{} {} HTTP/1.1\r\n\r\n", method, target).as_bytes())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_handle_splits_query_and_decodes_params() {
+        let mut router = Router::new();
+        router.get("/api/users/:name", |req| {
+            let body = format!(
+                "{}|{}",
+                req.params.get("name").unwrap(),
+                req.query.get_all("tag").join(",")
+            );
+            Response::ok(&body)
+        });
+
+        let response = router.handle(http_request("GET", "/api/users/John%20Doe?tag=a&tag=b"));
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.body.as_bytes(), Some(&b"John Doe|a,b"[..]));
+
+        let response = router.handle(http_request("GET", "/api/users/%zz"));
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_encoded_slash_stays_in_segment() {
+        let mut router = Router::new();
+        router.get("/files/:name", |req| Response::ok(&req.params["name"]));
+        router.get("/files/:name/raw", |_req| Response::ok("raw"));
+        router.get("/static/:dir/:file", |req| Response::ok(&req.path));
+
+        // "%2F" はセグメントの区切りにならず、デコードした値がパラメータに入る
+        let response = router.handle(http_request("GET", "/files/a%2Fb"));
+        assert_eq!(response.body.as_bytes(), Some(&b"a/b"[..]));
+        let response = router.handle(http_request("GET", "/files/100%25%2F"));
+        assert_eq!(response.body.as_bytes(), Some(&b"100%/"[..]));
+        let response = router.handle(http_request("GET", "/files/a%2Fb/raw"));
+        assert_eq!(response.body.as_bytes(), Some(&b"raw"[..]));
+
+        // Request::pathは全体をデコードしたもの
+        let response = router.handle(http_request("GET", "/static/x%20y/z"));
+        assert_eq!(response.body.as_bytes(), Some(&b"/static/x y/z"[..]));
+    }
+
+    #[test]
+    fn test_static_route_wins_regardless_of_order() {
+        let mut router = Router::new();
+        router.get("/api/users/:id", |req| Response::ok(&req.params["id"]));
+        router.get("/api/users/me", |_req| Response::ok("me"));
+
+        let response = router.handle(http_request("GET", "/api/users/me"));
+        assert_eq!(response.body.as_bytes(), Some(&b"me"[..]));
+        let response = router.handle(http_request("GET", "/api/users/42"));
+        assert_eq!(response.body.as_bytes(), Some(&b"42"[..]));
+    }
+
+    #[test]
+    fn test_try_route_reports_duplicates() {
+        let mut router = Router::new();
+        let handler = || |_req: &Request| Response::ok("");
+        router.try_route(Method::Get, "/users/:id", handler()).unwrap();
+        // メソッドが異なれば別の木なので衝突しない
+        router.try_route(Method::Put, "/users/:name", handler()).unwrap();
+
+        let err = router.try_route(Method::Get, "/users/:id", handler()).unwrap_err();
+        assert_eq!(err.to_string(), "Route /users/:id duplicates /users/:id");
+        let err = router.try_route(Method::Get, "/users/:name", handler()).unwrap_err();
+        assert!(matches!(err, RouteError::Conflict { .. }));
+    }
+
+    #[test]
+    #[should_panic(expected = "POST Route /a duplicates /a")]
+    fn test_duplicate_route_panics() {
+        let mut router = Router::new();
+        router.post("/a", |_req| Response::ok(""));
+        router.post("/a", |_req| Response::ok(""));
+    }
+
+    #[test]
+    fn test_groups_prefix_and_scoped_middleware() {
+        fn deny(_req: &mut Request, _next: Next) -> Response {
+            Response::unauthorized("denied")
+        }
+
+        let mut router = Router::new();
+        router.get("/", |_req| Response::ok("home"));
+        router.group("/api", |api| {
+            api.get("/", |_req| Response::ok("api"));
+            api.group("/admin/", |admin| {
+                admin.use_middleware(deny);
+                admin.get("/users/:id", |req| Response::ok(&req.params["id"]));
+            });
+        });
+
+        let response = router.handle(http_request("GET", "/"));
+        assert_eq!(response.body.as_bytes(), Some(&b"home"[..]));
+        let response = router.handle(http_request("GET", "/api"));
+        assert_eq!(response.body.as_bytes(), Some(&b"api"[..]));
+        // グループのミドルウェアはグループ内のルートだけに適用される
+        let response = router.handle(http_request("GET", "/api/admin/users/1"));
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_route_middleware_and_metadata() {
+        // ルートのメタデータで要求されるロールを確認するミドルウェア
+        fn require_role(req: &mut Request, next: Next) -> Response {
+            let route = req.route.clone().unwrap();
+            let required = route.meta("role").unwrap_or("user");
+            if req.headers.get("x-role") == Some(required) {
+                next.run(req)
+            } else {
+                let body = format!("{} {} needs {}", route.pattern, req.params["id"], required);
+                Response::unauthorized(&body)
+            }
+        }
+
+        let mut router = Router::new();
+        router.group("/api", |api| {
+            api.get("/users/:id", |req| Response::ok(&req.params["id"]))
+                .middleware(require_role);
+            api.delete("/users/:id", |_req| Response::ok("deleted"))
+                .middleware(require_role)
+                .meta("role", "admin")
+                .meta("summary", "Delete a user");
+            api.get("/health", |req| Response::ok(&req.route.as_ref().unwrap().pattern));
+        });
+
+        let response = router.handle(http_request("GET", "/api/users/7"));
+        assert_eq!(response.body.as_bytes(), Some(&b"/api/users/:id 7 needs user"[..]));
+        let response = router.handle(http_request("DELETE", "/api/users/7"));
+        assert_eq!(response.body.as_bytes(), Some(&b"/api/users/:id 7 needs admin"[..]));
+        // ルート単位のミドルウェアは他のルートに影響しない
+        let response = router.handle(http_request("GET", "/api/health"));
+        assert_eq!(response.body.as_bytes(), Some(&b"/api/health"[..]));
+
+        let routes = router.routes();
+        assert_eq!(routes.len(), 3);
+        let delete = routes.iter().find(|r| r.method == Some(Method::Delete)).unwrap();
+        assert_eq!(delete.meta("summary"), Some("Delete a user"));
+    }
+
     #[test]
-    fn test_extract_param_names() {
-        let names = extract_param_names("/users/:id/posts/:post_id");
-        assert_eq!(names, vec!["id", "post_id"]);
+    fn test_global_middleware_sees_matched_route_after_next() {
+        let mut router = Router::new();
+        router.use_middleware(|req: &mut Request, next: Next| {
+            assert!(req.route.is_none());
+            let mut response = next.run(req);
+            let pattern = req.route.as_ref().map_or("-", |route| route.pattern.as_str());
+            response.headers.insert("X-Route", pattern);
+            response
+        });
+        router.get("/posts/:page?", |_req| Response::ok(""));
+
+        let response = router.handle(http_request("GET", "/posts"));
+        assert_eq!(response.headers.get("x-route"), Some("/posts/:page?"));
+        let response = router.handle(http_request("GET", "/missing"));
+        assert_eq!(response.headers.get("x-route"), Some("-"));
     }
 
     #[test]
-    fn test_match_path() {
-        let pattern = "/users/:id/posts/:post_id";
-        let param_names = extract_param_names(pattern);
-        let path = "/users/123/posts/456";
-        
-        let params = match_path(pattern, &param_names, path).unwrap();
-        assert_eq!(params.get("id"), Some(&"123".to_string()));
-        assert_eq!(params.get("post_id"), Some(&"456".to_string()));
+    fn test_state_and_extensions() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        struct Hits(AtomicU32);
+        struct Greeting(&'static str);
+        struct CurrentUser(String);
+
+        let mut admin = Router::new().with_state(Greeting("hello"));
+        admin.use_middleware(|req: &mut Request, next: Next| {
+            if let Some(name) = req.headers.get("x-user") {
+                let user = CurrentUser(name.to_string());
+                req.extensions.insert(user);
+            }
+            next.run(req)
+        });
+        admin.get("/me", |req| -> Result<Response, BoxError> {
+            let hits = req.state::<Hits>()?.0.fetch_add(1, Ordering::Relaxed) + 1;
+            let greeting = req.state::<Greeting>()?.0;
+            Ok(match req.extensions.get::<CurrentUser>() {
+                Some(user) => Response::ok(&format!("{} {} #{}", greeting, user.0, hits)),
+                None => Response::unauthorized(""),
+            })
+        });
+
+        let mut router = Router::new().with_state(Hits(AtomicU32::new(0)));
+        router.mount("/admin", admin);
+
+        let request = HttpRequest::parse(
+            &mut &b"GET /admin/me HTTP/1.1\r\nX-User: alice\r\n\r\n"[..],
+        )
+        .unwrap();
+        let response = router.handle(request);
+        assert_eq!(response.body.as_bytes(), Some(&b"hello alice #1"[..]));
+
+        // 拡張領域はリクエストごとに空から始まる
+        let response = router.handle(http_request("GET", "/admin/me"));
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+        // 同じ型の状態を持つルーターはマウントできない（ルートも登録されない）
+        let mut other = Router::new().with_state(Hits(AtomicU32::new(0)));
+        other.get("/other", |_req| Response::ok(""));
+        let err = router.try_mount("/other", other).unwrap_err();
+        assert!(matches!(err, RouteError::StateConflict { type_name } if type_name.ends_with("Hits")));
+        let response = router.handle(http_request("GET", "/other/other"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
     }
 
     #[test]
-    fn test_match_path_no_match() {
-        let pattern = "/users/:id";
-        let param_names = extract_param_names(pattern);
-        let path = "/posts/123";
-        
-        assert!(match_path(pattern, &param_names, path).is_none());
+    fn test_missing_state_is_500() {
+        let mut router = Router::new();
+        router.get("/", |req| -> Result<Response, BoxError> {
+            Ok(Response::ok(req.state::<String>()?))
+        });
+
+        let response = router.handle(http_request("GET", "/"));
+        assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        // 共有状態も含め、構造体リテラルでRequestを組み立てられる
+        let mut state = Extensions::new();
+        state.insert("x".to_string());
+        let request = Request {
+            method: Method::Get,
+            path: "/".to_string(),
+            query: QueryMap::new(),
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+            params: HashMap::new(),
+            route: None,
+            extensions: Extensions::new(),
+            state: Arc::new(state),
+        };
+        assert_eq!(request.state::<String>().map(String::as_str), Ok("x"));
+        assert!(request.state::<u8>().is_err());
+    }
+
+    #[test]
+    fn test_fallible_handlers_and_error_mapping() {
+        #[derive(Debug)]
+        struct Locked;
+        impl fmt::Display for Locked {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "resource is locked")
+            }
+        }
+        impl std::error::Error for Locked {}
+
+        let mut router = Router::new();
+        router.get("/locked", |_req| -> Result<Response, Locked> { Err(Locked) });
+        router.get("/items/:id", |req| -> Result<Response, BoxError> {
+            let id: u32 = req.param("id")?;
+            if id > 10 {
+                return Err(Problem::new(StatusCode::NOT_FOUND)
+                    .with_detail(&format!("Item {} not found", id))
+                    .into());
+            }
+            Ok(Response::ok(&id.to_string()))
+        });
+
+        // 既定の変換: Problemはそのまま、その他のエラーは500
+        let response = router.handle(http_request("GET", "/items/11"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+        assert_eq!(response.headers.get("content-type"), Some("application/problem+json"));
+        let body = String::from_utf8(response.body.as_bytes().unwrap().to_vec()).unwrap();
+        assert!(body.contains(r#""detail": "Item 11 not found""#), "{}", body);
+        let response = router.handle(http_request("GET", "/locked"));
+        assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        // 独自の変換（扱わないエラーは既定の変換に任せる）
+        router.on_error(|error, req| match error.downcast_ref::<Locked>() {
+            Some(locked) => Problem::new(StatusCode::LOCKED)
+                .with_detail(&locked.to_string())
+                .with_instance(&req.path)
+                .to_response(),
+            None => default_error_response(&error),
+        });
+        let response = router.handle(http_request("GET", "/locked"));
+        assert_eq!(response.status, StatusCode::LOCKED);
+        let response = router.handle(http_request("GET", "/items/x"));
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_mount_sub_router() {
+        let mut files = Router::new();
+        files.get("/*path", |req| Response::ok(&req.params["path"]));
+        files.get("/list/:page?", |req| {
+            Response::ok(req.params.get("page").map_or("1", String::as_str))
+        });
+
+        let mut router = Router::new();
+        router.mount("/files", files);
+
+        let response = router.handle(http_request("GET", "/files/a/b.txt"));
+        assert_eq!(response.body.as_bytes(), Some(&b"a/b.txt"[..]));
+        let response = router.handle(http_request("GET", "/files/list"));
+        assert_eq!(response.body.as_bytes(), Some(&b"1"[..]));
+        let response = router.handle(http_request("GET", "/files/list/3"));
+        assert_eq!(response.body.as_bytes(), Some(&b"3"[..]));
+
+        // 重複するルートが1つでもあれば、他のルートも登録されない
+        let mut other = Router::new();
+        other.get("/new", |_req| Response::ok("mounted"));
+        other.get("/list", |_req| Response::ok(""));
+        assert!(matches!(
+            router.try_mount("/files", other),
+            Err(RouteError::Duplicate { .. })
+        ));
+        let response = router.handle(http_request("GET", "/files/new"));
+        assert_eq!(response.body.as_bytes(), Some(&b"new"[..])); // 既存の /files/*path
+    }
+
+    #[test]
+    fn test_typed_params_and_400() {
+        let mut router = Router::new();
+        router.get("/users/:id", |req| -> Result<Response, ParamError> {
+            let id: u32 = req.param("id")?;
+            let page = req.query_param_opt::<u32>("page")?.unwrap_or(1);
+            Ok(Response::ok(&format!("{}:{}", id, page)))
+        });
+
+        let response = router.handle(http_request("GET", "/users/7?page=3"));
+        assert_eq!(response.body.as_bytes(), Some(&b"7:3"[..]));
+        let response = router.handle(http_request("GET", "/users/7"));
+        assert_eq!(response.body.as_bytes(), Some(&b"7:1"[..]));
+
+        let response = router.handle(http_request("GET", "/users/abc"));
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        let body = String::from_utf8(response.body.as_bytes().unwrap().to_vec()).unwrap();
+        assert!(body.contains(r#""parameter": "id", "location": "path""#), "{}", body);
+
+        let response = router.handle(http_request("GET", "/users/7?page=-1"));
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_missing_header_param() {
+        let request = Request {
+            method: Method::Get,
+            path: "/".to_string(),
+            query: QueryMap::new(),
+            headers: [("X-Count", "12")].into_iter().collect(),
+            body: Vec::new(),
+            params: HashMap::new(),
+            route: None,
+            extensions: Extensions::new(),
+            state: Arc::default(),
+        };
+        assert_eq!(request.header::<u8>("x-count"), Ok(12));
+
+        let err = request.header::<u8>("X-Missing").unwrap_err();
+        assert_eq!(err.to_string(), "Missing header parameter 'X-Missing'");
+    }
+
+    #[test]
+    fn test_head_options_and_405() {
+        let mut router = Router::new();
+        router.get("/items/:id", |req| Response::ok(&req.params["id"]));
+        router.delete("/items/:id", |_req| Response::new(StatusCode::NO_CONTENT));
+        router.any("/echo", |req| Response::ok(req.method.as_str()));
+
+        // HEADはGETルートに応答し、ヘッダーを残してボディを取り除く
+        let response = router.handle(http_request("HEAD", "/items/42"));
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers.get("Content-Length"), Some("2"));
+        assert_eq!(response.body.as_bytes(), Some(&b""[..]));
+
+        let response = router.handle(http_request("OPTIONS", "/items/42"));
+        assert_eq!(response.status, StatusCode::NO_CONTENT);
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, DELETE, OPTIONS"));
+
+        let response = router.handle(http_request("PUT", "/items/42"));
+        assert_eq!(response.status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, DELETE, OPTIONS"));
+
+        let response = router.handle(http_request("PURGE", "/echo"));
+        assert_eq!(response.body.as_bytes(), Some(&b"PURGE"[..]));
+
+        let response = router.handle(http_request("PUT", "/missing"));
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_options_asterisk() {
+        let mut router = Router::new();
+        router.get("/items", |_req| Response::ok("[]"));
+        router.post("/items", |_req| Response::new(StatusCode::CREATED));
+        router.any("/*rest", |_req| Response::ok("fallback"));
+
+        // サーバー全体への問い合わせにはルートを実行せずAllowで答える
+        let response = router.handle(http_request("OPTIONS", "*"));
+        assert_eq!(response.status, StatusCode::NO_CONTENT);
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, POST, OPTIONS"));
+
+        let response = router.handle(http_request("GET", "*"));
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_constrained_and_optional_routes() {
+        let mut router = Router::new();
+        router.get("/users/:id<int>", |req| Response::ok(&format!("id {}", req.params["id"])));
+        router.get("/users/:name", |req| Response::ok(&format!("name {}", req.params["name"])));
+        router.get("/archive/:year<[0-9]{4}>/:slug?", |req| {
+            let slug = req.params.get("slug").map_or("-", String::as_str);
+            Response::ok(&format!("{} {}", req.params["year"], slug))
+        });
+        router.get("/files/:name.:ext<[a-z]+>", |req| {
+            Response::ok(&format!("{} {}", req.params["name"], req.params["ext"]))
+        });
+
+        let cases = [
+            ("/users/42", "id 42"),
+            ("/users/alice", "name alice"),
+            ("/archive/2024", "2024 -"),
+            ("/archive/2024/hello", "2024 hello"),
+            ("/files/report.v2.pdf", "report.v2 pdf"),
+        ];
+        for (target, expected) in cases {
+            let response = router.handle(http_request("GET", target));
+            assert_eq!(response.body.as_bytes(), Some(expected.as_bytes()), "{}", target);
+        }
+
+        // 制約に合わない値はどのルートにもマッチしない
+        for target in ["/archive/24", "/archive/2024x/hello", "/files/README.TXT"] {
+            let response = router.handle(http_request("GET", target));
+            assert_eq!(response.status, StatusCode::NOT_FOUND, "{}", target);
+        }
     }
 }
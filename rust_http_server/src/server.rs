@@ -1,235 +1,912 @@
 // src/server.rs
 //
 // 【処理概要】
-// HTTPサーバーの核となる部分。TCPリスナーとワーカースレッドプールを実装。
+// HTTPサーバーの核となる部分。TCPリスナーと接続の処理を実装。
 // 接続を受け付け、並行処理でリクエストを処理する。
 //
 // 【主な機能】
-// - TCPソケットのバインドとリッスン
+// - TCPソケットのバインドとリッスン（複数アドレス可）
+// - 2つの動作方式: 接続ごとにワーカーが読み書きするスレッド方式と、
+//   epollで読み書きしワーカーにはリクエストの処理だけを渡すイベントループ方式（Linuxのみ）
+// - ServerConfigによる設定（ワーカー数、キュー容量、タイムアウト、サイズ制限）
 // - スレッドプールによる並行リクエスト処理
 // - 接続ごとのリクエスト/レスポンスハンドリング
+// - 持続接続（HTTP/1.1 キープアライブ）
+// - リクエストサイズ制限（超過時は414 / 431 / 413を返す）
+// - 不正なリクエストへのエラーレスポンス（400 / 405 / 505等、カスタマイズ可能）
+// - ハンドラのpanicを捕捉して500を返す（ワーカースレッドは止まらない）
+// - 過負荷時の動作（キューが満杯なら受付を待たせる、または503で断る）
+// - 負荷に応じたワーカー数の増減（最小数〜最大数）
+// - 統計情報（panicの回数、ワーカーの再起動回数、キューの長さ、断った接続数、ワーカーの稼働率）
 // - エラーハンドリングとグレースフルシャットダウン
+//   （ShutdownHandle・SIGINT/SIGTERMで受付を止め、処理中のリクエストを期限まで待つ）
 //
 // 【実装内容】
-// 1. TcpListenerで指定アドレスをリッスン
-// 2. 接続受付ループ（accept）
+// 1. TcpListenerで設定された全アドレスをリッスン
+// 2. 接続受付ループ（停止の要求を確認しながら、いずれかに接続が来るまでpollで待つ）
 // 3. 各接続をスレッドプールのワーカーに振り分け
 // 4. ワーカースレッドでHTTPリクエストをパース、ルーター処理、レスポンス送信
-// 5. スレッドプール管理（ワーカー生成、ジョブキューイング）
-
-use crate::http::HttpRequest;
+//    （キープアライブ中は同じ接続で繰り返す）
+// 5. スレッドプール（thread_pool::ThreadPool）でワーカーの増減・停止したワーカーの再起動を管理
+
+use crate::config::{OverloadPolicy, ServerConfig, ServerMode};
+#[cfg(target_os = "linux")]
+use crate::event_loop;
+use crate::error::Problem;
+use crate::http::{Framing, HttpRequest, HttpResponse, ParseError, RequestLimits};
+use crate::method::Method;
 use crate::router::Router;
-use std::io::{self, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::{Arc, Mutex, mpsc};
+use crate::shutdown::{ConnectionGuard, ShutdownHandle};
+use crate::status::StatusCode;
+use crate::sys;
+use crate::thread_pool::{panic_message, PoolMetrics, ThreadPool};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
+
+/// 受付ループが停止の要求を確認する間隔
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// シャットダウン時に接続の終了を確認する間隔
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// 過負荷で断る際に読み捨てる受信済みデータの上限（受付スレッドを長く止めない）
+pub(crate) const REJECT_DRAIN_LIMIT: usize = 64 * 1024;
+
+/// 期限を過ぎて接続を切断した後、ワーカーがジョブを終えるのを待つ時間
+/// （これを過ぎても戻らないワーカーは切り離す）
+const WORKER_STOP_GRACE: Duration = Duration::from_millis(100);
+
+/// 過負荷で断る際の503の書き込みタイムアウト（受付スレッドを長く止めない）
+const REJECT_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// パースエラー時のエラーページを生成する関数の型
+pub type ErrorPageHandler = Box<dyn Fn(&ParseError) -> HttpResponse + Send + Sync>;
 
 /// HTTPサーバー
 pub struct Server {
-    address: String,
+    config: ServerConfig,
     router: Arc<Router>,
+    error_page: Option<ErrorPageHandler>,
+    metrics: Arc<ServerMetrics>,
+    shutdown: ShutdownHandle,
+    handle_signals: bool,
+}
+
+/// サーバーの統計情報（全ワーカーで共有し、実行中に参照できる）
+///
+/// 例: ハンドラから参照する場合はルーターの共有状態にも登録する
+///   let metrics = Arc::new(ServerMetrics::new());
+///   let router = router.with_state(Arc::clone(&metrics));
+///   server.metrics(metrics);
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    panics: AtomicU64,
+    rejected: AtomicU64,
+    pool: Arc<PoolMetrics>,
+}
+
+impl ServerMetrics {
+    /// 全て0の統計情報を作成
+    pub fn new() -> Self {
+        ServerMetrics::default()
+    }
+
+    /// ハンドラ（またはワーカーのジョブ）でpanicが起きた回数
+    pub fn panics(&self) -> u64 {
+        self.panics.load(Ordering::Relaxed) + self.pool.panics()
+    }
+
+    /// 停止したワーカースレッドを起動し直した回数
+    pub fn worker_restarts(&self) -> u64 {
+        self.pool.worker_restarts()
+    }
+
+    /// ワーカーの空きを待っている接続の数
+    pub fn queue_depth(&self) -> usize {
+        self.pool.queue_depth()
+    }
+
+    /// キューが満杯のため503で断った接続の数（OverloadPolicy::Reject）
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_rejection(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 起動中のワーカースレッド数
+    pub fn workers(&self) -> usize {
+        self.pool.workers()
+    }
+
+    /// ジョブ（接続）を処理中のワーカースレッド数
+    pub fn busy_workers(&self) -> usize {
+        self.pool.busy_workers()
+    }
+
+    /// ワーカーの稼働率（処理中のワーカー数 / 起動中のワーカー数。0.0〜1.0）
+    pub fn worker_utilization(&self) -> f64 {
+        self.pool.utilization()
+    }
+}
+
+/// 接続処理で共有する情報（全ワーカーで共有）
+pub(crate) struct ConnectionContext {
+    router: Arc<Router>,
+    pub(crate) options: ConnectionOptions,
+    error_page: Option<ErrorPageHandler>,
+    pub(crate) metrics: Arc<ServerMetrics>,
+    pub(crate) shutdown: ShutdownHandle,
+}
+
+/// 接続ごとの処理設定
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ConnectionOptions {
+    /// 次のリクエストを待つ最大時間
+    pub(crate) idle_timeout: Duration,
+    /// リクエストの受信中に、次のデータを待つ最大時間
+    pub(crate) read_timeout: Duration,
+    /// レスポンスの書き込みの最大時間
+    pub(crate) write_timeout: Duration,
+    /// 1接続で処理する最大リクエスト数
+    max_requests: usize,
+    /// リクエストのサイズ制限
+    pub(crate) limits: RequestLimits,
+}
+
+impl ConnectionOptions {
+    fn from_config(config: &ServerConfig) -> Self {
+        ConnectionOptions {
+            idle_timeout: config.idle_timeout,
+            read_timeout: config.read_timeout,
+            write_timeout: config.write_timeout,
+            max_requests: config.max_requests_per_connection,
+            limits: config.limits,
+        }
+    }
 }
 
 impl Server {
-    /// 新しいサーバーを作成
+    /// 新しいサーバーを作成（アドレス以外は既定の設定）
     pub fn new(address: &str, router: Router) -> Self {
+        let config = ServerConfig {
+            bind: vec![address.to_string()],
+            ..ServerConfig::default()
+        };
+        Server::with_config(config, router)
+    }
+
+    /// 設定を指定してサーバーを作成
+    ///
+    /// 設定はServerConfig::builderやServerConfig::loadで検証済みのものを渡す
+    /// （検証していない値はserve_allで検証し、不正ならInvalidInputのエラーを返す）。
+    pub fn with_config(config: ServerConfig, router: Router) -> Self {
         Server {
-            address: address.to_string(),
+            config,
             router: Arc::new(router),
+            error_page: None,
+            metrics: Arc::default(),
+            shutdown: ShutdownHandle::new(),
+            handle_signals: false,
         }
     }
 
-    /// サーバーを起動（ブロッキング）
-    /// 
-    /// 処理フロー:
-    /// 1. TCPリスナーをバインド
-    /// 2. スレッドプールを初期化（ワーカー数: 4）
-    /// 3. 接続受付ループに入る
-    /// 4. 各接続をスレッドプールに送信
+    /// 現在の設定
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    /// サーバーを停止させるハンドル（runの前に取得して別スレッドに渡す）
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// SIGINT（Ctrl+C）・SIGTERMでグレースフルシャットダウンする（Unixのみ）
+    ///
+    /// 2回目のシグナルでは待たずに終了する。
+    pub fn shutdown_on_signals(&mut self) {
+        self.handle_signals = true;
+    }
+
+    /// シャットダウン時に処理中のリクエストを待つ最大時間を変更
+    ///
+    /// 期限を過ぎても残っている接続は、レスポンスの途中でも切断する。
+    pub fn drain_timeout(&mut self, timeout: Duration) {
+        self.config.drain_timeout = timeout;
+    }
+
+    /// 統計情報の記録先を設定（ハンドラなどから参照する場合に渡す）
+    pub fn metrics(&mut self, metrics: Arc<ServerMetrics>) {
+        self.metrics = metrics;
+    }
+
+    /// キープアライブの設定を変更
+    ///
+    /// idle_timeout: 次のリクエストを待つ最大時間（超えたら接続を閉じる）
+    /// max_requests: 1接続で処理する最大リクエスト数（1なら常に切断）
+    pub fn keep_alive(&mut self, idle_timeout: Duration, max_requests: usize) {
+        assert!(max_requests > 0);
+        self.config.idle_timeout = idle_timeout;
+        self.config.max_requests_per_connection = max_requests;
+    }
+
+    /// リクエストのサイズ制限を変更
+    pub fn limits(&mut self, limits: RequestLimits) {
+        self.config.limits = limits;
+    }
+
+    /// 不正なリクエストに対するエラーページを設定
+    ///
+    /// 未設定の場合は ParseError::to_response（JSONボディ）を使う。
+    /// どちらの場合もレスポンス送信後に接続を閉じる。
+    pub fn error_page(&mut self, handler: ErrorPageHandler) {
+        self.error_page = Some(handler);
+    }
+
+    /// サーバーを起動（ブロッキング。シャットダウンが完了すると戻る）
+    ///
+    /// 設定された全てのアドレスをバインドする（1つでも失敗したらエラー）。
     pub fn run(self) -> io::Result<()> {
-        let listener = TcpListener::bind(&self.address)?;
-        println!("✅ Listening on {}\n", self.address);
+        let mut listeners = Vec::with_capacity(self.config.bind.len());
+        for address in &self.config.bind {
+            let listener = TcpListener::bind(address).map_err(|e| {
+                io::Error::new(e.kind(), format!("cannot bind {}: {}", address, e))
+            })?;
+            println!("✅ Listening on {}", listener.local_addr()?);
+            listeners.push(listener);
+        }
+        println!();
+        self.serve_all(listeners)
+    }
 
-        // スレッドプール作成（4ワーカー）
-        let pool = ThreadPool::new(4);
+    /// バインド済みのリスナーで接続を受け付ける（ブロッキング）
+    pub fn serve(self, listener: TcpListener) -> io::Result<()> {
+        self.serve_all(vec![listener])
+    }
+
+    /// バインド済みの複数のリスナーで接続を受け付ける（ブロッキング）
+    ///
+    /// 処理フロー:
+    /// 1. スレッドプールを初期化（ワーカー数の範囲・キュー容量は設定から）
+    /// 2. 停止が要求されるまで接続受付ループ（各接続をスレッドプールに送信）
+    /// 3. 受付を止め、アイドル接続を閉じ、処理中のリクエストを期限まで待つ
+    /// 4. ワーカーの終了を待って戻る（期限を過ぎても戻らないワーカーは切り離す）
+    pub fn serve_all(self, listeners: Vec<TcpListener>) -> io::Result<()> {
+        assert!(!listeners.is_empty(), "no listeners to serve");
+        if cfg!(not(target_os = "linux")) && self.config.mode == ServerMode::EventLoop {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "event loop mode is only supported on Linux",
+            ));
+        }
+        self.config
+            .validate_settings()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        for listener in &listeners {
+            listener.set_nonblocking(true)?;
+        }
+        if self.handle_signals {
+            sys::install_signal_handlers();
+        }
+
+        // スレッドプール作成（キューが満杯のときの動作は overload_policy）
+        let config = self.config;
+        let pool = ThreadPool::builder()
+            .min_workers(config.min_workers)
+            .max_workers(config.max_workers)
+            .queue_capacity(config.queue_capacity)
+            .idle_timeout(config.worker_idle_timeout)
+            .name("http-worker")
+            .metrics(Arc::clone(&self.metrics.pool))
+            .build()?;
+        if config.min_workers == config.max_workers {
+            println!("🧵 Thread pool initialized with {} workers", config.min_workers);
+        } else {
+            println!(
+                "🧵 Thread pool initialized with {}-{} workers",
+                config.min_workers, config.max_workers
+            );
+        }
+
+        let shutdown = self.shutdown;
+        let context = Arc::new(ConnectionContext {
+            router: self.router,
+            options: ConnectionOptions::from_config(&config),
+            error_page: self.error_page,
+            metrics: self.metrics,
+            shutdown: shutdown.clone(),
+        });
+
+        // イベントループ方式（受付・読み書きは1スレッドで行い、ワーカーはリクエストの処理のみ）
+        #[cfg(target_os = "linux")]
+        if config.mode == ServerMode::EventLoop {
+            let deadline = event_loop::run(listeners, &context, &pool, &config, self.handle_signals)?;
+            stop_pool(pool, deadline);
+            println!("✅ Server stopped");
+            return Ok(());
+        }
 
         // 接続受付ループ
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let router = Arc::clone(&self.router);
-                    
-                    // ジョブをスレッドプールに送信
-                    pool.execute(move || {
-                        if let Err(e) = handle_connection(stream, router) {
-                            eprintln!("❌ Error handling connection: {}", e);
+        loop {
+            if shutdown_requested(&shutdown, self.handle_signals) {
+                break;
+            }
+            if !sys::wait_readable(&listeners, ACCEPT_POLL_INTERVAL)? {
+                continue;
+            }
+
+            for listener in &listeners {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        // 全ワーカーが処理中でキューも満杯なら、待たせずに断る
+                        // （キューに入れるのはこのスレッドだけなので、確認後に満杯になることはない）
+                        if config.overload_policy == OverloadPolicy::Reject
+                            && context.metrics.queue_depth() >= config.queue_capacity
+                        {
+                            context.metrics.record_rejection();
+                            reject_connection(stream, config.retry_after);
+                            continue;
                         }
-                    });
-                }
-                Err(e) => {
-                    eprintln!("❌ Connection failed: {}", e);
+
+                        // 受付時に登録し、ワーカー待ちの接続もシャットダウン時に閉じられるようにする
+                        let guard = match stream.set_nonblocking(false).and_then(|_| shutdown.track(&stream)) {
+                            Ok(guard) => guard,
+                            Err(e) => {
+                                eprintln!("❌ Connection failed: {}", e);
+                                continue;
+                            }
+                        };
+                        let context = Arc::clone(&context);
+
+                        // ジョブをスレッドプールに送信
+                        pool.execute(move || {
+                            if let Err(e) = handle_connection(stream, &context, guard) {
+                                eprintln!("❌ Error handling connection: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => {
+                        eprintln!("❌ Connection failed: {}", e);
+                    }
                 }
             }
         }
 
+        // 受付を止めてから、処理中の接続の終了を待つ
+        drop(listeners);
+        let deadline = Instant::now() + config.drain_timeout;
+        drain_connections(&shutdown, deadline);
+        stop_pool(pool, deadline);
+        println!("✅ Server stopped");
         Ok(())
     }
 }
 
+/// 過負荷のため、503 Service Unavailable を返して切断する（受付スレッドで実行）
+///
+/// リクエストは読まずに応答する。受信済みのデータだけは（REJECT_DRAIN_LIMITまで）読み捨て、
+/// 未読のデータが残ってRSTで応答が失われることを避ける。
+fn reject_connection(mut stream: TcpStream, retry_after: Duration) {
+    let response = overload_response(retry_after);
+    let sent = stream
+        .set_write_timeout(Some(REJECT_WRITE_TIMEOUT))
+        .and_then(|_| write_response(&mut stream, response, false));
+    if let Err(e) = sent {
+        eprintln!("⚠️  Failed to send 503: {}", e);
+        return;
+    }
+    let _ = stream.shutdown(Shutdown::Write);
+    if stream.set_nonblocking(true).is_ok() {
+        let mut buf = [0; 4096];
+        let mut drained = 0;
+        while drained < REJECT_DRAIN_LIMIT {
+            match stream.read(&mut buf) {
+                Ok(n @ 1..) => drained += n,
+                _ => break,
+            }
+        }
+    }
+}
+
+/// 過負荷を伝える 503 Service Unavailable（Retry-Afterは秒単位に切り上げ。送信後に切断する）
+pub(crate) fn overload_response(retry_after: Duration) -> HttpResponse {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let mut response = Problem::new(StatusCode::SERVICE_UNAVAILABLE)
+        .with_detail("Server is overloaded")
+        .to_response();
+    response.headers.insert("Retry-After", seconds.max(1).to_string());
+    response.prepare_framing(false);
+    response.set_keep_alive(false);
+    response
+}
+
+/// 停止が要求されたか（シグナルを扱う場合は、受信していれば停止を要求する）
+pub(crate) fn shutdown_requested(shutdown: &ShutdownHandle, handle_signals: bool) -> bool {
+    if handle_signals && sys::signal_received() && !shutdown.is_shutdown() {
+        println!("\n📴 Signal received, shutting down...");
+        shutdown.shutdown();
+    }
+    shutdown.is_shutdown()
+}
+
+/// 処理中の接続が終わるまで待つ（期限を過ぎたら強制的に切断する）
+///
+/// アイドル接続は繰り返し閉じる（処理を終えた接続は、停止の要求を見て自ら閉じる）。
+fn drain_connections(shutdown: &ShutdownHandle, deadline: Instant) {
+    loop {
+        shutdown.close_idle();
+        let remaining = shutdown.active_connections();
+        if remaining == 0 {
+            return;
+        }
+        if Instant::now() >= deadline {
+            eprintln!("⚠️  Drain timeout: closing {} connection(s)", remaining);
+            shutdown.close_all();
+            return;
+        }
+        thread::sleep(DRAIN_POLL_INTERVAL);
+    }
+}
+
+/// スレッドプールを停止する（期限を過ぎても戻らないジョブのワーカーは待たずに切り離す）
+fn stop_pool(pool: ThreadPool, deadline: Instant) {
+    let timeout = deadline.saturating_duration_since(Instant::now()).max(WORKER_STOP_GRACE);
+    if !pool.shutdown_timeout(timeout) {
+        eprintln!("⚠️  Some workers did not finish in time; leaving them behind");
+    }
+}
+
 /// 接続を処理する関数
 /// 
 /// 処理手順:
-/// 1. HTTPリクエストをパース
+/// 1. 次のリクエストの先頭を待ち（idle_timeout）、HTTPリクエストをパース（read_timeout）
 /// 2. ルーターで処理
 /// 3. レスポンスを送信
-fn handle_connection(mut stream: TcpStream, router: Arc<Router>) -> io::Result<()> {
-    // リクエストのパース
-    let request = HttpRequest::parse(&mut stream)?;
-
-    // ルーターで処理
-    let response = router.handle(request);
+/// 4. キープアライブなら1に戻る（同じバッファ付きリーダーを再利用）
+///
+/// 停止が要求されている場合は、処理中のリクエストに応答してから切断する
+/// （待機中の接続は、ShutdownHandleによって閉じられる）。
+/// 次のリクエストを待つ間にクライアントが切断した場合や、
+/// アイドルタイムアウトに達した場合は正常終了として扱う。
+/// 不正なリクエストにはエラーページを返してから切断する。
+fn handle_connection(
+    stream: TcpStream,
+    context: &ConnectionContext,
+    guard: ConnectionGuard,
+) -> io::Result<()> {
+    let options = &context.options;
+    stream.set_write_timeout(Some(options.write_timeout))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut served = 0;
+
+    loop {
+        // 次のリクエストを待つ（停止が要求されていれば切断）
+        if !guard.wait_for_request() {
+            return Ok(());
+        }
+        reader.get_ref().set_read_timeout(Some(options.idle_timeout))?;
+        match reader.fill_buf() {
+            Ok([]) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => match ParseError::from(e) {
+                ParseError::Io(e) => return Err(e),
+                // クライアントの切断・アイドルタイムアウトは正常終了
+                _ => return Ok(()),
+            },
+        }
+        guard.start_request();
+
+        // リクエストのパース（受信を始めたら、データの間隔はread_timeoutまで待つ）
+        reader.get_ref().set_read_timeout(Some(options.read_timeout))?;
+        let request = match HttpRequest::parse_with_limits(&mut reader, &options.limits) {
+            Ok(request) => request,
+            Err(ParseError::Io(e)) => return Err(e),
+            // 受信の途中で止まったクライアントには408を返して切断
+            Err(e @ ParseError::Timeout) => {
+                return write_response(&mut writer, error_response(context, &e), false);
+            }
+            // クライアントの切断は正常終了
+            Err(e) if e.is_connection_error() => return Ok(()),
+            Err(e) => {
+                // エラーページを返してから切断（以降のバイト列は信用できない）
+                eprintln!("⚠️  Request rejected: {}", e);
+                return write_response(&mut writer, error_response(context, &e), false);
+            }
+        };
+        served += 1;
 
-    // レスポンスを送信
-    let response_bytes = response.to_bytes();
-    stream.write_all(&response_bytes)?;
-    stream.flush()?;
+        // ルーターで処理し、レスポンスを送信（ストリーミングボディは逐次書き出す）
+        let prepared = prepare_response(context, request, served);
+        let keep_alive = prepared.keep_alive;
+        write_response(&mut writer, prepared.response, prepared.head_only)?;
 
-    Ok(())
+        if !keep_alive {
+            return Ok(());
+        }
+    }
 }
 
-// ===== スレッドプール実装 =====
+/// 送信する準備ができたレスポンス
+pub(crate) struct PreparedResponse {
+    pub(crate) response: HttpResponse,
+    /// HEADへの応答か（ヘッダー部のみ送る）
+    pub(crate) head_only: bool,
+    /// 送信後も接続を持続するか
+    pub(crate) keep_alive: bool,
+}
 
-/// ワーカースレッドプール
-/// 
-/// 仕組み:
-/// - 固定数のワーカースレッドを事前に起動
-/// - ジョブ（クロージャ）をキューに追加
-/// - ワーカーはキューからジョブを取り出して実行
-/// - チャネル（mpsc）を使ってスレッド間通信
-struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: mpsc::Sender<Job>,
+/// リクエストを処理し、転送方式と持続可否を決めたレスポンスを返す
+///
+/// served: この接続で処理したリクエスト数（このリクエストを含む）
+pub(crate) fn prepare_response(
+    context: &ConnectionContext,
+    request: HttpRequest,
+    served: usize,
+) -> PreparedResponse {
+    // 持続可否の判定（クライアントの希望と接続あたりの上限）
+    let client_keep_alive = request.wants_keep_alive();
+    let chunked_allowed = request.version == "HTTP/1.1";
+    let head_only = request.method == Method::Head;
+
+    // ルーターで処理（panicしたら500。状態が壊れている可能性があるため切断する）
+    let mut response = handle_request(context, request);
+
+    // 転送方式の決定（長さ不明のボディは切断でしか終端を示せない場合がある）
+    // HEADへの応答はボディを送らないため、ルーターが設定したヘッダーをそのまま使う
+    let framing = (!head_only).then(|| response.prepare_framing(chunked_allowed));
+    let keep_alive = client_keep_alive
+        && served < context.options.max_requests
+        && !response.wants_close()
+        && framing != Some(Framing::CloseDelimited)
+        && !context.shutdown.is_shutdown();
+    response.set_keep_alive(keep_alive);
+
+    PreparedResponse {
+        response,
+        head_only,
+        keep_alive,
+    }
 }
 
-type Job = Box<dyn FnOnce() + Send + 'static>;
+/// ルーターでリクエストを処理する
+///
+/// ハンドラやミドルウェアがpanicした場合はワーカーを巻き込まずに500を返す。
+/// panicのメッセージはログにのみ出力し、レスポンスには含めない。
+fn handle_request(context: &ConnectionContext, request: HttpRequest) -> HttpResponse {
+    let target = format!("{} {}", request.method, request.path);
+    match panic::catch_unwind(AssertUnwindSafe(|| context.router.handle(request))) {
+        Ok(response) => response,
+        Err(payload) => {
+            context.metrics.panics.fetch_add(1, Ordering::Relaxed);
+            eprintln!("💥 Handler panicked ({}): {}", target, panic_message(&*payload));
+
+            let mut response = Problem::new(StatusCode::INTERNAL_SERVER_ERROR).to_response();
+            response.headers.insert("Connection", "close");
+            response
+        }
+    }
+}
 
-impl ThreadPool {
-    /// 新しいスレッドプールを作成
-    /// 
-    /// size: ワーカースレッド数
-    fn new(size: usize) -> Self {
-        assert!(size > 0);
+/// レスポンスをバッファ付きで書き出す（head_onlyならヘッダー部のみ）
+pub(crate) fn write_response(writer: impl Write, response: HttpResponse, head_only: bool) -> io::Result<()> {
+    let mut out = BufWriter::new(writer);
+    if head_only {
+        response.write_head_to(&mut out)?;
+    } else {
+        response.write_to(&mut out)?;
+    }
+    out.flush()
+}
 
-        let (sender, receiver) = mpsc::channel();
-        let receiver = Arc::new(Mutex::new(receiver));
+/// パースエラーに対するエラーレスポンス（送信後に切断する）
+///
+/// カスタムのエラーページがあればそれを使い、なければデフォルトのJSONを返す。
+pub(crate) fn error_response(context: &ConnectionContext, error: &ParseError) -> HttpResponse {
+    let mut response = match &context.error_page {
+        Some(handler) => handler(error),
+        None => error.to_response(),
+    };
+    response.prepare_framing(false);
+    response.set_keep_alive(false);
+    response
+}
 
-        let mut workers = Vec::with_capacity(size);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Response;
+    use std::io::Read;
+    use std::sync::{mpsc, Mutex};
+
+    /// 1接続だけ処理するサーバースレッドを起動
+    fn serve_one(context: ConnectionContext) -> (std::net::SocketAddr, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let guard = context.shutdown.track(&stream).unwrap();
+            handle_connection(stream, &context, guard).unwrap();
+        });
+        (addr, server)
+    }
 
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+    fn test_context(router: Router) -> ConnectionContext {
+        ConnectionContext {
+            router: Arc::new(router),
+            options: ConnectionOptions {
+                idle_timeout: Duration::from_secs(1),
+                read_timeout: Duration::from_secs(1),
+                write_timeout: Duration::from_secs(1),
+                max_requests: 10,
+                limits: RequestLimits::default(),
+            },
+            error_page: None,
+            metrics: Arc::default(),
+            shutdown: ShutdownHandle::new(),
         }
+    }
 
-        println!("🧵 Thread pool initialized with {} workers", size);
+    /// リクエストを送信し、サーバーが切断するまでの応答を全て受け取る
+    fn exchange(addr: std::net::SocketAddr, request: &[u8]) -> String {
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(request).unwrap();
+        let mut received = String::new();
+        client.read_to_string(&mut received).unwrap();
+        received
+    }
 
-        ThreadPool { workers, sender }
+    #[test]
+    fn test_keep_alive_reuses_connection() {
+        let mut router = Router::new();
+        router.get("/ping", |_req| Response::ok("pong"));
+        let (addr, server) = serve_one(test_context(router));
+
+        // 同じ接続で2件送信（2件目で切断を要求）
+        let received = exchange(
+            addr,
+            b"GET /ping HTTP/1.1\r\nHost: x\r\n\r\n\
+              GET /ping HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+        );
+        server.join().unwrap();
+
+        assert_eq!(received.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(received.contains("Connection: keep-alive"));
+        assert!(received.contains("Connection: close"));
     }
 
-    /// ジョブを実行キューに追加
-    fn execute<F>(&self, f: F)
-    where
-        F: FnOnce() + Send + 'static,
-    {
-        let job = Box::new(f);
-        self.sender.send(job).unwrap();
+    #[test]
+    fn test_oversized_header_gets_431() {
+        let mut context = test_context(Router::new());
+        context.options.limits.max_header_bytes = 64;
+        let (addr, server) = serve_one(context);
+
+        let request = format!("GET / HTTP/1.1\r\nX-Big: {}\r\n\r\n", "a".repeat(100));
+        let received = exchange(addr, request.as_bytes());
+        server.join().unwrap();
+
+        assert!(received.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        assert!(received.contains("Connection: close"));
     }
-}
 
-/// ワーカースレッド
-struct Worker {
-    id: usize,
-    thread: Option<thread::JoinHandle<()>>,
-}
+    #[test]
+    fn test_stalled_request_gets_408() {
+        let mut context = test_context(Router::new());
+        context.options.read_timeout = Duration::from_millis(100);
+        let (addr, server) = serve_one(context);
 
-impl Worker {
-    /// 新しいワーカーを作成
-    /// 
-    /// 処理フロー:
-    /// 1. スレッドを起動
-    /// 2. レシーバーからジョブを受信待機
-    /// 3. ジョブを受信したら実行
-    /// 4. 2に戻る（ループ）
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Self {
-        let thread = thread::spawn(move || loop {
-            // ジョブを受信（ブロッキング）
-            let message = receiver.lock().unwrap().recv();
-
-            match message {
-                Ok(job) => {
-                    // デバッグ用ログ（本番では削除推奨）
-                    // println!("Worker {} executing job", id);
-                    job();
-                }
-                Err(_) => {
-                    // チャネルがクローズされたら終了
-                    println!("Worker {} shutting down", id);
-                    break;
-                }
-            }
-        });
+        // ヘッダーの途中で送信が止まる
+        let received = exchange(addr, b"GET / HTTP/1.1\r\nHost: x\r\nX-Par");
+        server.join().unwrap();
 
-        Worker {
-            id,
-            thread: Some(thread),
-        }
+        assert!(received.starts_with("HTTP/1.1 408 Request Timeout\r\n"), "{}", received);
+        assert!(received.contains("Connection: close"));
     }
-}
 
-impl Drop for ThreadPool {
-    /// スレッドプールが破棄される際に全ワーカーを停止
-    fn drop(&mut self) {
-        println!("\n🛑 Shutting down thread pool...");
+    #[test]
+    fn test_custom_error_page() {
+        let mut context = test_context(Router::new());
+        context.error_page = Some(Box::new(|err| {
+            HttpResponse::new(err.status()).with_body("custom")
+        }));
+        let (addr, server) = serve_one(context);
+
+        let received = exchange(addr, b"GET / HTTP/3.0\r\n\r\n");
+        server.join().unwrap();
+
+        assert!(received.starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+        assert!(received.ends_with("custom"));
+    }
 
-        // センダーをドロップしてチャネルをクローズ
-        drop(&self.sender);
+    #[test]
+    fn test_head_sends_headers_without_body() {
+        let mut router = Router::new();
+        router.get("/ping", |_req| Response::ok("pong"));
+        let (addr, server) = serve_one(test_context(router));
+
+        // HEADのあとに続くGETが正しく読めれば、ボディは送られていない
+        let received = exchange(
+            addr,
+            b"HEAD /ping HTTP/1.1\r\n\r\nGET /ping HTTP/1.1\r\nConnection: close\r\n\r\n",
+        );
+        server.join().unwrap();
+
+        let (head, get) = received.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains("Content-Length: 4"));
+        assert!(get.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(get.ends_with("\r\n\r\npong"));
+    }
 
-        // 全ワーカーの終了を待つ
-        for worker in &mut self.workers {
-            if let Some(thread) = worker.thread.take() {
-                thread.join().unwrap();
-            }
-        }
+    #[test]
+    fn test_handler_panic_becomes_500() {
+        let mut router = Router::new();
+        router.get("/boom", |_req| -> Response { panic!("secret detail") });
+        let context = test_context(router);
+        let metrics = Arc::clone(&context.metrics);
+        let (addr, server) = serve_one(context);
+
+        // panic後は切断するため、2件目のリクエストは処理されない
+        let received = exchange(
+            addr,
+            b"GET /boom HTTP/1.1\r\n\r\nGET /boom HTTP/1.1\r\n\r\n",
+        );
+        server.join().unwrap();
+
+        assert!(received.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(received.contains("Connection: close"));
+        assert!(!received.contains("secret detail"));
+        assert_eq!(received.matches("HTTP/1.1 500").count(), 1);
+        assert_eq!(metrics.panics(), 1);
+    }
 
-        println!("✅ All workers stopped");
+    /// 空きポートでサーバーを起動する
+    fn start_server(server: Server) -> (std::net::SocketAddr, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let thread = thread::spawn(move || server.serve(listener).unwrap());
+        (addr, thread)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_graceful_shutdown_drains_in_flight_requests() {
+        let mut router = Router::new();
+        router.get("/ping", |_req| Response::ok("pong"));
+        router.get("/slow", |_req| {
+            thread::sleep(Duration::from_millis(300));
+            Response::ok("done")
+        });
+        let server = Server::new("unused", router);
+        let shutdown = server.shutdown_handle();
+        let (addr, thread) = start_server(server);
+
+        // キープアライブで待機中の接続
+        let mut idle = TcpStream::connect(addr).unwrap();
+        idle.write_all(b"GET /ping HTTP/1.1\r\n\r\n").unwrap();
+        let mut buf = [0; 1024];
+        assert!(idle.read(&mut buf).unwrap() > 0);
+
+        // 処理中のリクエスト
+        let slow = thread::spawn(move || exchange(addr, b"GET /slow HTTP/1.1\r\n\r\n"));
+        while shutdown.active_connections() < 2 {
+            thread::yield_now();
+        }
+        thread::sleep(Duration::from_millis(50));
+        shutdown.shutdown();
+
+        // 処理中のリクエストは完了し、Connection: closeで閉じられる
+        let received = slow.join().unwrap();
+        assert!(received.starts_with("HTTP/1.1 200 OK\r\n"), "{}", received);
+        assert!(received.contains("Connection: close"));
+        assert!(received.ends_with("done"));
+
+        // 待機中の接続は閉じられる
+        let mut rest = Vec::new();
+        idle.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+
+        thread.join().unwrap();
+        assert_eq!(shutdown.active_connections(), 0);
+        assert!(TcpStream::connect(addr).is_err());
+    }
 
     #[test]
-    fn test_thread_pool_creation() {
-        let pool = ThreadPool::new(4);
-        assert_eq!(pool.workers.len(), 4);
+    fn test_drain_timeout_closes_stuck_connections() {
+        let mut router = Router::new();
+        router.get("/stuck", |_req| {
+            thread::sleep(Duration::from_secs(3));
+            Response::ok("late")
+        });
+        let mut server = Server::new("unused", router);
+        server.drain_timeout(Duration::from_millis(50));
+        let shutdown = server.shutdown_handle();
+        let (addr, thread) = start_server(server);
+
+        let stuck = thread::spawn(move || exchange(addr, b"GET /stuck HTTP/1.1\r\n\r\n"));
+        while shutdown.active_connections() < 1 {
+            thread::yield_now();
+        }
+        thread::sleep(Duration::from_millis(50));
+        let stopping = Instant::now();
+        shutdown.shutdown();
+
+        // 期限を過ぎたため、レスポンスを受け取る前に切断される
+        assert_eq!(stuck.join().unwrap(), "");
+        // ハンドラが戻るのを待たずにサーバーは停止する
+        thread.join().unwrap();
+        assert!(stopping.elapsed() < Duration::from_secs(1));
     }
 
     #[test]
-    fn test_thread_pool_execute() {
-        let pool = ThreadPool::new(2);
-        let counter = Arc::new(Mutex::new(0));
-        
-        for _ in 0..10 {
-            let counter = Arc::clone(&counter);
-            pool.execute(move || {
-                let mut num = counter.lock().unwrap();
-                *num += 1;
-            });
-        }
-
-        // ジョブが完了するまで少し待つ
-        thread::sleep(std::time::Duration::from_millis(100));
-        
-        let final_count = *counter.lock().unwrap();
-        assert_eq!(final_count, 10);
+    fn test_invalid_config_is_rejected_before_serving() {
+        // with_configは検証しないため、serveで不正な値を報告する
+        let config = ServerConfig {
+            queue_capacity: 0,
+            ..ServerConfig::default()
+        };
+        let server = Server::with_config(config, Router::new());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let err = server.serve(listener).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("queue_capacity must be at least 1"));
+    }
+
+    #[test]
+    fn test_full_queue_rejects_with_503() {
+        let (entered, handler_entered) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let (entered, released) = (Mutex::new(entered), Mutex::new(released));
+        let mut router = Router::new();
+        router.get("/block", move |_req| {
+            entered.lock().unwrap().send(()).unwrap();
+            released.lock().unwrap().recv().unwrap();
+            Response::ok("done")
+        });
+        let config = ServerConfig::builder()
+            .workers(1)
+            .queue_capacity(1)
+            .overload_policy(OverloadPolicy::Reject)
+            .retry_after(Duration::from_millis(1500))
+            .build()
+            .unwrap();
+        let mut server = Server::with_config(config, router);
+        let metrics = Arc::new(ServerMetrics::new());
+        server.metrics(Arc::clone(&metrics));
+        let shutdown = server.shutdown_handle();
+        let (addr, thread) = start_server(server);
+
+        // ワーカーを処理中にし、キューを1件で満杯にする
+        let busy = thread::spawn(move || exchange(addr, b"GET /block HTTP/1.1\r\nConnection: close\r\n\r\n"));
+        handler_entered.recv_timeout(Duration::from_secs(1)).unwrap();
+        let mut queued = TcpStream::connect(addr).unwrap();
+        while metrics.queue_depth() < 1 {
+            thread::yield_now();
+        }
+
+        // 次の接続は受付スレッドで断られる
+        let received = exchange(addr, b"GET /block HTTP/1.1\r\n\r\n");
+        assert!(received.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{}", received);
+        assert!(received.contains("Retry-After: 2\r\n"));
+        assert_eq!(metrics.rejected_connections(), 1);
+
+        // 待っていた接続は、ワーカーが空けば処理される
+        release.send(()).unwrap();
+        assert!(busy.join().unwrap().ends_with("done"));
+        queued.write_all(b"GET /block HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        handler_entered.recv_timeout(Duration::from_secs(1)).unwrap();
+        release.send(()).unwrap();
+        let mut rest = String::new();
+        queued.read_to_string(&mut rest).unwrap();
+        assert!(rest.ends_with("done"));
+        assert_eq!(metrics.queue_depth(), 0);
+
+        shutdown.shutdown();
+        thread.join().unwrap();
     }
 }
@@ -0,0 +1,206 @@
+// src/shutdown.rs
+//
+// 【処理概要】
+// サーバーのグレースフルシャットダウンを実装。
+// 停止の要求を受け付けるハンドルと、処理中の接続の一覧を管理する。
+//
+// 【主な機能】
+// - ShutdownHandle: 別スレッドやシグナルからサーバーを停止させる
+// - 接続の追跡（リクエスト待ちのアイドル状態か、処理中か）
+// - アイドル接続の切断と、期限を過ぎた接続の強制切断
+//
+// 【実装内容】
+// 1. 停止の要求はフラグで伝え、受付ループと各接続が確認する
+// 2. 接続は受付時に登録し、処理を終えたらガードのDropで登録を外す
+// 3. アイドル接続はソケットを閉じて、リクエスト待ちの読み取りを終わらせる
+// 4. イベントループの接続は数えるだけにする（ソケットはイベントループが自ら閉じるため、
+//    複製してファイルディスクリプタを増やさない）
+//
+// 使用例:
+//   let shutdown = server.shutdown_handle();
+//   thread::spawn(move || {
+//       thread::sleep(Duration::from_secs(60));
+//       shutdown.shutdown(); // 受付を止め、処理中のリクエストを待って終了
+//   });
+//   server.run()?;
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// サーバーを停止させるハンドル（複製して複数のスレッドから使える）
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    requested: AtomicBool,
+    next_id: AtomicU64,
+    connections: Mutex<HashMap<u64, Tracked>>,
+    /// 数えるだけの接続（イベントループが管理する接続）の数
+    counted: AtomicUsize,
+}
+
+/// 追跡中の接続
+struct Tracked {
+    /// 切断用に複製したソケット
+    stream: TcpStream,
+    /// 次のリクエストを待っているか（処理中でなければtrue）
+    idle: bool,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new() -> Self {
+        ShutdownHandle::default()
+    }
+
+    /// 停止を要求する（すぐに戻り、サーバーは新しい接続の受付を止める）
+    ///
+    /// 処理中のリクエストは期限（Server::drain_timeout）まで待ち、
+    /// キープアライブで待機中の接続は閉じる。
+    pub fn shutdown(&self) {
+        self.inner.requested.store(true, Ordering::SeqCst);
+    }
+
+    /// 停止が要求されたか
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    /// 開いている接続の数（受付済みでワーカー待ちのものを含む）
+    pub fn active_connections(&self) -> usize {
+        self.inner.connections.lock().unwrap().len() + self.inner.counted.load(Ordering::SeqCst)
+    }
+
+    /// 接続を数える（切断は呼び出し元が行うため、ソケットは複製しない）
+    pub(crate) fn count(&self) -> CountedConnection {
+        self.inner.counted.fetch_add(1, Ordering::SeqCst);
+        CountedConnection { handle: self.clone() }
+    }
+
+    /// 接続を登録する（最初はアイドル状態）
+    pub(crate) fn track(&self, stream: &TcpStream) -> io::Result<ConnectionGuard> {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let tracked = Tracked {
+            stream: stream.try_clone()?,
+            idle: true,
+        };
+        self.inner.connections.lock().unwrap().insert(id, tracked);
+        Ok(ConnectionGuard {
+            id,
+            handle: self.clone(),
+        })
+    }
+
+    /// アイドル状態の接続を閉じる（リクエスト待ちの読み取りがEOFで終わる）
+    pub(crate) fn close_idle(&self) {
+        let connections = self.inner.connections.lock().unwrap();
+        for tracked in connections.values().filter(|tracked| tracked.idle) {
+            let _ = tracked.stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    /// 全ての接続を閉じる（処理中のレスポンスは送られない）
+    pub(crate) fn close_all(&self) {
+        let connections = self.inner.connections.lock().unwrap();
+        for tracked in connections.values() {
+            let _ = tracked.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// 登録した接続の状態を更新し、Dropで登録を外すガード
+pub(crate) struct ConnectionGuard {
+    id: u64,
+    handle: ShutdownHandle,
+}
+
+impl ConnectionGuard {
+    /// 次のリクエストを待つ状態にする
+    ///
+    /// 停止が要求されていればfalse（接続を閉じる）。状態の変更後に確認するため、
+    /// close_idleとの間で閉じ損ねることはない。
+    pub(crate) fn wait_for_request(&self) -> bool {
+        self.set_idle(true);
+        !self.handle.is_shutdown()
+    }
+
+    /// リクエストを受け取り、処理中の状態にする
+    pub(crate) fn start_request(&self) {
+        self.set_idle(false);
+    }
+
+    fn set_idle(&self, idle: bool) {
+        let mut connections = self.handle.inner.connections.lock().unwrap();
+        if let Some(tracked) = connections.get_mut(&self.id) {
+            tracked.idle = idle;
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Ok(mut connections) = self.handle.inner.connections.lock() {
+            connections.remove(&self.id);
+        }
+    }
+}
+
+/// 数えている接続（Dropで数から外す）
+pub(crate) struct CountedConnection {
+    handle: ShutdownHandle,
+}
+
+impl Drop for CountedConnection {
+    fn drop(&mut self) {
+        self.handle.inner.counted.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_close_idle_keeps_busy_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut idle_client = TcpStream::connect(addr).unwrap();
+        let (idle, _) = listener.accept().unwrap();
+        let mut busy_client = TcpStream::connect(addr).unwrap();
+        let (busy, _) = listener.accept().unwrap();
+
+        let handle = ShutdownHandle::new();
+        let idle_guard = handle.track(&idle).unwrap();
+        let busy_guard = handle.track(&busy).unwrap();
+        busy_guard.start_request();
+        assert_eq!(handle.active_connections(), 2);
+
+        handle.shutdown();
+        assert!(!idle_guard.wait_for_request());
+        handle.close_idle();
+
+        // アイドル接続だけが閉じられる
+        let mut buf = [0; 1];
+        assert_eq!(idle_client.read(&mut buf).unwrap(), 0);
+        busy_client
+            .set_read_timeout(Some(std::time::Duration::from_millis(50)))
+            .unwrap();
+        assert!(busy_client.read(&mut buf).is_err());
+
+        // 数えるだけの接続（イベントループ）も開いている接続に含める
+        let counted = handle.count();
+        assert_eq!(handle.active_connections(), 3);
+
+        drop(idle_guard);
+        drop(busy_guard);
+        drop(counted);
+        assert_eq!(handle.active_connections(), 0);
+    }
+}
@@ -0,0 +1,275 @@
+// src/sys.rs
+//
+// 【処理概要】
+// 標準ライブラリにないOSの機能（Unixのシステムコール）を呼び出す。
+// 外部クレートを使わないため、必要な関数だけをextern "C"で宣言する。
+//
+// 【主な機能】
+// - ソケット（複数可）が読み取り可能になるまで、タイムアウト付きで待つ（poll）
+// - SIGINT / SIGTERM の受信をフラグで知らせる（signal）
+// - 多数のソケットの読み書き可能を待つ（epoll。Linuxのみ）
+//
+// 【実装内容】
+// 1. Unix以外では、待機はスリープ、シグナルは何もしない実装に置き換える
+// 2. シグナルハンドラではフラグを立てるだけにし、2回目は既定の動作（終了）に戻す
+// 3. epollはレベルトリガーで使い、ソケットごとに呼び出し側が決めたトークンで識別する
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// SIGINT / SIGTERM を受信したか
+static SIGNALED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod ffi {
+    use std::os::raw::{c_int, c_short};
+
+    pub const POLLIN: c_short = 0x1;
+    pub const SIGINT: c_int = 2;
+    pub const SIGTERM: c_int = 15;
+    pub const SIG_DFL: usize = 0;
+
+    /// nfds_t（Linuxではunsigned long、macOSやBSDではunsigned int）
+    #[cfg(target_os = "linux")]
+    pub type NfdsT = std::os::raw::c_ulong;
+    #[cfg(not(target_os = "linux"))]
+    pub type NfdsT = std::os::raw::c_uint;
+
+    #[repr(C)]
+    pub struct PollFd {
+        pub fd: c_int,
+        pub events: c_short,
+        pub revents: c_short,
+    }
+
+    #[cfg(target_os = "linux")]
+    pub const EPOLLIN: u32 = 0x1;
+    #[cfg(target_os = "linux")]
+    pub const EPOLLOUT: u32 = 0x4;
+    #[cfg(target_os = "linux")]
+    pub const EPOLLERR: u32 = 0x8;
+    #[cfg(target_os = "linux")]
+    pub const EPOLLHUP: u32 = 0x10;
+    #[cfg(target_os = "linux")]
+    pub const EPOLLRDHUP: u32 = 0x2000;
+    #[cfg(target_os = "linux")]
+    pub const EPOLL_CLOEXEC: c_int = 0o2000000;
+    #[cfg(target_os = "linux")]
+    pub const EPOLL_CTL_ADD: c_int = 1;
+    #[cfg(target_os = "linux")]
+    pub const EPOLL_CTL_DEL: c_int = 2;
+    #[cfg(target_os = "linux")]
+    pub const EPOLL_CTL_MOD: c_int = 3;
+
+    /// struct epoll_event（x86_64ではpackedで定義されている）
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(target_arch = "x86_64", repr(C, packed))]
+    #[cfg_attr(not(target_arch = "x86_64"), repr(C))]
+    #[derive(Clone, Copy)]
+    pub struct EpollEvent {
+        pub events: u32,
+        pub data: u64,
+    }
+
+    extern "C" {
+        pub fn poll(fds: *mut PollFd, nfds: NfdsT, timeout: c_int) -> c_int;
+        pub fn signal(signum: c_int, handler: usize) -> usize;
+        #[cfg(target_os = "linux")]
+        pub fn epoll_create1(flags: c_int) -> c_int;
+        #[cfg(target_os = "linux")]
+        pub fn epoll_ctl(epfd: c_int, op: c_int, fd: c_int, event: *mut EpollEvent) -> c_int;
+        #[cfg(target_os = "linux")]
+        pub fn epoll_wait(epfd: c_int, events: *mut EpollEvent, maxevents: c_int, timeout: c_int) -> c_int;
+    }
+}
+
+/// いずれかのソケットが読み取り可能（リスナーなら接続待ちあり）になるまで待つ
+///
+/// タイムアウトした場合やシグナルで中断された場合はfalse。
+#[cfg(unix)]
+pub(crate) fn wait_readable<S: std::os::fd::AsRawFd>(sockets: &[S], timeout: Duration) -> io::Result<bool> {
+    let mut fds: Vec<ffi::PollFd> = sockets
+        .iter()
+        .map(|socket| ffi::PollFd {
+            fd: socket.as_raw_fd(),
+            events: ffi::POLLIN,
+            revents: 0,
+        })
+        .collect();
+    let timeout = timeout_millis(timeout);
+    // SAFETY: fdsは要素数とともに渡し、呼び出し中だけ参照される
+    let ready = unsafe { ffi::poll(fds.as_mut_ptr(), fds.len() as _, timeout) };
+    if ready < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::Interrupted {
+            return Ok(false);
+        }
+        return Err(err);
+    }
+    Ok(ready > 0)
+}
+
+/// Unix以外: 待ってから読み取りを試させる（呼び出し側はWouldBlockを扱う）
+#[cfg(not(unix))]
+pub(crate) fn wait_readable<S>(_sockets: &[S], timeout: Duration) -> io::Result<bool> {
+    std::thread::sleep(timeout.min(Duration::from_millis(10)));
+    Ok(true)
+}
+
+/// タイムアウトをミリ秒に変換（poll・epoll_waitの引数）
+#[cfg(unix)]
+fn timeout_millis(timeout: Duration) -> i32 {
+    timeout.as_millis().min(i32::MAX as u128) as i32
+}
+
+/// epollで待つイベントの種類
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Interest {
+    /// 読み取り可能（相手の切断を含む）
+    Read,
+    /// 書き込み可能
+    Write,
+    /// どちらも待たない（エラーと切断のみ通知される）
+    None,
+}
+
+/// epoll_waitで受け取ったイベント
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Event {
+    pub(crate) token: u64,
+    pub(crate) readable: bool,
+    pub(crate) writable: bool,
+    /// エラー・切断（読み書きを試みて結果を確認する）
+    pub(crate) closed: bool,
+}
+
+/// epollのインスタンス（Dropで閉じる）
+#[cfg(target_os = "linux")]
+pub(crate) struct Epoll {
+    fd: std::os::fd::OwnedFd,
+    buffer: Vec<ffi::EpollEvent>,
+}
+
+#[cfg(target_os = "linux")]
+impl Epoll {
+    /// 1回のwaitで受け取る最大イベント数
+    const MAX_EVENTS: usize = 1024;
+
+    pub(crate) fn new() -> io::Result<Self> {
+        use std::os::fd::FromRawFd;
+
+        // SAFETY: 引数はフラグのみ
+        let fd = unsafe { ffi::epoll_create1(ffi::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Epoll {
+            // SAFETY: epoll_create1が返した、他で所有されていないディスクリプタ
+            fd: unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) },
+            buffer: Vec::with_capacity(Self::MAX_EVENTS),
+        })
+    }
+
+    /// ソケットを登録する
+    pub(crate) fn add(&self, socket: &impl std::os::fd::AsRawFd, token: u64, interest: Interest) -> io::Result<()> {
+        self.control(ffi::EPOLL_CTL_ADD, socket.as_raw_fd(), token, interest)
+    }
+
+    /// 登録済みのソケットの待つイベントを変更する
+    pub(crate) fn modify(&self, socket: &impl std::os::fd::AsRawFd, token: u64, interest: Interest) -> io::Result<()> {
+        self.control(ffi::EPOLL_CTL_MOD, socket.as_raw_fd(), token, interest)
+    }
+
+    /// 登録を外す（ソケットを閉じれば自動的に外れる）
+    pub(crate) fn delete(&self, socket: &impl std::os::fd::AsRawFd) -> io::Result<()> {
+        self.control(ffi::EPOLL_CTL_DEL, socket.as_raw_fd(), 0, Interest::None)
+    }
+
+    fn control(&self, op: std::os::raw::c_int, fd: std::os::raw::c_int, token: u64, interest: Interest) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+
+        let events = match interest {
+            Interest::Read => ffi::EPOLLIN | ffi::EPOLLRDHUP,
+            Interest::Write => ffi::EPOLLOUT,
+            Interest::None => 0,
+        };
+        let mut event = ffi::EpollEvent { events, data: token };
+        // SAFETY: eventは呼び出し中だけ参照される（DELでは無視される）
+        if unsafe { ffi::epoll_ctl(self.fd.as_raw_fd(), op, fd, &mut event) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// イベントを待つ（タイムアウトした場合やシグナルで中断された場合は空）
+    pub(crate) fn wait(&mut self, events: &mut Vec<Event>, timeout: Duration) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+
+        events.clear();
+        self.buffer.clear();
+        // SAFETY: bufferの容量分だけ書き込まれ、書き込まれた数をset_lenで反映する
+        let ready = unsafe {
+            ffi::epoll_wait(
+                self.fd.as_raw_fd(),
+                self.buffer.as_mut_ptr(),
+                Self::MAX_EVENTS as std::os::raw::c_int,
+                timeout_millis(timeout),
+            )
+        };
+        if ready < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(());
+            }
+            return Err(err);
+        }
+        // SAFETY: 先頭のready個はepoll_waitが初期化している
+        unsafe { self.buffer.set_len(ready as usize) };
+
+        events.extend(self.buffer.iter().map(|event| {
+            let flags = event.events;
+            Event {
+                token: event.data,
+                readable: flags & ffi::EPOLLIN != 0,
+                writable: flags & ffi::EPOLLOUT != 0,
+                closed: flags & (ffi::EPOLLERR | ffi::EPOLLHUP | ffi::EPOLLRDHUP) != 0,
+            }
+        }));
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signum: std::os::raw::c_int) {
+    SIGNALED.store(true, Ordering::SeqCst);
+    // 2回目のシグナルでは待たずに終了できるよう、既定の動作に戻す
+    // SAFETY: signalはシグナルハンドラ内から呼び出せる
+    unsafe {
+        ffi::signal(signum, ffi::SIG_DFL);
+    }
+}
+
+/// SIGINT / SIGTERM を受信したらフラグを立てるようにする
+#[cfg(unix)]
+pub(crate) fn install_signal_handlers() {
+    // 以前のサーバーが受信したシグナルで、新しいサーバーがすぐに停止しないようにする
+    SIGNALED.store(false, Ordering::SeqCst);
+    let handler = on_signal as extern "C" fn(std::os::raw::c_int) as usize;
+    // SAFETY: ハンドラはアトミック変数の書き込みとsignalの呼び出しのみを行う
+    unsafe {
+        ffi::signal(ffi::SIGINT, handler);
+        ffi::signal(ffi::SIGTERM, handler);
+    }
+}
+
+/// Unix以外: シグナルは扱わない
+#[cfg(not(unix))]
+pub(crate) fn install_signal_handlers() {}
+
+/// SIGINT / SIGTERM を受信したか
+pub(crate) fn signal_received() -> bool {
+    SIGNALED.load(Ordering::SeqCst)
+}
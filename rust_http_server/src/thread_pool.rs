@@ -0,0 +1,1091 @@
+// src/thread_pool.rs
+//
+// 【処理概要】
+// 再利用できるワーカースレッドプールを実装。
+// サーバーの接続処理のほか、グラフ計算などの並列処理にも使える。
+//
+// 【主な機能】
+// - execute: ジョブを投げっぱなしで実行
+// - submit: 結果（またはpanic）を受け取れるJoinHandleを返す
+// - scope: 呼び出し元の変数を借用するジョブ（スコープを抜ける前に全て完了する）
+// - wait_idle: 投入済みの全ジョブの完了を待つ
+// - shutdown_timeout: 期限まで待って停止する（終わらないワーカーは切り離す）
+// - 負荷に応じたワーカー数の増減（最小数〜最大数）と、名前付きスレッド
+// - 統計情報（PoolMetrics: キューの長さ、稼働中のワーカー数など）
+//
+// 【実装内容】
+// 1. ジョブは容量付きのチャネル（mpsc::sync_channel）でワーカーに渡す（満杯なら空くまで待つ）
+// 2. 空いているワーカーがなければ最大数まで増やし、一定時間アイドルなら最小数まで減らす
+// 3. ジョブのpanicはワーカー内で捕捉し、それでも停止したワーカーはその場で自ら起動し直す
+// 4. スコープ付きのジョブは完了を数え、scopeは全ての完了を待ってから戻る
+//
+// 使用例:
+//   let pool = ThreadPool::new(4);
+//   let handle = pool.submit(|| shortest_paths(&graph));
+//   let distances = handle.join().unwrap();
+//
+//   let mut results = vec![0; 8];
+//   pool.scope(|s| {
+//       for (i, slot) in results.iter_mut().enumerate() {
+//           s.execute(move || *slot = i * i);
+//       }
+//   });
+
+use std::any::Any;
+use std::io;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub(crate) type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// shutdown_timeoutでワーカーの終了を確認する間隔
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// スレッドプールの統計情報（実行中に参照できる）
+#[derive(Debug, Default)]
+pub struct PoolMetrics {
+    panics: AtomicU64,
+    worker_restarts: AtomicU64,
+    queue_depth: AtomicUsize,
+    workers: AtomicUsize,
+    busy_workers: AtomicUsize,
+}
+
+impl PoolMetrics {
+    /// 全て0の統計情報を作成
+    pub fn new() -> Self {
+        PoolMetrics::default()
+    }
+
+    /// executeで投入したジョブがpanicした回数（submit・scopeのpanicは呼び出し元に渡す）
+    pub fn panics(&self) -> u64 {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    /// 停止したワーカースレッドを起動し直した回数
+    pub fn worker_restarts(&self) -> u64 {
+        self.worker_restarts.load(Ordering::Relaxed)
+    }
+
+    /// ワーカーの空きを待っているジョブの数
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth.load(Ordering::Relaxed)
+    }
+
+    /// 起動中のワーカースレッド数
+    pub fn workers(&self) -> usize {
+        self.workers.load(Ordering::Relaxed)
+    }
+
+    /// ジョブを実行中のワーカースレッド数
+    pub fn busy_workers(&self) -> usize {
+        self.busy_workers.load(Ordering::Relaxed)
+    }
+
+    /// ワーカーの稼働率（実行中のワーカー数 / 起動中のワーカー数。0.0〜1.0）
+    pub fn utilization(&self) -> f64 {
+        match self.workers() {
+            0 => 0.0,
+            workers => (self.busy_workers() as f64 / workers as f64).min(1.0),
+        }
+    }
+}
+
+/// スレッドプールのビルダー
+///
+/// 例:
+///   let pool = ThreadPool::builder()
+///       .min_workers(2)
+///       .max_workers(16)
+///       .name("graph")
+///       .build()?;
+#[derive(Debug)]
+pub struct ThreadPoolBuilder {
+    min_workers: usize,
+    max_workers: usize,
+    queue_capacity: usize,
+    idle_timeout: Duration,
+    name: String,
+    metrics: Option<Arc<PoolMetrics>>,
+}
+
+impl Default for ThreadPoolBuilder {
+    fn default() -> Self {
+        let workers = thread::available_parallelism().map_or(4, |n| n.get());
+        ThreadPoolBuilder {
+            min_workers: workers,
+            max_workers: workers,
+            queue_capacity: 1024,
+            idle_timeout: Duration::from_secs(30),
+            name: "pool".to_string(),
+            metrics: None,
+        }
+    }
+}
+
+impl ThreadPoolBuilder {
+    /// ワーカースレッド数（増減しない固定数にする。既定はCPU数）
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.min_workers = workers;
+        self.max_workers = workers;
+        self
+    }
+
+    /// 常に起動しておくワーカースレッド数
+    pub fn min_workers(mut self, workers: usize) -> Self {
+        self.min_workers = workers;
+        self
+    }
+
+    /// 負荷に応じて増やすワーカースレッドの最大数
+    pub fn max_workers(mut self, workers: usize) -> Self {
+        self.max_workers = workers;
+        self
+    }
+
+    /// 実行待ちのジョブを保持するキューの容量（満杯ならexecuteが空くまで待つ）
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = capacity;
+        self
+    }
+
+    /// 最小数を超えるワーカーが終了するまでのアイドル時間
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// スレッド名の接頭辞（スレッド名は "<name>-<番号>"）
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// 統計情報の記録先（他の統計情報と共有する場合に渡す）
+    pub fn metrics(mut self, metrics: Arc<PoolMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// スレッドプールを作成し、最小数のワーカーを起動する
+    ///
+    /// ワーカー数やキュー容量が0、または最大数が最小数より小さい場合は
+    /// ErrorKind::InvalidInputのエラーを返す。
+    pub fn build(self) -> io::Result<ThreadPool> {
+        let invalid = |message: &str| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        if self.min_workers == 0 {
+            return invalid("min_workers must be at least 1");
+        }
+        if self.max_workers < self.min_workers {
+            return invalid("max_workers must not be less than min_workers");
+        }
+        if self.queue_capacity == 0 {
+            return invalid("queue_capacity must be at least 1");
+        }
+
+        let (sender, receiver) = mpsc::sync_channel(self.queue_capacity);
+        let shared = Arc::new(Shared {
+            receiver: Mutex::new(receiver),
+            metrics: self.metrics.unwrap_or_default(),
+            min_workers: self.min_workers,
+            idle_timeout: self.idle_timeout,
+            name: self.name,
+            pending: Mutex::new(0),
+            idle: Condvar::new(),
+            workers: Mutex::new(Workers {
+                list: Vec::with_capacity(self.max_workers),
+                next_id: 0,
+            }),
+        });
+
+        let pool = ThreadPool {
+            sender: Some(sender),
+            shared,
+            max_workers: self.max_workers,
+        };
+        {
+            let mut workers = pool.shared.workers.lock().unwrap();
+            for _ in 0..self.min_workers {
+                pool.shared.spawn_worker(&mut workers)?;
+            }
+        }
+        Ok(pool)
+    }
+}
+
+/// ワーカースレッドプール
+///
+/// 仕組み:
+/// - 最小数のワーカースレッドを事前に起動
+/// - ジョブ（クロージャ）をキューに追加
+/// - ワーカーはキューからジョブを取り出して実行
+/// - チャネル（mpsc）を使ってスレッド間通信（容量を超えたら空くまで待つ）
+/// - 空いているワーカーがなければ最大数まで増やし、一定時間アイドルなら最小数まで減らす
+/// - ジョブのpanicはワーカー内で捕捉し、それでも停止したワーカーは自ら起動し直す
+///
+/// 破棄すると、キューに残ったジョブを全て実行してからワーカーを停止する。
+pub struct ThreadPool {
+    sender: Option<mpsc::SyncSender<Job>>,
+    shared: Arc<Shared>,
+    max_workers: usize,
+}
+
+/// 起動したワーカーの一覧
+struct Workers {
+    list: Vec<Worker>,
+    next_id: usize,
+}
+
+/// プールと全ワーカーで共有する情報
+struct Shared {
+    receiver: Mutex<mpsc::Receiver<Job>>,
+    metrics: Arc<PoolMetrics>,
+    /// アイドルでも終了しないワーカー数
+    min_workers: usize,
+    /// この時間ジョブがなければ、最小数を超えるワーカーは終了する
+    idle_timeout: Duration,
+    /// スレッド名の接頭辞
+    name: String,
+    /// 投入されてまだ完了していないジョブの数（wait_idle用）
+    pending: Mutex<usize>,
+    idle: Condvar,
+    /// 起動したワーカー（停止したワーカーが自身の代わりを登録するため共有する）
+    workers: Mutex<Workers>,
+}
+
+impl ThreadPool {
+    /// ワーカー数が固定のスレッドプールを作成
+    ///
+    /// size: ワーカースレッド数（0、またはスレッドを起動できなければpanic）
+    pub fn new(size: usize) -> Self {
+        ThreadPool::builder()
+            .workers(size)
+            .build()
+            .expect("failed to create thread pool")
+    }
+
+    /// 設定を指定して作成するためのビルダー
+    pub fn builder() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::default()
+    }
+
+    /// 統計情報
+    pub fn metrics(&self) -> &Arc<PoolMetrics> {
+        &self.shared.metrics
+    }
+
+    /// ジョブを実行キューに追加（キューが満杯なら空くまでブロック）
+    ///
+    /// 停止したプールに追加した場合は、ジョブを実行せずに破棄してpanicする。
+    ///
+    /// 停止したまま残っているワーカーがあれば、先に起動し直してワーカー数を保つ。
+    /// 空いているワーカーがなければ、最大数まで新しいワーカーを起動する。
+    /// キューの長さ（PoolMetrics::queue_depth）はワーカーが取り出すまで数える。
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.prepare_workers();
+
+        // ワーカーが取り出す前に数える（送信に失敗したら元に戻す）
+        self.shared.queue_job();
+        let job: Job = Box::new(f);
+        let sent = match &self.sender {
+            Some(sender) => sender.send(job).is_ok(),
+            None => false,
+        };
+        if !sent {
+            self.shared.cancel_job();
+            panic!("thread pool is shutting down");
+        }
+    }
+
+    /// ジョブを実行キューに追加する（キューが満杯なら待たずに、ジョブをそのまま返す）
+    ///
+    /// イベントループのように、ブロックできないスレッドから投入するために使う。
+    /// 停止したプールに追加した場合はpanicする。
+    pub(crate) fn try_execute(&self, job: Job) -> Result<(), Job> {
+        self.prepare_workers();
+
+        self.shared.queue_job();
+        let result = match &self.sender {
+            Some(sender) => sender.try_send(job),
+            None => Err(mpsc::TrySendError::Disconnected(job)),
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(mpsc::TrySendError::Full(job)) => {
+                self.shared.cancel_job();
+                Err(job)
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                self.shared.cancel_job();
+                panic!("thread pool is shutting down");
+            }
+        }
+    }
+
+    /// 停止したワーカーを起動し直し、空いているワーカーがなければ最大数まで増やす
+    fn prepare_workers(&self) {
+        let mut workers = self.shared.workers.lock().unwrap();
+        self.shared.restart_dead_workers(&mut workers);
+
+        // 一覧には終了処理中のワーカーも残っているため、稼働中のワーカー数と比べる
+        let metrics = &self.shared.metrics;
+        let idle = metrics.workers().saturating_sub(metrics.busy_workers());
+        if metrics.queue_depth() >= idle && metrics.workers() < self.max_workers {
+            // 起動できなくても、既存のワーカーで処理を続ける
+            if let Err(e) = self.shared.spawn_worker(&mut workers) {
+                eprintln!("⚠️  Failed to spawn worker: {}", e);
+            }
+        }
+    }
+
+    /// ジョブを実行キューに追加し、結果を受け取るハンドルを返す
+    ///
+    /// ジョブのpanicはプールの統計には数えず、JoinHandle::joinのErrとして返す。
+    pub fn submit<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.execute(move || {
+            let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(f)));
+        });
+        JoinHandle { receiver }
+    }
+
+    /// 呼び出し元の変数を借用するジョブを実行する（std::thread::scopeと同様）
+    ///
+    /// スコープ内で投入した全てのジョブが完了してから戻る。
+    /// いずれかのジョブがpanicした場合は、全ての完了を待ってからpanicする。
+    /// このプールのジョブの中から呼ぶと、ワーカーが足りずに止まることがある。
+    pub fn scope<'env, F, R>(&self, f: F) -> R
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R,
+    {
+        let scope = Scope {
+            pool: self,
+            state: Arc::new(ScopeState::default()),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+        // fがpanicしても、投入済みのジョブ（借用を持つ）の完了は必ず待つ
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+        scope.state.wait();
+
+        match result {
+            Err(payload) => panic::resume_unwind(payload),
+            Ok(_) if scope.state.panicked.load(Ordering::SeqCst) => panic!("a scoped job panicked"),
+            Ok(value) => value,
+        }
+    }
+
+    /// 投入済みの全てのジョブが完了するまで待つ
+    ///
+    /// このプールのジョブの中から呼ぶと、自身の完了を待って止まる。
+    pub fn wait_idle(&self) {
+        let pending = self.shared.pending.lock().unwrap();
+        let _idle = self.shared.idle.wait_while(pending, |pending| *pending > 0).unwrap();
+    }
+
+    /// 期限まで全ワーカーの終了を待って停止する（キューに残ったジョブは実行される）
+    ///
+    /// 期限を過ぎても終わらないワーカー（戻らないジョブを実行中など）は待たずに切り離し、
+    /// falseを返す。切り離したワーカーは、実行中のジョブが終わり次第終了する。
+    /// 破棄（Drop）は全ワーカーの終了を期限なしで待つ。
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> bool {
+        drop(self.sender.take());
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut workers = self.shared.workers.lock().unwrap_or_else(|e| e.into_inner());
+            workers.list.retain_mut(|worker| {
+                let running = worker.thread.as_ref().is_some_and(|t| !t.is_finished());
+                if !running {
+                    if let Some(Err(_)) = worker.thread.take().map(|t| t.join()) {
+                        eprintln!("Worker {} had stopped with a panic", worker.id);
+                    }
+                }
+                running
+            });
+            if workers.list.is_empty() {
+                return true;
+            }
+            if Instant::now() >= deadline {
+                eprintln!("⚠️  Shutdown timeout: detaching {} worker(s)", workers.list.len());
+                // JoinHandleを破棄してスレッドを切り離す
+                workers.list.clear();
+                return false;
+            }
+            drop(workers);
+            thread::sleep(SHUTDOWN_POLL_INTERVAL);
+        }
+    }
+}
+
+impl Shared {
+    /// 新しいIDでワーカーを起動する
+    fn spawn_worker(self: &Arc<Self>, workers: &mut Workers) -> io::Result<()> {
+        let worker = Worker::spawn(workers.next_id, Arc::clone(self))?;
+        workers.next_id += 1;
+        self.metrics.workers.fetch_add(1, Ordering::Relaxed);
+        workers.list.push(worker);
+        Ok(())
+    }
+
+    /// 終了したワーカーを片付け、停止したワーカーは同じIDで起動し直す
+    ///
+    /// アイドルで終了したワーカーは一覧から外すだけにする。
+    /// panicで停止したワーカーは通常Sentinelが起動し直すため、ここで見つかるのはそれも失敗した場合のみ。
+    fn restart_dead_workers(self: &Arc<Self>, workers: &mut Workers) {
+        workers.list.retain_mut(|worker| {
+            let finished = worker.thread.as_ref().is_some_and(|t| t.is_finished());
+            if !finished {
+                return true;
+            }
+
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+            if worker.retired.load(Ordering::SeqCst) {
+                return false;
+            }
+            eprintln!("⚠️  Worker {} stopped unexpectedly; restarting", worker.id);
+            match Worker::spawn(worker.id, Arc::clone(self)) {
+                Ok(restarted) => {
+                    *worker = restarted;
+                    self.metrics.worker_restarts.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Failed to restart worker {}: {}", worker.id, e);
+                    self.metrics.workers.fetch_sub(1, Ordering::Relaxed);
+                    false
+                }
+            }
+        });
+    }
+
+    /// 最小数を超えていれば、ワーカー数を1つ減らしてtrue（呼び出したワーカーは終了する）
+    fn try_retire(&self) -> bool {
+        self.metrics
+            .workers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n > self.min_workers).then(|| n - 1)
+            })
+            .is_ok()
+    }
+
+    /// キューに追加するジョブを数える
+    fn queue_job(&self) {
+        *self.pending.lock().unwrap() += 1;
+        self.metrics.queue_depth.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// キューに追加できなかったジョブを数えから外す
+    fn cancel_job(&self) {
+        self.metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+        self.finish_job();
+    }
+
+    /// ジョブの完了を記録し、全て完了していればwait_idleを起こす
+    fn finish_job(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        *pending -= 1;
+        if *pending == 0 {
+            self.idle.notify_all();
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    /// スレッドプールが破棄される際に全ワーカーを停止
+    fn drop(&mut self) {
+        // センダーをドロップしてチャネルをクローズ
+        // （ワーカーはキューに残ったジョブを実行してから、recvがErrを返してループを抜ける）
+        drop(self.sender.take());
+
+        // 全ワーカーの終了を待つ
+        // （停止中にpanicしたワーカーは代わりを一覧に追加するので、空になるまで取り出す）
+        loop {
+            let worker = self.shared.workers.lock().unwrap_or_else(|e| e.into_inner()).list.pop();
+            let Some(mut worker) = worker else { break };
+            if let Some(thread) = worker.thread.take() {
+                if thread.join().is_err() {
+                    eprintln!("Worker {} had stopped with a panic", worker.id);
+                }
+            }
+        }
+    }
+}
+
+/// ワーカースレッド
+struct Worker {
+    id: usize,
+    thread: Option<thread::JoinHandle<()>>,
+    /// 自ら終了したか（アイドルでの終了・プールの停止）。falseのまま終了していれば異常
+    retired: Arc<AtomicBool>,
+}
+
+impl Worker {
+    /// 新しいワーカーを起動（スレッド名は "<name>-<id>"）
+    ///
+    /// 処理フロー:
+    /// 1. スレッドを起動
+    /// 2. レシーバーからジョブを受信待機（アイドルタイムアウト付き）
+    /// 3. ジョブを受信したら実行（panicしてもスレッドは終了しない。
+    ///    それでもスレッドがpanicで終了する場合は、Sentinelが同じIDで起動し直す）
+    /// 4. 最後のジョブからアイドル時間が経過した場合、ワーカー数が最小数を超えていれば終了
+    ///    （期限はワーカーごとに数え、レシーバーのロック待ちの間も経過するため、
+    ///    アイドルのワーカーはidle_timeoutの1回分でまとめて終了する）
+    /// 5. 2に戻る（ループ）
+    fn spawn(id: usize, shared: Arc<Shared>) -> io::Result<Self> {
+        let retired = Arc::new(AtomicBool::new(false));
+        let retired_flag = Arc::clone(&retired);
+        let thread = thread::Builder::new()
+            .name(format!("{}-{}", shared.name, id))
+            .spawn(move || {
+                let _sentinel = Sentinel { id, shared: Arc::clone(&shared) };
+                let metrics = &shared.metrics;
+                let mut idle_since = Instant::now();
+                loop {
+                    // ジョブを受信（ブロッキング。ロックを待つ間に期限を過ぎていれば、キューを確認するだけ）
+                    let receiver = shared.receiver.lock().unwrap();
+                    // （idle_timeoutが長すぎて期限を表せない場合は、そのまま待つ）
+                    let remaining = match idle_since.checked_add(shared.idle_timeout) {
+                        Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                        None => shared.idle_timeout,
+                    };
+                    let message = receiver.recv_timeout(remaining);
+                    drop(receiver);
+
+                    match message {
+                        Ok(job) => {
+                            metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+                            let _running = RunningJob::start(&shared);
+                            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                                metrics.panics.fetch_add(1, Ordering::Relaxed);
+                                eprintln!("💥 Worker {} recovered from a panic: {}", id, panic_message(&*payload));
+                            }
+                            idle_since = Instant::now();
+                        }
+                        Err(mpsc::RecvTimeoutError::Timeout) => {
+                            idle_since = Instant::now();
+                            if shared.try_retire() {
+                                break;
+                            }
+                        }
+                        Err(mpsc::RecvTimeoutError::Disconnected) => {
+                            // チャネルがクローズされたら終了
+                            metrics.workers.fetch_sub(1, Ordering::Relaxed);
+                            break;
+                        }
+                    }
+                }
+                retired_flag.store(true, Ordering::SeqCst);
+            })?;
+
+        Ok(Worker {
+            id,
+            thread: Some(thread),
+            retired,
+        })
+    }
+}
+
+/// 実行中のジョブ（破棄されると、panicでスレッドが終了する場合も完了を記録する）
+struct RunningJob<'a> {
+    shared: &'a Shared,
+}
+
+impl<'a> RunningJob<'a> {
+    fn start(shared: &'a Shared) -> Self {
+        shared.metrics.busy_workers.fetch_add(1, Ordering::Relaxed);
+        RunningJob { shared }
+    }
+}
+
+impl Drop for RunningJob<'_> {
+    fn drop(&mut self) {
+        self.shared.metrics.busy_workers.fetch_sub(1, Ordering::Relaxed);
+        self.shared.finish_job();
+    }
+}
+
+/// ワーカースレッドの見張り
+///
+/// catch_unwindの外（panicのペイロードの破棄など）でpanicしてスレッドが終了する場合、
+/// 次のexecuteを待たずにその場で同じIDのワーカーを起動し、一覧の自身と入れ替える。
+struct Sentinel {
+    id: usize,
+    shared: Arc<Shared>,
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            return;
+        }
+
+        let shared = &self.shared;
+        let mut workers = shared.workers.lock().unwrap_or_else(|e| e.into_inner());
+        // 自身のハンドルはjoinできないので切り離す（プールの停止中なら既に取り出されている）
+        workers.list.retain(|worker| worker.id != self.id);
+        eprintln!("⚠️  Worker {} stopped unexpectedly; restarting", self.id);
+        match Worker::spawn(self.id, Arc::clone(shared)) {
+            Ok(restarted) => {
+                workers.list.push(restarted);
+                shared.metrics.worker_restarts.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                eprintln!("⚠️  Failed to restart worker {}: {}", self.id, e);
+                shared.metrics.workers.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// submitしたジョブの結果を受け取るハンドル
+pub struct JoinHandle<T> {
+    receiver: mpsc::Receiver<thread::Result<T>>,
+}
+
+impl<T> JoinHandle<T> {
+    /// ジョブの完了を待ち、結果を返す（panicした場合はそのペイロード）
+    pub fn join(self) -> thread::Result<T> {
+        self.receiver
+            .recv()
+            .unwrap_or_else(|_| Err(Box::new("job was dropped before it finished")))
+    }
+}
+
+/// ThreadPool::scopeの中でジョブを投入するためのスコープ
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    state: Arc<ScopeState>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+/// スコープ内のジョブの完了状況
+#[derive(Default)]
+struct ScopeState {
+    running: Mutex<usize>,
+    done: Condvar,
+    panicked: AtomicBool,
+}
+
+/// スコープ内のジョブの完了を記録する
+///
+/// ジョブが実行されずに破棄された場合（プールへの追加に失敗した場合など）も記録し、
+/// scopeが待ち続けないようにする。
+struct Finish(Arc<ScopeState>);
+
+impl Drop for Finish {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+impl ScopeState {
+    fn finish(&self) {
+        let mut running = self.running.lock().unwrap();
+        *running -= 1;
+        if *running == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    fn wait(&self) {
+        let running = self.running.lock().unwrap();
+        let _done = self.done.wait_while(running, |running| *running > 0).unwrap();
+    }
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// スコープの外側の変数を借用できるジョブを投入する
+    pub fn execute<F>(&'scope self, f: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        *self.state.running.lock().unwrap() += 1;
+        let finish = Finish(Arc::clone(&self.state));
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || {
+            if panic::catch_unwind(AssertUnwindSafe(f)).is_err() {
+                finish.0.panicked.store(true, Ordering::SeqCst);
+            }
+            drop(finish);
+        });
+        // SAFETY: ThreadPool::scopeは、fがpanicした場合も含めて全てのジョブの完了
+        // （Finishの破棄。実行されずに破棄された場合も含む）を待ってから戻るため、
+        // 借用はジョブが存在する間ずっと有効
+        let job: Job = unsafe { std::mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+        self.pool.execute(job);
+    }
+}
+
+/// panicのメッセージ（panic!に渡した文字列。それ以外の値は固定の文字列）
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "non-string panic payload"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn pool(workers: usize) -> ThreadPool {
+        ThreadPool::builder().workers(workers).queue_capacity(16).build().unwrap()
+    }
+
+    #[test]
+    fn test_thread_pool_creation() {
+        let pool = pool(4);
+        assert_eq!(pool.shared.workers.lock().unwrap().list.len(), 4);
+        assert_eq!(pool.metrics().workers(), 4);
+    }
+
+    #[test]
+    fn test_thread_pool_execute() {
+        let pool = pool(2);
+        let counter = Arc::new(Mutex::new(0));
+
+        for _ in 0..10 {
+            let counter = Arc::clone(&counter);
+            pool.execute(move || {
+                let mut num = counter.lock().unwrap();
+                *num += 1;
+            });
+        }
+
+        // ジョブが完了するまで待つ
+        pool.wait_idle();
+
+        let final_count = *counter.lock().unwrap();
+        assert_eq!(final_count, 10);
+    }
+
+    #[test]
+    fn test_submit_returns_result_or_panic() {
+        let pool = pool(2);
+        let sum = pool.submit(|| (1..=10).sum::<u32>());
+        let failed = pool.submit(|| -> u32 { panic!("bad input") });
+
+        assert_eq!(sum.join().unwrap(), 55);
+        let payload = failed.join().unwrap_err();
+        assert_eq!(panic_message(&*payload), "bad input");
+        assert_eq!(pool.metrics().panics(), 0);
+    }
+
+    #[test]
+    fn test_scope_borrows_from_caller() {
+        let pool = pool(3);
+        let input = [1, 2, 3, 4, 5];
+        let mut squares = vec![0; input.len()];
+        pool.scope(|s| {
+            for (slot, value) in squares.iter_mut().zip(&input) {
+                s.execute(move || *slot = value * value);
+            }
+        });
+        assert_eq!(squares, [1, 4, 9, 16, 25]);
+
+        // ジョブのpanicは全ての完了後にscopeから伝わる
+        let finished = AtomicUsize::new(0);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                s.execute(|| panic!("scoped failure"));
+                s.execute(|| {
+                    thread::sleep(Duration::from_millis(20));
+                    finished.fetch_add(1, Ordering::SeqCst);
+                });
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_settings() {
+        let invalid = [
+            ThreadPool::builder().workers(0),
+            ThreadPool::builder().min_workers(4).max_workers(2),
+            ThreadPool::builder().queue_capacity(0),
+        ];
+        for builder in invalid {
+            let err = builder.build().err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_failed_execute_does_not_leak_pending_jobs() {
+        let mut pool = pool(1);
+        drop(pool.sender.take());
+
+        // 追加に失敗したジョブは完了として数え、wait_idleやscopeが待ち続けない
+        let result = panic::catch_unwind(AssertUnwindSafe(|| pool.execute(|| {})));
+        assert!(result.is_err());
+        assert_eq!(pool.metrics().queue_depth(), 0);
+        pool.wait_idle();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| pool.scope(|s| s.execute(|| {}))));
+        assert!(result.is_err());
+        pool.wait_idle();
+    }
+
+    #[test]
+    fn test_try_execute_returns_job_when_queue_is_full() {
+        let pool = ThreadPool::builder().workers(1).queue_capacity(1).build().unwrap();
+        let (entered, worker_entered) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        pool.execute(move || {
+            entered.send(()).unwrap();
+            let _ = released.recv();
+        });
+        worker_entered.recv_timeout(Duration::from_secs(1)).unwrap();
+
+        // キューの1件目は入り、2件目は待たずに返される
+        let ran = Arc::new(AtomicUsize::new(0));
+        let job = |ran: &Arc<AtomicUsize>| -> Job {
+            let ran = Arc::clone(ran);
+            Box::new(move || {
+                ran.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        assert!(pool.try_execute(job(&ran)).is_ok());
+        let rejected = pool.try_execute(job(&ran)).unwrap_err();
+        assert_eq!(pool.metrics().queue_depth(), 1);
+
+        drop(release);
+        pool.wait_idle();
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+        assert!(pool.try_execute(rejected).is_ok());
+        pool.wait_idle();
+        assert_eq!(ran.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_named_threads() {
+        let pool = ThreadPool::builder().workers(1).name("graph").build().unwrap();
+        let name = pool.submit(|| thread::current().name().map(str::to_string));
+        assert_eq!(name.join().unwrap().as_deref(), Some("graph-0"));
+    }
+
+    #[test]
+    fn test_worker_survives_and_restarts() {
+        let pool = pool(1);
+        let metrics = Arc::clone(pool.metrics());
+        let (done, finished) = mpsc::channel();
+
+        // ジョブがpanicしてもワーカーは次のジョブを実行する
+        pool.execute(|| panic!("job failed"));
+        let sender = done.clone();
+        pool.execute(move || sender.send(()).unwrap());
+        finished.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(metrics.panics(), 1);
+
+        // ワーカーが停止していれば、次のジョブの前に起動し直す
+        let stopped = thread::spawn(|| {});
+        while !stopped.is_finished() {
+            thread::yield_now();
+        }
+        let running = pool.shared.workers.lock().unwrap().list[0].thread.replace(stopped).unwrap();
+        pool.execute(move || done.send(()).unwrap());
+        finished.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(metrics.worker_restarts(), 1);
+
+        drop(pool);
+        running.join().unwrap();
+    }
+
+    #[test]
+    fn test_worker_restarts_itself_without_next_job() {
+        // 破棄時にpanicするペイロード（catch_unwindの外でワーカースレッドを止める）
+        struct PanicOnDrop;
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                panic!("payload dropped");
+            }
+        }
+
+        let pool = pool(1);
+        let metrics = Arc::clone(pool.metrics());
+        pool.execute(|| panic::panic_any(PanicOnDrop));
+
+        // 次のジョブを投入しなくても、ワーカーは起動し直される
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while metrics.worker_restarts() == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(metrics.worker_restarts(), 1);
+        assert_eq!(metrics.workers(), 1);
+
+        // 止まったジョブも完了として数えられ、起動し直したワーカーが次のジョブを実行する
+        pool.wait_idle();
+        assert_eq!(metrics.busy_workers(), 0);
+        let name = pool.submit(|| thread::current().name().map(str::to_string));
+        assert_eq!(name.join().unwrap().as_deref(), Some("pool-0"));
+    }
+
+    #[test]
+    fn test_shutdown_timeout_detaches_stuck_workers() {
+        let stuck = pool(2);
+        let (release, released) = mpsc::channel::<()>();
+        let finished = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&finished);
+        stuck.execute(move || {
+            let _ = released.recv();
+        });
+        stuck.execute(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        // 戻らないジョブがあっても、期限を過ぎれば待たずに戻る
+        let started = Instant::now();
+        assert!(!stuck.shutdown_timeout(Duration::from_millis(50)));
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert_eq!(finished.load(Ordering::SeqCst), 1);
+        drop(release);
+
+        // 全てのジョブが終われば期限内に停止できる
+        let pool = pool(2);
+        pool.execute(|| thread::sleep(Duration::from_millis(20)));
+        assert!(pool.shutdown_timeout(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_elastic_pool_grows_and_shrinks() {
+        let pool = ThreadPool::builder()
+            .min_workers(1)
+            .max_workers(3)
+            .idle_timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let metrics = Arc::clone(pool.metrics());
+        let (entered, all_entered) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+
+        // 同時に3件のジョブが処理中になるまでワーカーを増やす
+        for _ in 0..3 {
+            let (entered, released) = (entered.clone(), Arc::clone(&released));
+            pool.execute(move || {
+                entered.send(()).unwrap();
+                let _ = released.lock().unwrap().recv();
+            });
+        }
+        for _ in 0..3 {
+            all_entered.recv_timeout(Duration::from_secs(1)).unwrap();
+        }
+        assert_eq!(metrics.workers(), 3);
+        assert_eq!(metrics.busy_workers(), 3);
+        assert_eq!(metrics.utilization(), 1.0);
+
+        // アイドルになれば最小数まで減る
+        drop(release);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while metrics.workers() > 1 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(metrics.workers(), 1);
+        assert_eq!(metrics.busy_workers(), 0);
+
+        // 終了したワーカーは再起動の対象にならない
+        let remaining = || {
+            let mut workers = pool.shared.workers.lock().unwrap();
+            pool.shared.restart_dead_workers(&mut workers);
+            workers.list.len()
+        };
+        while remaining() > 1 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(remaining(), 1);
+        assert_eq!(metrics.worker_restarts(), 0);
+    }
+
+    #[test]
+    fn test_idle_workers_retire_together() {
+        let idle_timeout = Duration::from_millis(300);
+        let pool = ThreadPool::builder()
+            .min_workers(1)
+            .max_workers(4)
+            .idle_timeout(idle_timeout)
+            .build()
+            .unwrap();
+        let metrics = Arc::clone(pool.metrics());
+        let (entered, all_entered) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+        for _ in 0..4 {
+            let (entered, released) = (entered.clone(), Arc::clone(&released));
+            pool.execute(move || {
+                entered.send(()).unwrap();
+                let _ = released.lock().unwrap().recv();
+            });
+        }
+        for _ in 0..4 {
+            all_entered.recv_timeout(Duration::from_secs(1)).unwrap();
+        }
+        assert_eq!(metrics.workers(), 4);
+
+        // レシーバーのロックを順番に待っても、アイドル時間の1回分で最小数まで減る
+        // （1つずつ待つと、終了する3つのワーカーでidle_timeoutの3倍かかる）
+        drop(release);
+        let released_at = Instant::now();
+        while metrics.workers() > 1 && released_at.elapsed() < Duration::from_secs(2) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(metrics.workers(), 1);
+        assert!(released_at.elapsed() < idle_timeout * 2, "{:?}", released_at.elapsed());
+    }
+
+    #[test]
+    fn test_retiring_workers_do_not_block_growth() {
+        let pool = ThreadPool::builder()
+            .min_workers(1)
+            .max_workers(2)
+            .build()
+            .unwrap();
+
+        // アイドルで終了を決めたが、まだスレッドが終わっていないワーカーが一覧に残っている
+        let (finish, finished) = mpsc::channel::<()>();
+        pool.shared.workers.lock().unwrap().list.push(Worker {
+            id: usize::MAX,
+            thread: Some(thread::spawn(move || {
+                let _ = finished.recv();
+            })),
+            retired: Arc::new(AtomicBool::new(true)),
+        });
+
+        // それでも稼働中のワーカーは1つなので、最大数まで増やして2件を同時に処理する
+        let (entered, all_entered) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+        for _ in 0..2 {
+            let (entered, released) = (entered.clone(), Arc::clone(&released));
+            pool.execute(move || {
+                entered.send(()).unwrap();
+                let _ = released.lock().unwrap().recv();
+            });
+        }
+        for _ in 0..2 {
+            all_entered.recv_timeout(Duration::from_secs(1)).unwrap();
+        }
+        assert_eq!(pool.metrics().workers(), 2);
+
+        drop(release);
+        drop(finish);
+    }
+}
@@ -0,0 +1,698 @@
+// src/tree.rs
+//
+// 【処理概要】
+// ルーティング用の基数木（radix tree / 圧縮トライ）を実装。
+// パスの長さに比例する時間でルートを検索する。
+//
+// 【主な機能】
+// - 共通の接頭辞をまとめた静的エッジ（例: "/api/users" と "/api/stats" は "/api/" を共有）
+// - パラメータノード（例: ":id"。1セグメント内に複数可: ":name.:ext"）
+// - 制約付きパラメータ（例: ":id<int>"。合わなければ他の候補を試す）
+// - キャッチオール（例: "*filepath"。残りのパス全体にマッチ）
+// - 省略可能な末尾パラメータ（例: "/posts/:page?" は "/posts" にもマッチ）
+// - 静的セグメントをパラメータより優先するマッチング（失敗時はバックトラック）
+// - 重複・曖昧なルート登録の検出
+//
+// 【実装内容】
+// 1. パターンを静的部分・パラメータ・キャッチオールのトークン列に分解
+// 2. 静的部分は先頭バイトの異なるエッジとして挿入し、途中で分岐する場合はエッジを分割
+// 3. 検索は 静的エッジ → 制約付きパラメータ → 制約なしパラメータ → キャッチオール
+//    の順に試し、見つからなければ1つ前に戻る
+
+use crate::constraint::Constraint;
+use crate::router::RouteError;
+
+/// 基数木のノード
+///
+/// 例: "/api/users", "/api/users/:id", "/api/stats" を登録した場合
+///   "/api/" ─┬─ "users" (leaf) ── "/" ── :id (leaf)
+///            └─ "stats" (leaf)
+pub(crate) struct Node<T> {
+    /// 静的エッジ（ラベル, 子ノード）。ラベルの先頭バイトは互いに異なる
+    children: Vec<(String, Node<T>)>,
+    /// パラメータの子ノード（制約付きが先、制約なしは最後に1つだけ）
+    params: Vec<ParamChild<T>>,
+    /// キャッチオールの子ノード
+    catch_all: Option<Box<ParamChild<T>>>,
+    /// このノードで終わるルート
+    leaf: Option<Leaf<T>>,
+}
+
+/// パラメータ（またはキャッチオール）の子ノード
+struct ParamChild<T> {
+    name: String,
+    constraint: Option<Constraint>,
+    /// 最初にこのパラメータを登録したパターン（衝突時のエラーメッセージ用）
+    pattern: String,
+    node: Node<T>,
+}
+
+/// 登録されたルート
+pub(crate) struct Leaf<T> {
+    pub pattern: String,
+    pub value: T,
+}
+
+/// パターンを分解したトークン
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a> {
+    Static(&'a str),
+    Param {
+        name: &'a str,
+        constraint: Option<&'a str>,
+    },
+    CatchAll(&'a str),
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Node {
+            children: Vec::new(),
+            params: Vec::new(),
+            catch_all: None,
+            leaf: None,
+        }
+    }
+}
+
+impl<T: Clone> Node<T> {
+    /// ルートを登録する
+    ///
+    /// 同じ形のパターンが登録済みならDuplicate、同じ位置に異なる名前の
+    /// パラメータ（制約も同じもの）があればConflictを返す。
+    /// 末尾が省略可能なパラメータなら、省略した形のパターンも同じ値で登録する。
+    /// エラーの場合は木を変更しない（どちらの形も登録されない）。
+    pub(crate) fn insert(&mut self, pattern: &str, value: T) -> Result<(), RouteError> {
+        let forms = forms(pattern)?;
+        for tokens in &forms {
+            self.check_tokens(pattern, tokens)?;
+        }
+        for tokens in &forms {
+            self.insert_tokens(pattern, tokens, value.clone())?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Node<T> {
+    /// ルートを登録できるかを調べる（木は変更しない）
+    ///
+    /// insertと同じエラーを返す。複数のルートをまとめて登録する前の確認に使う。
+    pub(crate) fn check(&self, pattern: &str) -> Result<(), RouteError> {
+        for tokens in &forms(pattern)? {
+            self.check_tokens(pattern, tokens)?;
+        }
+        Ok(())
+    }
+
+    /// トークン列に沿って既存のノードを辿り、衝突・重複がないかを調べる
+    ///
+    /// 途中で既存のノードから外れた場合、その先は新しく作られるため衝突しない。
+    fn check_tokens(&self, pattern: &str, tokens: &[Token]) -> Result<(), RouteError> {
+        let conflict = |existing: &str| RouteError::Conflict {
+            pattern: pattern.to_string(),
+            existing: existing.to_string(),
+        };
+
+        let mut node = self;
+        for &token in tokens {
+            let next = match token {
+                Token::Static(text) => node.find_static(text),
+                Token::Param { name, constraint } => {
+                    match node
+                        .params
+                        .iter()
+                        .find(|param| param.constraint.as_ref().map(Constraint::source) == constraint)
+                    {
+                        Some(param) if param.name != name => return Err(conflict(&param.pattern)),
+                        Some(param) => Some(&param.node),
+                        None => None,
+                    }
+                }
+                Token::CatchAll(name) => match &node.catch_all {
+                    Some(catch_all) if catch_all.name != name => {
+                        return Err(conflict(&catch_all.pattern));
+                    }
+                    Some(catch_all) => Some(&catch_all.node),
+                    None => None,
+                },
+            };
+            match next {
+                Some(next) => node = next,
+                None => return Ok(()),
+            }
+        }
+
+        match &node.leaf {
+            Some(leaf) => Err(RouteError::Duplicate {
+                pattern: pattern.to_string(),
+                existing: leaf.pattern.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// 静的な文字列を辿った先の既存のノード（エッジの途中で終わる・外れる場合はNone）
+    fn find_static(&self, text: &str) -> Option<&Node<T>> {
+        if text.is_empty() {
+            return Some(self);
+        }
+        let first = text.as_bytes()[0];
+        let (label, child) = self
+            .children
+            .iter()
+            .find(|(label, _)| label.as_bytes()[0] == first)?;
+        child.find_static(text.strip_prefix(label.as_str())?)
+    }
+
+    /// トークン列に沿ってノードを辿り（なければ作り）、終端に値を置く
+    fn insert_tokens(&mut self, pattern: &str, tokens: &[Token], value: T) -> Result<(), RouteError> {
+        let conflict = |existing: &str| RouteError::Conflict {
+            pattern: pattern.to_string(),
+            existing: existing.to_string(),
+        };
+
+        let mut node = self;
+        for &token in tokens {
+            node = match token {
+                Token::Static(text) => node.insert_static(text),
+                Token::Param { name, constraint } => {
+                    let compiled = constraint.map(|source| {
+                        Constraint::parse(source).expect("constraint is validated by tokenize")
+                    });
+                    node
+                        .insert_param(pattern, name, compiled)
+                        .map_err(|existing| conflict(&existing))?
+                }
+                Token::CatchAll(name) => {
+                    if let Some(catch_all) = &node.catch_all {
+                        if catch_all.name != name {
+                            return Err(conflict(&catch_all.pattern));
+                        }
+                    }
+                    let catch_all = node.catch_all.get_or_insert_with(|| {
+                        Box::new(ParamChild {
+                            name: name.to_string(),
+                            constraint: None,
+                            pattern: pattern.to_string(),
+                            node: Node::default(),
+                        })
+                    });
+                    &mut catch_all.node
+                }
+            };
+        }
+
+        if let Some(leaf) = &node.leaf {
+            return Err(RouteError::Duplicate {
+                pattern: pattern.to_string(),
+                existing: leaf.pattern.clone(),
+            });
+        }
+        node.leaf = Some(Leaf {
+            pattern: pattern.to_string(),
+            value,
+        });
+        Ok(())
+    }
+
+    /// 静的な文字列を挿入し、その終端のノードを返す
+    fn insert_static(&mut self, text: &str) -> &mut Node<T> {
+        if text.is_empty() {
+            return self;
+        }
+
+        let first = text.as_bytes()[0];
+        let Some(index) = self
+            .children
+            .iter()
+            .position(|(label, _)| label.as_bytes()[0] == first)
+        else {
+            self.children.push((text.to_string(), Node::default()));
+            return &mut self.children.last_mut().expect("edge was just pushed").1;
+        };
+
+        let (label, child) = &mut self.children[index];
+        let common = common_prefix_len(label, text);
+        if common < label.len() {
+            // エッジを共通部分と残りに分割する
+            let rest = label.split_off(common);
+            let old = std::mem::take(child);
+            child.children.push((rest, old));
+        }
+        child.insert_static(&text[common..])
+    }
+
+    /// パラメータの子ノードを取得（なければ作成）する
+    ///
+    /// 制約が同じで名前が異なるパラメータがあれば、そのパターンをErrで返す。
+    fn insert_param(
+        &mut self,
+        pattern: &str,
+        name: &str,
+        constraint: Option<Constraint>,
+    ) -> Result<&mut Node<T>, String> {
+        let source = constraint.as_ref().map(Constraint::source);
+        let index = match self
+            .params
+            .iter()
+            .position(|param| param.constraint.as_ref().map(Constraint::source) == source)
+        {
+            Some(index) if self.params[index].name != name => {
+                return Err(self.params[index].pattern.clone());
+            }
+            Some(index) => index,
+            None => {
+                // 制約付きのパラメータは制約なしのものより前に置く
+                let index = match constraint {
+                    Some(_) => self
+                        .params
+                        .iter()
+                        .position(|param| param.constraint.is_none())
+                        .unwrap_or(self.params.len()),
+                    None => self.params.len(),
+                };
+                self.params.insert(
+                    index,
+                    ParamChild {
+                        name: name.to_string(),
+                        constraint,
+                        pattern: pattern.to_string(),
+                        node: Node::default(),
+                    },
+                );
+                index
+            }
+        };
+        Ok(&mut self.params[index].node)
+    }
+
+    /// 登録された全てのルート
+    pub(crate) fn leaves(&self) -> Vec<&Leaf<T>> {
+        let mut leaves = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            leaves.extend(node.leaf.as_ref());
+            // 静的エッジ → パラメータ → キャッチオールの順に取り出すよう、逆順に積む
+            stack.extend(node.catch_all.iter().map(|catch_all| &catch_all.node));
+            stack.extend(node.params.iter().rev().map(|param| &param.node));
+            stack.extend(node.children.iter().rev().map(|(_, child)| child));
+        }
+        leaves
+    }
+
+    /// パターンで登録したルートの値（省略可能なパラメータでは2つ）に関数を適用する
+    ///
+    /// パターンのトークンに沿って辿るため、木全体の大きさには依存しない。
+    pub(crate) fn for_each_value_mut(&mut self, pattern: &str, mut f: impl FnMut(&mut T)) {
+        let Ok(forms) = forms(pattern) else {
+            return;
+        };
+        for tokens in &forms {
+            if let Some(leaf) = self.node_mut(tokens).and_then(|node| node.leaf.as_mut()) {
+                f(&mut leaf.value);
+            }
+        }
+    }
+
+    /// トークン列に沿って辿った先の既存のノード
+    fn node_mut(&mut self, tokens: &[Token]) -> Option<&mut Node<T>> {
+        let mut node = self;
+        for &token in tokens {
+            node = match token {
+                Token::Static(text) => node.find_static_mut(text)?,
+                Token::Param { name, constraint } => {
+                    let param = node.params.iter_mut().find(|param| {
+                        param.name == name
+                            && param.constraint.as_ref().map(Constraint::source) == constraint
+                    })?;
+                    &mut param.node
+                }
+                Token::CatchAll(name) => {
+                    let catch_all = node.catch_all.as_mut().filter(|c| c.name == name)?;
+                    &mut catch_all.node
+                }
+            };
+        }
+        Some(node)
+    }
+
+    /// find_staticの可変版
+    fn find_static_mut(&mut self, text: &str) -> Option<&mut Node<T>> {
+        if text.is_empty() {
+            return Some(self);
+        }
+        let first = text.as_bytes()[0];
+        let (label, child) = self
+            .children
+            .iter_mut()
+            .find(|(label, _)| label.as_bytes()[0] == first)?;
+        child.find_static_mut(text.strip_prefix(label.as_str())?)
+    }
+
+    /// パスに一致するルートを検索する
+    ///
+    /// 静的エッジ → パラメータ → キャッチオールの順に試し、
+    /// その先で見つからなければ次の候補に戻る。
+    /// 見つかった場合、paramsには (パラメータ名, 値) がパスの順に入る。
+    pub(crate) fn find<'a, 'p>(
+        &'a self,
+        path: &'p str,
+        params: &mut Vec<(&'a str, &'p str)>,
+    ) -> Option<&'a Leaf<T>> {
+        if path.is_empty() {
+            if let Some(leaf) = &self.leaf {
+                return Some(leaf);
+            }
+        } else {
+            // 静的エッジ（先頭バイトで1つに絞れる）
+            let first = path.as_bytes()[0];
+            if let Some((label, child)) = self
+                .children
+                .iter()
+                .find(|(label, _)| label.as_bytes()[0] == first)
+            {
+                if let Some(rest) = path.strip_prefix(label.as_str()) {
+                    if let Some(leaf) = child.find(rest, params) {
+                        return Some(leaf);
+                    }
+                }
+            }
+
+            // パラメータ（空でない、同じセグメント内。長い値から試す）
+            // 値の後ろは、パスの終わりか子ノードの静的エッジの先頭バイトでなければならない
+            let segment_end = path.find('/').unwrap_or(path.len());
+            for param in &self.params {
+                let can_end_at = |end: usize| {
+                    end == path.len()
+                        || param
+                            .node
+                            .children
+                            .iter()
+                            .any(|(label, _)| label.as_bytes()[0] == path.as_bytes()[end])
+                };
+                for end in (1..=segment_end)
+                    .rev()
+                    .filter(|&end| path.is_char_boundary(end) && can_end_at(end))
+                {
+                    let value = &path[..end];
+                    if param.constraint.as_ref().is_some_and(|c| !c.matches(value)) {
+                        continue;
+                    }
+                    params.push((&param.name, value));
+                    if let Some(leaf) = param.node.find(&path[end..], params) {
+                        return Some(leaf);
+                    }
+                    params.pop();
+                }
+            }
+        }
+
+        // キャッチオール（残りのパス全体。空でもよい）
+        if let Some(catch_all) = &self.catch_all {
+            if let Some(leaf) = &catch_all.node.leaf {
+                params.push((&catch_all.name, path));
+                return Some(leaf);
+            }
+        }
+
+        None
+    }
+}
+
+/// パターンを登録する形のトークン列に分解する
+///
+/// 末尾が省略可能なパラメータなら、省略した形（先）と省略しない形の2つ。
+fn forms(pattern: &str) -> Result<Vec<Vec<Token<'_>>>, RouteError> {
+    let (tokens, optional) = tokenize(pattern)?;
+    if !optional {
+        return Ok(vec![tokens]);
+    }
+
+    // "/posts/:page?" -> "/posts"（直前の '/' も取り除く。ルートは "/" のまま）
+    let mut short = tokens[..tokens.len() - 1].to_vec();
+    if let Some(Token::Static(text)) = short.pop() {
+        match text.strip_suffix('/') {
+            Some("") if short.is_empty() => short.push(Token::Static("/")),
+            Some("") => {}
+            Some(text) => short.push(Token::Static(text)),
+            None => short.push(Token::Static(text)),
+        }
+    }
+    Ok(vec![short, tokens])
+}
+
+/// パターンをトークンに分解する（2つ目の値は末尾が省略可能なパラメータか）
+///
+/// 例: "/users/:id<int>/posts" -> [Static("/users/"), Param(id, int), Static("/posts")]
+///
+/// - ":name" はセグメントの先頭、または同じセグメント内のパラメータの後ろに置ける
+///   （例: "/files/:name.:ext"）
+/// - ":name<制約>" で値を制約する（例: ":id<int>", ":slug<[a-z0-9-]+>"）
+/// - 最後のセグメントが ":name?" なら省略可能
+/// - "*name" は最後のセグメントにのみ置け、残りのパス全体にマッチする
+fn tokenize(pattern: &str) -> Result<(Vec<Token<'_>>, bool), RouteError> {
+    let invalid = |reason: &'static str| RouteError::InvalidPattern {
+        pattern: pattern.to_string(),
+        reason,
+    };
+    let is_name_char = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+
+    if !pattern.starts_with('/') {
+        return Err(invalid("pattern must start with '/'"));
+    }
+
+    let bytes = pattern.as_bytes();
+    let mut tokens = Vec::new();
+    let mut names: Vec<&str> = Vec::new();
+    let mut optional = false;
+    let mut static_start = 0;
+    let mut param_in_segment = false;
+    let mut i = 1; // 先頭は '/'
+
+    while i < bytes.len() {
+        let at_segment_start = bytes[i - 1] == b'/';
+        if at_segment_start {
+            param_in_segment = false;
+        }
+
+        match bytes[i] {
+            b':' if at_segment_start || param_in_segment => {
+                if static_start < i {
+                    tokens.push(Token::Static(&pattern[static_start..i]));
+                } else if !at_segment_start {
+                    return Err(invalid("parameters must be separated by static text"));
+                }
+
+                // 名前
+                let name_start = i + 1;
+                let mut end = name_start;
+                while end < bytes.len() && is_name_char(bytes[end]) {
+                    end += 1;
+                }
+                let name = &pattern[name_start..end];
+                if name.is_empty() {
+                    return Err(invalid("parameter name must be non-empty [A-Za-z0-9_]"));
+                }
+
+                // 制約
+                let mut constraint = None;
+                if bytes.get(end) == Some(&b'<') {
+                    let close = pattern[end..]
+                        .find('>')
+                        .map(|close| end + close)
+                        .ok_or_else(|| invalid("unterminated parameter constraint"))?;
+                    let source = &pattern[end + 1..close];
+                    if Constraint::parse(source).is_none() {
+                        return Err(invalid("invalid parameter constraint"));
+                    }
+                    constraint = Some(source);
+                    end = close + 1;
+                }
+
+                // 省略可能（最後のセグメント全体のパラメータのみ）
+                if bytes.get(end) == Some(&b'?') {
+                    if end + 1 != bytes.len() || !at_segment_start {
+                        return Err(invalid("only a whole last segment can be optional"));
+                    }
+                    optional = true;
+                    end += 1;
+                }
+
+                if names.contains(&name) {
+                    return Err(invalid("parameter name is used twice"));
+                }
+                names.push(name);
+                tokens.push(Token::Param { name, constraint });
+                param_in_segment = true;
+                i = end;
+                static_start = end;
+            }
+            b'*' if at_segment_start => {
+                if static_start < i {
+                    tokens.push(Token::Static(&pattern[static_start..i]));
+                }
+                let name = &pattern[i + 1..];
+                if name.is_empty() || !name.bytes().all(is_name_char) {
+                    return Err(invalid("catch-all must be the last segment, e.g. /*path"));
+                }
+                if names.contains(&name) {
+                    return Err(invalid("parameter name is used twice"));
+                }
+                tokens.push(Token::CatchAll(name));
+                return Ok((tokens, false));
+            }
+            _ => i += 1,
+        }
+    }
+
+    if static_start < pattern.len() {
+        tokens.push(Token::Static(&pattern[static_start..]));
+    }
+    Ok((tokens, optional))
+}
+
+/// 2つの文字列の共通接頭辞のバイト数（文字境界に揃える）
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.chars()
+        .zip(b.chars())
+        .take_while(|(x, y)| x == y)
+        .map(|(c, _)| c.len_utf8())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup<'a>(tree: &'a Node<u32>, path: &'a str) -> Option<(u32, Vec<(&'a str, &'a str)>)> {
+        let mut params = Vec::new();
+        tree.find(path, &mut params).map(|leaf| (leaf.value, params))
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let (tokens, optional) = tokenize("/users/:id<int>/files/:name.:ext").unwrap();
+        assert!(!optional);
+        assert_eq!(
+            tokens,
+            [
+                Token::Static("/users/"),
+                Token::Param { name: "id", constraint: Some("int") },
+                Token::Static("/files/"),
+                Token::Param { name: "name", constraint: None },
+                Token::Static("."),
+                Token::Param { name: "ext", constraint: None },
+            ]
+        );
+        assert!(tokenize("/posts/:page?").unwrap().1);
+        for pattern in ["users", "/users/:", "/:id/:id", "/:a:b", "/*", "/*path/more", "/:a?/b"] {
+            assert!(tokenize(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_split_edges_and_find() {
+        let mut tree = Node::default();
+        tree.insert("/api/users", 1).unwrap();
+        tree.insert("/api/stats", 2).unwrap();
+        tree.insert("/api/users/:id/posts/:post_id", 3).unwrap();
+        tree.insert("/", 4).unwrap();
+
+        assert_eq!(lookup(&tree, "/api/users").unwrap().0, 1);
+        assert_eq!(lookup(&tree, "/api/stats").unwrap().0, 2);
+        assert_eq!(
+            lookup(&tree, "/api/users/123/posts/456").unwrap(),
+            (3, vec![("id", "123"), ("post_id", "456")])
+        );
+        assert_eq!(lookup(&tree, "/").unwrap().0, 4);
+        assert!(lookup(&tree, "/api/user").is_none());
+        assert!(lookup(&tree, "/api/users//posts/1").is_none());
+    }
+
+    #[test]
+    fn test_static_preferred_with_backtracking() {
+        let mut tree = Node::default();
+        tree.insert("/users/:id", 1).unwrap();
+        tree.insert("/users/me", 2).unwrap();
+        tree.insert("/users/:id/posts", 3).unwrap();
+
+        assert_eq!(lookup(&tree, "/users/me").unwrap().0, 2);
+        assert_eq!(lookup(&tree, "/users/meow").unwrap().0, 1);
+        // "me" の静的ルートには /posts がないため、パラメータに戻ってマッチする
+        assert_eq!(
+            lookup(&tree, "/users/me/posts").unwrap(),
+            (3, vec![("id", "me")])
+        );
+    }
+
+    #[test]
+    fn test_duplicate_and_conflict() {
+        let mut tree = Node::default();
+        tree.insert("/users/:id", 1).unwrap();
+
+        assert!(matches!(
+            tree.insert("/users/:id", 2),
+            Err(RouteError::Duplicate { .. })
+        ));
+        assert!(matches!(
+            tree.insert("/users/:name/posts", 3),
+            Err(RouteError::Conflict { existing, .. }) if existing == "/users/:id"
+        ));
+        // 制約が異なれば別の候補として登録できる
+        tree.insert("/users/:num<int>/posts", 4).unwrap();
+        assert!(matches!(
+            tree.insert("/posts/:page?", 5).and(tree.insert("/posts", 6)),
+            Err(RouteError::Duplicate { existing, .. }) if existing == "/posts/:page?"
+        ));
+
+        // 省略しない形が衝突する場合は、省略した形も登録しない
+        assert!(matches!(
+            tree.insert("/users/:name?", 7),
+            Err(RouteError::Conflict { .. })
+        ));
+        assert!(lookup(&tree, "/users").is_none());
+        assert!(tree.check("/users").is_ok());
+    }
+
+    #[test]
+    fn test_catch_all_and_optional() {
+        let mut tree = Node::default();
+        tree.insert("/static/*filepath", 1).unwrap();
+        tree.insert("/static/favicon.ico", 2).unwrap();
+        tree.insert("/posts/:page?", 3).unwrap();
+
+        assert_eq!(
+            lookup(&tree, "/static/css/app.css").unwrap(),
+            (1, vec![("filepath", "css/app.css")])
+        );
+        assert_eq!(lookup(&tree, "/static/").unwrap(), (1, vec![("filepath", "")]));
+        assert_eq!(lookup(&tree, "/static/favicon.ico").unwrap().0, 2);
+        assert_eq!(lookup(&tree, "/posts").unwrap(), (3, vec![]));
+        assert_eq!(lookup(&tree, "/posts/2").unwrap(), (3, vec![("page", "2")]));
+    }
+
+    #[test]
+    fn test_multiple_params_and_constraints() {
+        let mut tree = Node::default();
+        tree.insert("/files/:name.:ext", 1).unwrap();
+        tree.insert("/users/:id<int>", 2).unwrap();
+        tree.insert("/users/:uuid<uuid>", 3).unwrap();
+        tree.insert("/users/:name", 4).unwrap();
+
+        // 前のパラメータは長い値から試す
+        assert_eq!(
+            lookup(&tree, "/files/archive.tar.gz").unwrap(),
+            (1, vec![("name", "archive.tar"), ("ext", "gz")])
+        );
+        assert!(lookup(&tree, "/files/README").is_none());
+
+        // 制約に合わなければ次の候補に進む
+        assert_eq!(lookup(&tree, "/users/42").unwrap(), (2, vec![("id", "42")]));
+        assert_eq!(
+            lookup(&tree, "/users/550e8400-e29b-41d4-a716-446655440000").unwrap().0,
+            3
+        );
+        assert_eq!(lookup(&tree, "/users/alice").unwrap(), (4, vec![("name", "alice")]));
+    }
+}
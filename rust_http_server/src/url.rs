@@ -0,0 +1,174 @@
+// src/url.rs
+//
+// 【処理概要】
+// リクエストターゲット（例: "/api/users?page=2"）の解析を実装。
+// パスとクエリ文字列への分割、パーセントデコードを行う。
+//
+// 【主な機能】
+// - リクエストターゲットのパスとクエリへの分割（フラグメントは除去）
+// - パーセントエンコーディングのデコード（例: "%20" -> " "）
+// - クエリ文字列のパース（同じキーの複数指定に対応）
+//
+// 【実装内容】
+// 1. '?' でパスとクエリを分割し、'#' 以降を捨てる
+// 2. "%XX" を1バイトに戻し、UTF-8として検証
+// 3. クエリは '&' で区切り、'=' でキーと値に分ける（'+' は空白として扱う）
+
+use std::collections::HashMap;
+
+/// クエリパラメータのマップ
+///
+/// 例: "tag=rust&tag=http&page=2"
+///   -> get("page") == Some("2")
+///   -> get_all("tag") == ["rust", "http"]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryMap {
+    params: HashMap<String, Vec<String>>,
+}
+
+impl QueryMap {
+    /// 空のクエリマップを作成
+    pub fn new() -> Self {
+        QueryMap {
+            params: HashMap::new(),
+        }
+    }
+
+    /// クエリ文字列をパースする（先頭の '?' は含めない）
+    ///
+    /// 不正なパーセントエンコーディングを含む場合はNone。
+    pub fn parse(query: &str) -> Option<Self> {
+        let mut map = QueryMap::new();
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let key = decode_query_component(key)?;
+            let value = decode_query_component(value)?;
+            map.append(key, value);
+        }
+
+        Some(map)
+    }
+
+    /// 指定したキーの最初の値を取得
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params
+            .get(key)
+            .and_then(|values| values.first())
+            .map(String::as_str)
+    }
+
+    /// 指定したキーの全ての値を指定順に取得（なければ空）
+    pub fn get_all(&self, key: &str) -> &[String] {
+        self.params.get(key).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// 指定したキーが含まれるか
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.params.contains_key(key)
+    }
+
+    /// 値を追加（同じキーの既存の値は残す）
+    pub fn append(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.params.entry(key.into()).or_default().push(value.into());
+    }
+
+    /// 全てのキーと値の一覧
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.params
+            .iter()
+            .map(|(key, values)| (key.as_str(), values.as_slice()))
+    }
+
+    /// キーの個数
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// クエリパラメータが1つもないか
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+}
+
+/// リクエストターゲットをパスとクエリ文字列に分割する
+///
+/// 例: "/search?q=rust#top" -> ("/search", Some("q=rust"))
+pub fn split_target(target: &str) -> (&str, Option<&str>) {
+    // フラグメントはサーバーに送られないはずだが、念のため除去
+    let target = target.split_once('#').map_or(target, |(before, _)| before);
+
+    match target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (target, None),
+    }
+}
+
+/// パーセントエンコーディングをデコードする
+///
+/// "%XX"（XXは16進数2桁）を1バイトに戻し、結果をUTF-8として解釈する。
+/// '%' の後が16進数2桁でない場合や、UTF-8として不正な場合はNone。
+pub fn percent_decode(input: &str) -> Option<String> {
+    // エンコードされていなければそのまま返す
+    if !input.contains('%') {
+        return Some(input.to_string());
+    }
+
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            let hex = std::str::from_utf8(hex).ok()?;
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+/// クエリのキーまたは値をデコードする（'+' は空白）
+fn decode_query_component(component: &str) -> Option<String> {
+    percent_decode(&component.replace('+', " "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("John%20Doe").as_deref(), Some("John Doe"));
+        assert_eq!(percent_decode("%E3%81%82").as_deref(), Some("あ"));
+        assert_eq!(percent_decode("plain").as_deref(), Some("plain"));
+        assert_eq!(percent_decode("bad%2"), None);
+        assert_eq!(percent_decode("bad%zz"), None);
+        assert_eq!(percent_decode("%FF"), None); // 不正なUTF-8
+    }
+
+    #[test]
+    fn test_split_target() {
+        assert_eq!(split_target("/api/users?page=2"), ("/api/users", Some("page=2")));
+        assert_eq!(split_target("/api/users#top"), ("/api/users", None));
+        assert_eq!(split_target("/"), ("/", None));
+    }
+
+    #[test]
+    fn test_query_map_repeated_keys() {
+        let query = QueryMap::parse("tag=rust&tag=http&q=hello+world%21&flag").unwrap();
+        assert_eq!(query.get_all("tag"), ["rust", "http"]);
+        assert_eq!(query.get("q"), Some("hello world!"));
+        assert_eq!(query.get("flag"), Some(""));
+        assert_eq!(query.get("missing"), None);
+        assert!(QueryMap::parse("a=%zz").is_none());
+    }
+}